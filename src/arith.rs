@@ -0,0 +1,222 @@
+//! Pixel-wise arithmetic between two images.
+//!
+//! `combine` applies an operation to each pair of samples from two
+//! images of the same width, height, channel count, and bit depth,
+//! similar to netpbm's `pamarith`. Results saturate at 0 and the
+//! bit depth.
+//!
+//! The result keeps the format, encoding, and type info of the left
+//! image, except for `Operation::Compare`.
+//!
+
+use crate::image::Image;
+use crate::{BitDepth, Info, NetpbmError, NetpbmFormat};
+use alloc::format;
+
+/// Operation applied to each pair of samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Operation {
+    /// Add samples.
+    Add,
+
+    /// Subtract right samples from left samples.
+    Subtract,
+
+    /// Absolute difference of samples.
+    Difference,
+
+    /// Multiply samples as fractions of the bit depth.
+    Multiply,
+
+    /// Divide left samples by right samples as fractions of the bit
+    /// depth. Dividing a nonzero sample by 0 gives the bit depth.
+    Divide,
+
+    /// Smaller of the samples.
+    Min,
+
+    /// Larger of the samples.
+    Max,
+
+    /// Mean of the samples, rounded up.
+    Mean,
+
+    /// Bitwise AND of samples.
+    And,
+
+    /// Bitwise OR of samples.
+    Or,
+
+    /// Bitwise XOR of samples.
+    Xor,
+
+    /// Compare samples, giving 0 if the left sample is smaller, 1 if
+    /// the samples are equal, and 2 if the left sample is larger.
+    ///
+    /// The result has a bit depth of 2, and PBM images become PGM
+    /// images.
+    ///
+    Compare,
+}
+
+impl Operation {
+    /// Determine whether the operation works on sample bits.
+    fn is_bitwise(&self) -> bool {
+        matches!(self, Operation::And | Operation::Or | Operation::Xor)
+    }
+}
+
+/// Combine two images sample by sample.
+///
+/// Bitwise operations need a bit depth one less than a power of two,
+/// such as bitmaps, so that results are within the bit depth.
+///
+pub fn combine(left: &Image, right: &Image, operation: Operation) -> Result<Image, NetpbmError> {
+    check_compatible(left.info(), right.info())?;
+
+    let bit_depth = left.bit_depth() as u32;
+    if operation.is_bitwise() && !(bit_depth + 1).is_power_of_two() {
+        return Err(NetpbmError::InvalidArgument {
+            info: format!(
+                "Bitwise operations need a bit depth of 2^n - 1, found {}",
+                bit_depth
+            ),
+        });
+    }
+
+    let samples = left
+        .samples()
+        .iter()
+        .zip(right.samples())
+        .map(|(&a, &b)| apply(operation, a as u32, b as u32, bit_depth) as u16)
+        .collect();
+
+    let mut info = left.info().clone();
+    if operation == Operation::Compare {
+        info.bit_depth = BitDepth::new(2).expect("Comparison bit depth");
+        info.format = match info.format {
+            NetpbmFormat::PBMRaw => NetpbmFormat::PGMRaw,
+            NetpbmFormat::PBMPlain => NetpbmFormat::PGMPlain,
+            format => format,
+        };
+    }
+    Ok(Image::from_parts(info, left.type_info().clone(), samples))
+}
+
+/// Check that two images have the same shape and bit depth.
+pub(crate) fn check_compatible(expected: &Info, found: &Info) -> Result<(), NetpbmError> {
+    check_shape(expected, found)?;
+    if expected.bit_depth != found.bit_depth {
+        return Err(NetpbmError::BitDepthMismatch {
+            expected: expected.bit_depth,
+            found: found.bit_depth,
+        });
+    }
+    Ok(())
+}
+
+/// Check that two images have the same width, height, and channel
+/// count.
+pub(crate) fn check_shape(expected: &Info, found: &Info) -> Result<(), NetpbmError> {
+    if (expected.width, expected.height) != (found.width, found.height) {
+        return Err(NetpbmError::DimensionMismatch {
+            expected: (expected.width, expected.height),
+            found: (found.width, found.height),
+        });
+    }
+    if expected.channels != found.channels {
+        return Err(NetpbmError::ChannelMismatch {
+            expected: expected.channels,
+            found: found.channels,
+        });
+    }
+    Ok(())
+}
+
+/// Apply an operation to two samples.
+fn apply(operation: Operation, a: u32, b: u32, bit_depth: u32) -> u32 {
+    match operation {
+        Operation::Add => (a + b).min(bit_depth),
+        Operation::Subtract => a.saturating_sub(b),
+        Operation::Difference => a.abs_diff(b),
+        Operation::Multiply => (a * b + bit_depth / 2) / bit_depth,
+        Operation::Divide => match (a, b) {
+            (0, 0) => 0,
+            (_, 0) => bit_depth,
+            _ => ((a * bit_depth + b / 2) / b).min(bit_depth),
+        },
+        Operation::Min => a.min(b),
+        Operation::Max => a.max(b),
+        Operation::Mean => (a + b).div_ceil(2),
+        Operation::And => a & b,
+        Operation::Or => a | b,
+        Operation::Xor => a ^ b,
+        Operation::Compare => (a.cmp(&b) as i32 + 1) as u32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::pgm_row;
+    use crate::{EncodingType, TypeInfo};
+    use alloc::vec;
+
+    #[test]
+    fn test_arithmetic() {
+        let a = pgm_row(100, vec![0, 20, 50, 80, 100]);
+        let b = pgm_row(100, vec![10, 20, 80, 30, 0]);
+        let run = |operation| combine(&a, &b, operation).expect("combine").into_samples();
+
+        assert_eq!(run(Operation::Add), [10, 40, 100, 100, 100]);
+        assert_eq!(run(Operation::Subtract), [0, 0, 0, 50, 100]);
+        assert_eq!(run(Operation::Difference), [10, 0, 30, 50, 100]);
+        assert_eq!(run(Operation::Multiply), [0, 4, 40, 24, 0]);
+        assert_eq!(run(Operation::Divide), [0, 100, 63, 100, 100]);
+        assert_eq!(run(Operation::Min), [0, 20, 50, 30, 0]);
+        assert_eq!(run(Operation::Max), [10, 20, 80, 80, 100]);
+        assert_eq!(run(Operation::Mean), [5, 20, 65, 55, 50]);
+
+        let compared = combine(&a, &b, Operation::Compare).expect("compare");
+        assert_eq!(compared.samples(), [0, 1, 0, 2, 2]);
+        assert_eq!(compared.bit_depth(), 2);
+    }
+
+    #[test]
+    fn test_bitwise() {
+        let info = Info::new_pbm(EncodingType::Raw, 4, 1).expect("pbm");
+        let a = Image::new(info.clone(), TypeInfo::Empty, vec![0, 0, 1, 1]).expect("pbm");
+        let b = Image::new(info, TypeInfo::Empty, vec![0, 1, 0, 1]).expect("pbm");
+        let run = |operation| combine(&a, &b, operation).expect("combine").into_samples();
+
+        assert_eq!(run(Operation::And), [0, 0, 0, 1]);
+        assert_eq!(run(Operation::Or), [0, 1, 1, 1]);
+        assert_eq!(run(Operation::Xor), [0, 1, 1, 0]);
+
+        let compared = combine(&a, &b, Operation::Compare).expect("compare");
+        assert_eq!(compared.info().format, NetpbmFormat::PGMRaw);
+
+        let a = pgm_row(100, vec![1]);
+        assert!(combine(&a, &a, Operation::Xor).is_err());
+    }
+
+    #[test]
+    fn test_mismatch() {
+        let a = pgm_row(255, vec![1, 2]);
+        assert!(matches!(
+            combine(&a, &pgm_row(255, vec![1]), Operation::Add),
+            Err(NetpbmError::DimensionMismatch { .. })
+        ));
+        assert!(matches!(
+            combine(&a, &pgm_row(100, vec![1, 2]), Operation::Add),
+            Err(NetpbmError::BitDepthMismatch { .. })
+        ));
+
+        let info = Info::new_pam(2, 1, 255, 2).expect("pam");
+        let b = Image::new(info, TypeInfo::Empty, vec![0; 4]).expect("pam");
+        assert!(matches!(
+            combine(&a, &b, Operation::Add),
+            Err(NetpbmError::ChannelMismatch { .. })
+        ));
+    }
+}
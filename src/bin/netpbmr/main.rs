@@ -0,0 +1,452 @@
+//! The `netpbmr` command-line tool.
+//!
+//! Each subcommand reads a netpbm file from the named path, or from
+//! standard input, and writes images to standard output, so that
+//! commands compose in shell pipelines like the netpbm tools. Files
+//! may hold a sequence of images.
+//!
+
+use netpbmr::convert;
+use netpbmr::formats::{NetpbmFormat, TypeInfo};
+use netpbmr::image::{Image, Images};
+use netpbmr::{EncodingType, NetpbmError};
+use std::env;
+use std::fs;
+use std::io::{self, BufWriter, Read, Write};
+use std::process::ExitCode;
+use std::str::FromStr;
+
+mod ops;
+
+const USAGE: &str = "\
+usage: netpbmr <command> [options] [file]
+
+Reads from standard input when no file is given, or the file is `-`.
+
+commands:
+    info                    Print the header of each image.
+    validate                Check that every image decodes.
+    convert [format] [--plain | --raw] [--maxval n]
+                            Convert images to another format or bit depth.
+                            Formats are pbm, pgm, ppm, pam, or P1 to P7.
+    scale [factor] [--width n] [--height n] [--fit] [--filter name]
+                            Resize images, like pamscale. Filters are nearest,
+                            bilinear, bicubic, lanczos3, and box.
+    flip --lr | --tb | --cw | --ccw | --r90 | --r180 | --r270 | --transpose
+                            Flip or rotate images, like pamflip. Rotations
+                            in degrees are counterclockwise.
+    cut <left> <top> <width> <height> [--pad]
+                            Cut out a rectangle, like pamcut. Offsets may be
+                            negative. With --pad, the part outside the image
+                            is black.
+    invert                  Invert images, like pnminvert.
+    gamma <value>           Apply gamma correction, like pnmgamma.
+    stack [--tupletype type] <file>...
+                            Stack the channels of images from each file into
+                            PAM images, like pamstack.
+
+exit status:
+    0    success
+    1    an image is invalid or cannot be converted
+    2    usage or I/O error
+";
+
+/// Reasons a command fails.
+enum Failure {
+    /// The command line is invalid.
+    Usage(String),
+
+    /// Input or output failed.
+    Io(String),
+
+    /// An image is invalid or an operation failed on it.
+    Image(String),
+}
+
+impl From<io::Error> for Failure {
+    fn from(err: io::Error) -> Self {
+        Failure::Io(err.to_string())
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Failure::Usage(info)) => {
+            eprintln!("netpbmr: {}\n\n{}", info, USAGE);
+            ExitCode::from(2)
+        }
+        Err(Failure::Io(info)) => {
+            eprintln!("netpbmr: {}", info);
+            ExitCode::from(2)
+        }
+        Err(Failure::Image(info)) => {
+            eprintln!("netpbmr: {}", info);
+            ExitCode::from(1)
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<(), Failure> {
+    let (command, args) = args
+        .split_first()
+        .ok_or_else(|| Failure::Usage("missing command".to_string()))?;
+    match command.as_str() {
+        "info" => info(args),
+        "validate" => validate(args),
+        "convert" => convert(args),
+        "scale" => ops::scale(args),
+        "flip" => ops::flip(args),
+        "cut" => ops::cut(args),
+        "invert" => ops::invert(args),
+        "gamma" => ops::gamma(args),
+        "stack" => ops::stack(args),
+        "help" | "-h" | "--help" => {
+            print!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(Failure::Usage(format!("unknown command {:?}", command))),
+    }
+}
+
+/// Print the header of each image.
+fn info(args: &[String]) -> Result<(), Failure> {
+    let mut options = Options::new(args);
+    options.finish()?;
+    let (name, buf) = read_input(options.file.as_deref())?;
+
+    let mut out = io::stdout().lock();
+    for (index, image) in Images::new(&buf).enumerate() {
+        let image = image.map_err(|err| image_failure(&name, index, err))?;
+        let info = image.info();
+        writeln!(
+            out,
+            "{}: image {}: {} {:?}, {} by {}, maxval {}, {} channel{}",
+            name,
+            index,
+            info.format.magic(),
+            info.format,
+            info.width,
+            info.height,
+            info.bit_depth,
+            info.channels,
+            if info.channels.value() == 1 { "" } else { "s" },
+        )?;
+        if let TypeInfo::Info(types) = image.type_info() {
+            for tuple_type in types {
+                writeln!(out, "    tuple type: {}", tuple_type)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check that every image decodes.
+fn validate(args: &[String]) -> Result<(), Failure> {
+    let mut options = Options::new(args);
+    options.finish()?;
+    let (name, buf) = read_input(options.file.as_deref())?;
+
+    let mut count = 0;
+    for (index, image) in Images::new(&buf).enumerate() {
+        image.map_err(|err| image_failure(&name, index, err))?;
+        count += 1;
+    }
+    println!(
+        "{}: {} valid image{}",
+        name,
+        count,
+        if count == 1 { "" } else { "s" }
+    );
+    Ok(())
+}
+
+/// Convert images to another format or bit depth.
+fn convert(args: &[String]) -> Result<(), Failure> {
+    let mut options = Options::new(args);
+    let encoding = match (options.flag("--plain"), options.flag("--raw")) {
+        (true, true) => return Err(Failure::Usage("--plain conflicts with --raw".to_string())),
+        (true, false) => Some(EncodingType::Plain),
+        (false, true) => Some(EncodingType::Raw),
+        (false, false) => None,
+    };
+    let maxval = options.number("--maxval")?;
+    let target = options.format()?;
+    options.finish()?;
+
+    map_images(options.file.as_deref(), |image| {
+        let (family, magic_encoding) = target.unwrap_or((Family::of(&image.info().format), None));
+        let encoding = encoding.or(magic_encoding).unwrap_or(image.info().encoding);
+        let image = convert::convert(&image, family.format(encoding))?;
+        match maxval {
+            Some(maxval) => convert::rescale(&image, maxval),
+            None => Ok(image),
+        }
+    })
+}
+
+/// Apply an operation to each image of the input, and write the
+/// results to standard output.
+fn map_images<F>(file: Option<&str>, mut f: F) -> Result<(), Failure>
+where
+    F: FnMut(Image) -> Result<Image, NetpbmError>,
+{
+    let (name, buf) = read_input(file)?;
+    let mut out = BufWriter::new(io::stdout().lock());
+    for (index, image) in Images::new(&buf).enumerate() {
+        let image = image
+            .and_then(&mut f)
+            .map_err(|err| image_failure(&name, index, err))?;
+        write_image(&mut out, &image)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Format families, independent of the encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Family {
+    Pbm,
+    Pgm,
+    Ppm,
+    Pam,
+}
+
+impl Family {
+    fn of(format: &NetpbmFormat) -> Self {
+        match format {
+            NetpbmFormat::PBMRaw | NetpbmFormat::PBMPlain => Family::Pbm,
+            NetpbmFormat::PGMRaw | NetpbmFormat::PGMPlain => Family::Pgm,
+            NetpbmFormat::PPMRaw | NetpbmFormat::PPMPlain => Family::Ppm,
+            _ => Family::Pam,
+        }
+    }
+
+    fn format(&self, encoding: EncodingType) -> NetpbmFormat {
+        let plain = encoding == EncodingType::Plain;
+        match self {
+            Family::Pbm if plain => NetpbmFormat::PBMPlain,
+            Family::Pbm => NetpbmFormat::PBMRaw,
+            Family::Pgm if plain => NetpbmFormat::PGMPlain,
+            Family::Pgm => NetpbmFormat::PGMRaw,
+            Family::Ppm if plain => NetpbmFormat::PPMPlain,
+            Family::Ppm => NetpbmFormat::PPMRaw,
+            Family::Pam => NetpbmFormat::PAM,
+        }
+    }
+}
+
+/// Parse a format name or magic number.
+fn parse_format(name: &str) -> Option<(Family, Option<EncodingType>)> {
+    let format = match name.to_ascii_lowercase().as_str() {
+        "pbm" => (Family::Pbm, None),
+        "pgm" => (Family::Pgm, None),
+        "ppm" => (Family::Ppm, None),
+        "pam" => (Family::Pam, None),
+        "p1" => (Family::Pbm, Some(EncodingType::Plain)),
+        "p2" => (Family::Pgm, Some(EncodingType::Plain)),
+        "p3" => (Family::Ppm, Some(EncodingType::Plain)),
+        "p4" => (Family::Pbm, Some(EncodingType::Raw)),
+        "p5" => (Family::Pgm, Some(EncodingType::Raw)),
+        "p6" => (Family::Ppm, Some(EncodingType::Raw)),
+        "p7" => (Family::Pam, Some(EncodingType::Raw)),
+        _ => return None,
+    };
+    Some(format)
+}
+
+/// Command-line options of a subcommand.
+///
+/// Options are taken out as the subcommand reads them. The
+/// positional argument left over is the input file.
+///
+struct Options {
+    args: Vec<String>,
+    file: Option<String>,
+}
+
+impl Options {
+    fn new(args: &[String]) -> Self {
+        Options {
+            args: args.to_vec(),
+            file: None,
+        }
+    }
+
+    /// Take a flag without a value.
+    fn flag(&mut self, name: &str) -> bool {
+        let found = self.args.iter().position(|arg| arg == name);
+        found.map(|i| self.args.remove(i)).is_some()
+    }
+
+    /// Take the value of an option such as `--maxval 255`.
+    fn value(&mut self, name: &str) -> Result<Option<String>, Failure> {
+        let Some(i) = self.args.iter().position(|arg| arg == name) else {
+            return Ok(None);
+        };
+        if i + 1 >= self.args.len() {
+            return Err(Failure::Usage(format!("{} needs a value", name)));
+        }
+        self.args.remove(i);
+        Ok(Some(self.args.remove(i)))
+    }
+
+    /// Take the value of an option and parse it.
+    fn number<T: FromStr>(&mut self, name: &str) -> Result<Option<T>, Failure> {
+        self.value(name)?
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| Failure::Usage(format!("invalid {} {:?}", name, value)))
+            })
+            .transpose()
+    }
+
+    /// Take the first positional argument and parse it.
+    fn argument<T: FromStr>(&mut self, what: &str) -> Result<T, Failure> {
+        let i = self
+            .args
+            .iter()
+            .position(|arg| !is_option(arg))
+            .ok_or_else(|| Failure::Usage(format!("missing {}", what)))?;
+        let arg = self.args.remove(i);
+        arg.parse()
+            .map_err(|_| Failure::Usage(format!("invalid {} {:?}", what, arg)))
+    }
+
+    /// Take the first positional argument if it parses.
+    fn optional_argument<T: FromStr>(&mut self) -> Option<T> {
+        let i = self.args.iter().position(|arg| !is_option(arg))?;
+        let value = self.args[i].parse().ok()?;
+        self.args.remove(i);
+        Some(value)
+    }
+
+    /// Take the first positional argument if it names a format.
+    fn format(&mut self) -> Result<Option<(Family, Option<EncodingType>)>, Failure> {
+        let Some(i) = self.args.iter().position(|arg| !is_option(arg)) else {
+            return Ok(None);
+        };
+        match parse_format(&self.args[i]) {
+            Some(format) => {
+                self.args.remove(i);
+                Ok(Some(format))
+            }
+            // A single positional argument is the input file.
+            None if self.args.iter().filter(|arg| !is_option(arg)).count() == 1 => Ok(None),
+            None => Err(Failure::Usage(format!("unknown format {:?}", self.args[i]))),
+        }
+    }
+
+    /// Take every input file, checking that no options are left.
+    fn files(&mut self) -> Result<Vec<String>, Failure> {
+        if let Some(option) = self.args.iter().find(|arg| is_option(arg)) {
+            return Err(Failure::Usage(format!("unknown option {:?}", option)));
+        }
+        Ok(std::mem::take(&mut self.args))
+    }
+
+    /// Take the input file and check that no arguments are left.
+    fn finish(&mut self) -> Result<(), Failure> {
+        let mut files = self.files()?;
+        if files.len() > 1 {
+            return Err(Failure::Usage(format!(
+                "unexpected argument {:?}",
+                files[0]
+            )));
+        }
+        self.file = files.pop();
+        Ok(())
+    }
+}
+
+/// Determine whether an argument is an option rather than a value.
+///
+/// Negative numbers, such as the offsets of `cut`, are values.
+///
+fn is_option(arg: &str) -> bool {
+    arg.starts_with('-') && arg != "-" && arg.parse::<f64>().is_err()
+}
+
+/// Read the whole input file, or standard input.
+fn read_input(file: Option<&str>) -> Result<(String, Vec<u8>), Failure> {
+    match file {
+        None | Some("-") => {
+            let mut buf = Vec::new();
+            io::stdin().lock().read_to_end(&mut buf)?;
+            Ok(("stdin".to_string(), buf))
+        }
+        Some(path) => {
+            let buf = fs::read(path).map_err(|err| Failure::Io(format!("{}: {}", path, err)))?;
+            Ok((path.to_string(), buf))
+        }
+    }
+}
+
+/// Write an image, reporting encoder errors as I/O failures.
+fn write_image<W: Write>(out: &mut W, image: &Image) -> Result<(), Failure> {
+    image.write(out).map_err(|err| match err {
+        NetpbmError::IOOperationFailed { info } => Failure::Io(info),
+        err => Failure::Image(err.to_string()),
+    })
+}
+
+/// Describe an error in one image of the input.
+fn image_failure(name: &str, index: usize, err: NetpbmError) -> Failure {
+    Failure::Image(format!("{}: image {}: {}", name, index, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_options() {
+        let mut options = Options::new(&args(&["--raw", "p2", "--maxval", "15", "in.pgm"]));
+        assert!(options.flag("--raw"));
+        assert!(!options.flag("--plain"));
+        assert_eq!(options.value("--maxval").ok(), Some(Some("15".to_string())));
+        assert!(matches!(
+            options.format(),
+            Ok(Some((Family::Pgm, Some(EncodingType::Plain))))
+        ));
+        assert!(options.finish().is_ok());
+        assert_eq!(options.file.as_deref(), Some("in.pgm"));
+
+        // A lone positional argument is the input file.
+        let mut options = Options::new(&args(&["in.pgm"]));
+        assert!(matches!(options.format(), Ok(None)));
+        assert!(options.finish().is_ok());
+        assert_eq!(options.file.as_deref(), Some("in.pgm"));
+
+        let mut options = Options::new(&args(&["--bogus"]));
+        assert!(matches!(options.finish(), Err(Failure::Usage(_))));
+        let mut options = Options::new(&args(&["--maxval"]));
+        assert!(matches!(options.value("--maxval"), Err(Failure::Usage(_))));
+
+        let mut options = Options::new(&args(&["-3", "-", "-0.5"]));
+        assert_eq!(options.argument::<i64>("left").ok(), Some(-3));
+        assert_eq!(options.optional_argument::<f64>(), None);
+        assert!(options.finish().is_err());
+    }
+
+    #[test]
+    fn test_formats() {
+        assert_eq!(
+            Family::Ppm.format(EncodingType::Plain),
+            NetpbmFormat::PPMPlain
+        );
+        assert_eq!(Family::Pam.format(EncodingType::Plain), NetpbmFormat::PAM);
+        assert_eq!(Family::of(&NetpbmFormat::PBMRaw), Family::Pbm);
+        assert_eq!(
+            parse_format("P4"),
+            Some((Family::Pbm, Some(EncodingType::Raw)))
+        );
+        assert_eq!(parse_format("gif"), None);
+    }
+}
@@ -0,0 +1,221 @@
+//! Image-processing subcommands, mirroring the netpbm tools.
+
+use super::{image_failure, map_images, read_input, write_image, Failure, Options};
+use netpbmr::formats::{NetpbmFormat, TypeInfo};
+use netpbmr::image::Images;
+use netpbmr::layout::{self, CropMode, Rect};
+use netpbmr::scale::{self, Filter};
+use netpbmr::tone;
+use netpbmr::transform::{self, Transform};
+use std::io::{self, BufWriter, Write};
+
+/// Resize images, like `pamscale`.
+pub fn scale(args: &[String]) -> Result<(), Failure> {
+    let mut options = Options::new(args);
+    let filter = match options.value("--filter")? {
+        Some(name) => parse_filter(&name)?,
+        None => Filter::Box,
+    };
+    let fit = options.flag("--fit");
+    let width: Option<u32> = options.number("--width")?;
+    let height: Option<u32> = options.number("--height")?;
+    let factor: Option<f64> = options.optional_argument();
+    options.finish()?;
+
+    match (factor, width, height) {
+        (Some(_), Some(_), _) | (Some(_), _, Some(_)) => {
+            return Err(Failure::Usage(
+                "a scale factor conflicts with --width and --height".to_string(),
+            ))
+        }
+        (None, None, None) => {
+            return Err(Failure::Usage(
+                "missing scale factor, --width, or --height".to_string(),
+            ))
+        }
+        _ => {}
+    }
+    if fit && (width.is_none() || height.is_none()) {
+        return Err(Failure::Usage(
+            "--fit needs --width and --height".to_string(),
+        ));
+    }
+
+    map_images(options.file.as_deref(), |image| {
+        // A missing dimension keeps the aspect ratio.
+        let keep = |dim: u32, target: u32, other: u32| {
+            ((dim as f64 * target as f64 / other as f64).round() as u32).max(1)
+        };
+        match (factor, width, height) {
+            (Some(factor), _, _) => scale::scale(&image, factor, filter),
+            (None, Some(width), Some(height)) if fit => {
+                scale::resize_to_fit(&image, width, height, filter)
+            }
+            (None, Some(width), Some(height)) => scale::resize(&image, width, height, filter),
+            (None, Some(width), None) => {
+                let height = keep(image.height(), width, image.width());
+                scale::resize(&image, width, height, filter)
+            }
+            (None, None, Some(height)) => {
+                let width = keep(image.width(), height, image.height());
+                scale::resize(&image, width, height, filter)
+            }
+            (None, None, None) => unreachable!(),
+        }
+    })
+}
+
+/// Flip or rotate images, like `pamflip`.
+pub fn flip(args: &[String]) -> Result<(), Failure> {
+    const FLAGS: [(&str, Transform); 8] = [
+        ("--lr", Transform::FlipHorizontal),
+        ("--tb", Transform::FlipVertical),
+        ("--cw", Transform::Rotate90),
+        ("--r270", Transform::Rotate90),
+        ("--ccw", Transform::Rotate270),
+        ("--r90", Transform::Rotate270),
+        ("--r180", Transform::Rotate180),
+        ("--transpose", Transform::Transpose),
+    ];
+
+    let mut options = Options::new(args);
+    let transforms: Vec<Transform> = FLAGS
+        .iter()
+        .filter(|(flag, _)| options.flag(flag))
+        .map(|&(_, transform)| transform)
+        .collect();
+    options.finish()?;
+
+    let [transform] = transforms[..] else {
+        return Err(Failure::Usage(
+            "flip needs exactly one transform".to_string(),
+        ));
+    };
+    map_images(options.file.as_deref(), |image| {
+        Ok(transform::transform(&image, transform))
+    })
+}
+
+/// Cut out a rectangle, like `pamcut`.
+pub fn cut(args: &[String]) -> Result<(), Failure> {
+    let mut options = Options::new(args);
+    let pad = options.flag("--pad");
+    let rect = Rect::new(
+        options.argument("left")?,
+        options.argument("top")?,
+        options.argument("width")?,
+        options.argument("height")?,
+    );
+    options.finish()?;
+
+    map_images(options.file.as_deref(), |image| {
+        let mode = if !pad {
+            CropMode::Strict
+        } else if matches!(
+            image.info().format,
+            NetpbmFormat::PBMRaw | NetpbmFormat::PBMPlain
+        ) {
+            // PBM samples are 1 for black.
+            CropMode::Fill(vec![1])
+        } else {
+            CropMode::Fill(vec![0; image.channels() as usize])
+        };
+        layout::crop(&image, rect, &mode)
+    })
+}
+
+/// Invert images, like `pnminvert`.
+pub fn invert(args: &[String]) -> Result<(), Failure> {
+    let mut options = Options::new(args);
+    options.finish()?;
+    map_images(options.file.as_deref(), |image| Ok(tone::invert(&image)))
+}
+
+/// Apply gamma correction, like `pnmgamma`.
+pub fn gamma(args: &[String]) -> Result<(), Failure> {
+    let mut options = Options::new(args);
+    let value: f64 = options.argument("gamma")?;
+    options.finish()?;
+    if !(value.is_finite() && value > 0.0) {
+        return Err(Failure::Usage(format!(
+            "gamma {} should be positive",
+            value
+        )));
+    }
+    map_images(options.file.as_deref(), |image| tone::gamma(&image, value))
+}
+
+/// Stack the channels of images from each file, like `pamstack`.
+///
+/// The nth image of the output stacks the nth image of every file.
+///
+pub fn stack(args: &[String]) -> Result<(), Failure> {
+    let mut options = Options::new(args);
+    let type_info = match options.value("--tupletype")? {
+        Some(tuple_type) => TypeInfo::Info(vec![tuple_type]),
+        None => TypeInfo::Empty,
+    };
+    let files = options.files()?;
+    if files.is_empty() {
+        return Err(Failure::Usage("missing files to stack".to_string()));
+    }
+    if files.iter().filter(|file| file.as_str() == "-").count() > 1 {
+        return Err(Failure::Usage(
+            "standard input can be read once".to_string(),
+        ));
+    }
+
+    let inputs = files
+        .iter()
+        .map(|file| read_input(Some(file)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut streams: Vec<Images> = inputs.iter().map(|(_, buf)| Images::new(buf)).collect();
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    for index in 0.. {
+        let mut images = Vec::with_capacity(streams.len());
+        for ((name, _), stream) in inputs.iter().zip(&mut streams) {
+            if let Some(image) = stream.next() {
+                images.push(image.map_err(|err| image_failure(name, index, err))?);
+            }
+        }
+        if images.is_empty() {
+            break;
+        }
+        if images.len() < streams.len() {
+            return Err(Failure::Image(format!(
+                "image {}: some files have no more images",
+                index
+            )));
+        }
+
+        let stacked = layout::stack(&images, type_info.clone())
+            .map_err(|err| Failure::Image(format!("image {}: {}", index, err)))?;
+        write_image(&mut out, &stacked)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Parse the name of a resampling filter.
+fn parse_filter(name: &str) -> Result<Filter, Failure> {
+    match name.to_ascii_lowercase().as_str() {
+        "nearest" => Ok(Filter::Nearest),
+        "bilinear" => Ok(Filter::Bilinear),
+        "bicubic" => Ok(Filter::Bicubic),
+        "lanczos3" => Ok(Filter::Lanczos3),
+        "box" => Ok(Filter::Box),
+        _ => Err(Failure::Usage(format!("unknown filter {:?}", name))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_filter() {
+        assert_eq!(parse_filter("Lanczos3").ok(), Some(Filter::Lanczos3));
+        assert!(parse_filter("sinc").is_err());
+    }
+}
@@ -0,0 +1,227 @@
+//! Byte-packed bitmaps.
+//!
+//! A `Bitmap` holds a PBM image in the `raw` raster layout. Each
+//! row is `ceil(width / 8)` bytes long, with the leftmost pixel in
+//! the most significant bit. Padding bits at the end of each row
+//! are always zero. Following the PBM convention, a set bit is
+//! black.
+//!
+//! Bitmaps are read and written without unpacking `raw` rasters,
+//! and take a sixteenth of the memory of the equivalent `Image`.
+//! Bitmap operations work on whole bytes where they can.
+//!
+
+use crate::image::Image;
+use crate::io;
+use crate::pbm;
+use crate::{EncodingType, ImageDim, Info, NetpbmError, NetpbmFormat, TypeInfo};
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// A byte-packed PBM image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bitmap {
+    width: ImageDim,
+    height: ImageDim,
+    data: Vec<u8>,
+}
+
+impl Bitmap {
+    /// Create a new white bitmap.
+    pub fn new(width: u32, height: u32) -> Result<Self, NetpbmError> {
+        let width = ImageDim::new(width)?;
+        let height = ImageDim::new(height)?;
+        let row_len = (width.value() as usize).div_ceil(8);
+        Ok(Bitmap {
+            width,
+            height,
+            data: vec![0; row_len * height.value() as usize],
+        })
+    }
+
+    /// Create a bitmap from byte-packed rows.
+    ///
+    /// Padding bits are cleared.
+    ///
+    pub fn from_packed(width: u32, height: u32, data: Vec<u8>) -> Result<Self, NetpbmError> {
+        let mut bitmap = Bitmap {
+            width: ImageDim::new(width)?,
+            height: ImageDim::new(height)?,
+            data,
+        };
+        if bitmap.data.len() != bitmap.row_len() * height as usize {
+            return Err(NetpbmError::MalformedInitArray {
+                data_size: bitmap.data.len(),
+                width: bitmap.width,
+                height: bitmap.height,
+            });
+        }
+        bitmap.clear_padding();
+
+        Ok(bitmap)
+    }
+
+    /// Create a bitmap from a PBM image.
+    pub fn from_image(image: &Image) -> Result<Self, NetpbmError> {
+        if !matches!(
+            image.info().format,
+            NetpbmFormat::PBMRaw | NetpbmFormat::PBMPlain
+        ) {
+            return Err(NetpbmError::InvalidArgument {
+                info: format!(
+                    "Expected a PBM image, found {}",
+                    image.info().format.magic()
+                ),
+            });
+        }
+
+        let mut bitmap = Self::new(image.width(), image.height())?;
+        let row_len = bitmap.row_len();
+        for (y, row) in bitmap.data.chunks_exact_mut(row_len).enumerate() {
+            for (x, &sample) in image.row(y as u32).iter().enumerate() {
+                row[x / 8] |= (sample as u8) << (7 - x % 8);
+            }
+        }
+
+        Ok(bitmap)
+    }
+
+    /// Decode the first PBM image from the given reader.
+    #[cfg(feature = "std")]
+    pub fn read<R: io::Read>(reader: R) -> Result<Self, NetpbmError> {
+        let mut data = Vec::new();
+        let info = pbm::Decoder::new(reader).read_packed(&mut data)?;
+        Ok(Bitmap {
+            width: info.width,
+            height: info.height,
+            data,
+        })
+    }
+
+    /// Encode the bitmap as a PBM image.
+    pub fn write<W: io::Write>(
+        &self,
+        writer: W,
+        encoding: EncodingType,
+    ) -> Result<(), NetpbmError> {
+        pbm::Encoder::new(writer).write_packed(encoding, self.width(), self.height(), &self.data)
+    }
+
+    /// Convert the bitmap to a PBM image with the given encoding.
+    pub fn to_image(&self, encoding: EncodingType) -> Image {
+        let info = Info::new_pbm(encoding, self.width(), self.height()).expect("Bitmap dims");
+        let samples = (0..self.height())
+            .flat_map(|y| (0..self.width()).map(move |x| self.get(x, y) as u16))
+            .collect();
+        Image::new(info, TypeInfo::Empty, samples).expect("Bitmap samples")
+    }
+
+    /// Get the bitmap width.
+    pub fn width(&self) -> u32 {
+        self.width.value()
+    }
+
+    /// Get the bitmap height.
+    pub fn height(&self) -> u32 {
+        self.height.value()
+    }
+
+    /// Get the number of bytes in a row.
+    pub fn row_len(&self) -> usize {
+        (self.width() as usize).div_ceil(8)
+    }
+
+    /// Get the byte-packed rows.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Consume the bitmap and return its byte-packed rows.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Get the bytes of row `y`.
+    pub fn row(&self, y: u32) -> &[u8] {
+        let row_len = self.row_len();
+        &self.data[y as usize * row_len..(y as usize + 1) * row_len]
+    }
+
+    /// Get the bytes of row `y` for modification.
+    ///
+    /// Padding bits must be left clear.
+    ///
+    pub fn row_mut(&mut self, y: u32) -> &mut [u8] {
+        let row_len = self.row_len();
+        &mut self.data[y as usize * row_len..(y as usize + 1) * row_len]
+    }
+
+    /// Determine whether the pixel at `(x, y)` is black.
+    pub fn get(&self, x: u32, y: u32) -> bool {
+        assert!(x < self.width() && y < self.height(), "pixel out of bounds");
+        let byte = self.data[y as usize * self.row_len() + x as usize / 8];
+        byte & (0x80 >> (x % 8)) != 0
+    }
+
+    /// Set the pixel at `(x, y)` to black or white.
+    pub fn set(&mut self, x: u32, y: u32, black: bool) {
+        assert!(x < self.width() && y < self.height(), "pixel out of bounds");
+        let index = y as usize * self.row_len() + x as usize / 8;
+        let mask = 0x80 >> (x % 8);
+        if black {
+            self.data[index] |= mask;
+        } else {
+            self.data[index] &= !mask;
+        }
+    }
+
+    /// Clear the padding bits at the end of each row.
+    pub(crate) fn clear_padding(&mut self) {
+        let row_len = self.row_len();
+        let mask = 0xFFu8 << ((8 - self.width() % 8) % 8);
+        for row in self.data.chunks_exact_mut(row_len) {
+            row[row_len - 1] &= mask;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bitmap_pixels() {
+        let mut bitmap = Bitmap::new(10, 2).expect("bitmap");
+        bitmap.set(0, 0, true);
+        bitmap.set(9, 1, true);
+        bitmap.set(8, 1, true);
+        bitmap.set(8, 1, false);
+        assert_eq!(bitmap.as_bytes(), [128, 0, 0, 64]);
+        assert!(bitmap.get(9, 1));
+        assert!(!bitmap.get(8, 1));
+
+        let bitmap = Bitmap::from_packed(10, 1, vec![255, 255]).expect("bitmap");
+        assert_eq!(bitmap.as_bytes(), [255, 192]);
+        assert!(Bitmap::from_packed(10, 2, vec![255, 255]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_bitmap_round_trip() {
+        let bitmap = Bitmap::from_packed(10, 2, vec![170, 192, 85, 0]).expect("bitmap");
+
+        let mut buf = Vec::new();
+        bitmap.write(&mut buf, EncodingType::Raw).expect("write");
+        assert_eq!(buf, b"P4\n10 2\n\xaa\xc0\x55\x00");
+        assert_eq!(Bitmap::read(&buf[..]), Ok(bitmap.clone()));
+
+        let mut buf = Vec::new();
+        bitmap.write(&mut buf, EncodingType::Plain).expect("write");
+        assert_eq!(Bitmap::read(&buf[..]), Ok(bitmap.clone()));
+
+        let image = bitmap.to_image(EncodingType::Raw);
+        assert_eq!(image.row(1), [0, 1, 0, 1, 0, 1, 0, 1, 0, 0]);
+        assert_eq!(Bitmap::from_image(&image), Ok(bitmap));
+    }
+}
@@ -0,0 +1,352 @@
+//! Connected-component labeling.
+//!
+//! `label` finds the connected regions of black pixels in a bitmap
+//! and numbers them from 1 in the order their first pixels appear,
+//! scanning rows from the top. White pixels have label 0.
+//!
+//! Bitmaps are scanned as runs of black pixels, skipping white
+//! bytes whole. Runs that touch runs in the previous row are merged
+//! with a union-find, so every pixel is visited once.
+//!
+//! Labels can be exported as a PGM or PAM image for inspection.
+//! Images have at most 65535 distinct labels, so larger labels wrap
+//! around, skipping 0.
+//!
+
+use crate::bitmap::Bitmap;
+use crate::image::Image;
+use crate::layout::Rect;
+use crate::{EncodingType, Info, NetpbmError, TypeInfo};
+use alloc::format;
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Pixel connectivity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Connectivity {
+    /// Pixels touch if they share an edge.
+    Four,
+
+    /// Pixels touch if they share an edge or a corner.
+    Eight,
+}
+
+/// Statistics of one connected component.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Component {
+    /// The label of the component.
+    pub label: u32,
+
+    /// The number of pixels in the component.
+    pub area: u64,
+
+    /// The smallest rectangle holding the component.
+    pub bbox: Rect,
+
+    /// The mean pixel coordinates of the component.
+    pub centroid: (f64, f64),
+}
+
+/// Labeled components of a bitmap.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Labels {
+    width: u32,
+    height: u32,
+    labels: Vec<u32>,
+    components: Vec<Component>,
+}
+
+impl Labels {
+    /// Get the label image width.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Get the label image height.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Get the row-major labels of every pixel.
+    pub fn labels(&self) -> &[u32] {
+        &self.labels
+    }
+
+    /// Get the label of the pixel at `(x, y)`.
+    pub fn get(&self, x: u32, y: u32) -> u32 {
+        assert!(x < self.width && y < self.height, "pixel out of bounds");
+        self.labels[y as usize * self.width as usize + x as usize]
+    }
+
+    /// Get the components, ordered by label.
+    pub fn components(&self) -> &[Component] {
+        &self.components
+    }
+
+    /// Export the labels as a PGM image.
+    ///
+    /// The bit depth is the number of labels, up to 65535.
+    ///
+    pub fn to_pgm(&self, encoding: EncodingType) -> Image {
+        let info = Info::new_pgm(encoding, self.width, self.height, self.bit_depth())
+            .expect("Label image info");
+        Image::from_parts(info, TypeInfo::Empty, self.samples())
+    }
+
+    /// Export the labels as a PAM image with the given tuple type.
+    ///
+    /// The bit depth is the number of labels, up to 65535.
+    ///
+    pub fn to_pam(&self, tuple_type: &str) -> Result<Image, NetpbmError> {
+        if tuple_type.is_empty() || !tuple_type.is_ascii() || tuple_type.contains('\n') {
+            return Err(NetpbmError::InvalidArgument {
+                info: format!("Invalid tuple type {:?}", tuple_type),
+            });
+        }
+
+        let info = Info::new_pam(self.width, self.height, self.bit_depth(), 1)?;
+        let type_info = TypeInfo::Info(vec![tuple_type.to_string()]);
+        Ok(Image::from_parts(info, type_info, self.samples()))
+    }
+
+    /// Get the bit depth of exported label images.
+    fn bit_depth(&self) -> u16 {
+        self.components.len().clamp(1, u16::MAX as usize) as u16
+    }
+
+    /// Get the labels as samples, wrapping labels past 65535.
+    fn samples(&self) -> Vec<u16> {
+        self.labels.iter().map(|&label| wrap(label)).collect()
+    }
+}
+
+/// Label the connected regions of black pixels in a bitmap.
+pub fn label(bitmap: &Bitmap, connectivity: Connectivity) -> Labels {
+    // Runs overlap if they share a column, or touch at a corner
+    // with 8-connectivity.
+    let reach = match connectivity {
+        Connectivity::Four => 0,
+        Connectivity::Eight => 1,
+    };
+
+    let mut parents: Vec<u32> = Vec::new();
+    let mut runs: Vec<Run> = Vec::new();
+    let mut previous = 0..0;
+    for y in 0..bitmap.height() {
+        let current = runs.len()..runs.len();
+        let mut above = previous.start;
+        for (start, end) in row_runs(bitmap.row(y), bitmap.width()) {
+            let mut id = None;
+            while above < previous.end && runs[above].end + reach <= start {
+                above += 1;
+            }
+
+            // Merge with every run above that overlaps. The last
+            // one may also overlap the next run in this row.
+            let mut k = above;
+            while k < previous.end && runs[k].start < end + reach {
+                let other = runs[k].id;
+                id = Some(match id {
+                    Some(id) => union(&mut parents, id, other),
+                    None => find(&mut parents, other),
+                });
+                k += 1;
+            }
+
+            let id = id.unwrap_or_else(|| {
+                parents.push(parents.len() as u32);
+                parents.len() as u32 - 1
+            });
+            runs.push(Run { y, start, end, id });
+        }
+        previous = current.start..runs.len();
+    }
+
+    // Runs are stored in raster order, so numbering sets as their
+    // first runs appear numbers components in raster order.
+    let mut labels_of = vec![0u32; parents.len()];
+    let mut components: Vec<Component> = Vec::new();
+    let mut sums: Vec<(f64, f64)> = Vec::new();
+    let mut labels = vec![0u32; bitmap.width() as usize * bitmap.height() as usize];
+    for run in &runs {
+        let root = find(&mut parents, run.id) as usize;
+        if labels_of[root] == 0 {
+            components.push(Component {
+                label: components.len() as u32 + 1,
+                area: 0,
+                bbox: Rect::new(run.start as i64, run.y as i64, 0, 0),
+                centroid: (0.0, 0.0),
+            });
+            sums.push((0.0, 0.0));
+            labels_of[root] = components.len() as u32;
+        }
+        let label = labels_of[root];
+
+        let len = (run.end - run.start) as u64;
+        let component = &mut components[label as usize - 1];
+        component.area += len;
+        component.bbox = union_rect(
+            component.bbox,
+            Rect::new(run.start as i64, run.y as i64, run.end - run.start, 1),
+        );
+        let sum = &mut sums[label as usize - 1];
+        sum.0 += len as f64 * (run.start + run.end - 1) as f64 / 2.0;
+        sum.1 += len as f64 * run.y as f64;
+
+        let offset = run.y as usize * bitmap.width() as usize;
+        labels[offset + run.start as usize..offset + run.end as usize].fill(label);
+    }
+
+    for (component, (sx, sy)) in components.iter_mut().zip(sums) {
+        let area = component.area as f64;
+        component.centroid = (sx / area, sy / area);
+    }
+
+    Labels {
+        width: bitmap.width(),
+        height: bitmap.height(),
+        labels,
+        components,
+    }
+}
+
+/// A run of black pixels in a row.
+#[derive(Debug, Clone, Copy)]
+struct Run {
+    y: u32,
+    start: u32,
+    end: u32,
+    id: u32,
+}
+
+/// Find the runs of black pixels in a packed row.
+fn row_runs(row: &[u8], width: u32) -> Vec<(u32, u32)> {
+    let mut runs = Vec::new();
+    let mut start = None;
+    for (i, &byte) in row.iter().enumerate() {
+        // Skip whole bytes that do not end a run or start one.
+        if (byte == 0x00 && start.is_none()) || (byte == 0xFF && start.is_some()) {
+            continue;
+        }
+        for bit in 0..8 {
+            let x = i as u32 * 8 + bit;
+            let black = byte & (0x80 >> bit) != 0;
+            match (black, start) {
+                (true, None) => start = Some(x),
+                (false, Some(s)) => {
+                    runs.push((s, x));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+    }
+    if let Some(s) = start {
+        runs.push((s, width));
+    }
+    runs
+}
+
+/// Find the root of a set, halving paths along the way.
+fn find(parents: &mut [u32], mut id: u32) -> u32 {
+    while parents[id as usize] != id {
+        parents[id as usize] = parents[parents[id as usize] as usize];
+        id = parents[id as usize];
+    }
+    id
+}
+
+/// Merge two sets, keeping the smaller root.
+fn union(parents: &mut [u32], a: u32, b: u32) -> u32 {
+    let (a, b) = (find(parents, a), find(parents, b));
+    let (root, child) = (a.min(b), a.max(b));
+    parents[child as usize] = root;
+    root
+}
+
+/// Get the smallest rectangle holding two rectangles.
+fn union_rect(a: Rect, b: Rect) -> Rect {
+    if a.width == 0 || a.height == 0 {
+        return b;
+    }
+    let left = a.x.min(b.x);
+    let top = a.y.min(b.y);
+    let right = (a.x + a.width as i64).max(b.x + b.width as i64);
+    let bottom = (a.y + a.height as i64).max(b.y + b.height as i64);
+    Rect::new(left, top, (right - left) as u32, (bottom - top) as u32)
+}
+
+/// Wrap a label to a nonzero sample, keeping 0 for the background.
+fn wrap(label: u32) -> u16 {
+    if label == 0 {
+        0
+    } else {
+        ((label - 1) % u16::MAX as u32 + 1) as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::bitmap;
+    use crate::NetpbmFormat;
+
+    #[test]
+    fn test_connectivity() {
+        let image = bitmap(&["x.x..........", ".x...xxxxxxxx", "x.....x.....x"]);
+
+        let four = label(&image, Connectivity::Four);
+        assert_eq!(four.components().len(), 5);
+        assert_eq!(four.get(0, 0), 1);
+        assert_eq!(four.get(2, 0), 2);
+        assert_eq!(four.get(1, 1), 3);
+        assert_eq!(four.get(5, 1), 4);
+        assert_eq!(four.get(12, 2), 4);
+        assert_eq!(four.get(0, 2), 5);
+        assert_eq!(four.get(1, 0), 0);
+
+        let eight = label(&image, Connectivity::Eight);
+        assert_eq!(eight.components().len(), 2);
+        assert_eq!(eight.get(0, 2), 1);
+        assert_eq!(eight.get(6, 2), 2);
+    }
+
+    #[test]
+    fn test_component_stats() {
+        // A U shape whose arms only join on the last row.
+        let image = bitmap(&["..........", ".x.....x..", ".x.....x..", ".xxxxxxx.."]);
+        let labels = label(&image, Connectivity::Four);
+
+        assert_eq!(
+            labels.components(),
+            [Component {
+                label: 1,
+                area: 11,
+                bbox: Rect::new(1, 1, 7, 3),
+                centroid: (4.0, 27.0 / 11.0),
+            }]
+        );
+        assert_eq!(labels.get(7, 1), 1);
+    }
+
+    #[test]
+    fn test_export() {
+        let image = bitmap(&["x.x.x", "....."]);
+        let labels = label(&image, Connectivity::Eight);
+
+        let pgm = labels.to_pgm(EncodingType::Plain);
+        assert_eq!(pgm.info().format, NetpbmFormat::PGMPlain);
+        assert_eq!(pgm.bit_depth(), 3);
+        assert_eq!(pgm.samples(), [1, 0, 2, 0, 3, 0, 0, 0, 0, 0]);
+
+        let pam = labels.to_pam("LABEL").expect("pam");
+        assert_eq!(pam.info().format, NetpbmFormat::PAM);
+        assert_eq!(pam.type_info(), &TypeInfo::Info(vec!["LABEL".to_string()]));
+        assert!(labels.to_pam("").is_err());
+
+        assert_eq!(wrap(65535), 65535);
+        assert_eq!(wrap(65536), 1);
+        assert_eq!(wrap(0), 0);
+    }
+}
@@ -0,0 +1,366 @@
+//! Contrast normalization and histogram equalization.
+//!
+//! `normalize` stretches samples between two percentiles to the
+//! full range of the bit depth, similar to netpbm's `pnmnorm`.
+//! `equalize` flattens the histogram of the whole image, similar
+//! to netpbm's `pnmhisteq`. `clahe` equalizes tiles of the image
+//! separately with a limit on how much contrast is added
+//! (contrast-limited adaptive histogram equalization).
+//!
+//! Grayscale images are adjusted directly. Color images are
+//! adjusted through their luminance: each pixel is scaled so that
+//! its luminance takes the new value, which keeps its hue. Pixels
+//! are scaled less if a sample would otherwise exceed the bit depth.
+//!
+//! Every operation works at the bit depth of the image, so 16-bit
+//! images are not quantized to 8 bits first.
+//!
+
+use crate::image::Image;
+use crate::math;
+use crate::NetpbmError;
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Parameters of contrast-limited adaptive histogram equalization.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Clahe {
+    /// The number of tiles across the image.
+    pub tiles_x: u32,
+
+    /// The number of tiles down the image.
+    pub tiles_y: u32,
+
+    /// The largest histogram bin of a tile, as a multiple of the
+    /// mean bin size. Counts above the limit are spread across
+    /// every bin. Smaller limits add less contrast.
+    pub clip_limit: f64,
+}
+
+impl Default for Clahe {
+    fn default() -> Self {
+        Clahe {
+            tiles_x: 8,
+            tiles_y: 8,
+            clip_limit: 2.0,
+        }
+    }
+}
+
+/// Stretch samples between two percentiles to the full range of
+/// the bit depth.
+///
+/// Samples at or below the `low` percentile become 0, and samples
+/// at or above the `high` percentile become the bit depth. Percentiles are in
+/// the range `[0, 100]`.
+///
+pub fn normalize(image: &Image, low: f64, high: f64) -> Result<Image, NetpbmError> {
+    if !(0.0..=100.0).contains(&low) || !(0.0..=100.0).contains(&high) || low >= high {
+        return Err(NetpbmError::InvalidArgument {
+            info: format!(
+                "Percentiles {} and {} should satisfy 0 <= low < high <= 100",
+                low, high
+            ),
+        });
+    }
+
+    let levels = levels(image)?;
+    let histogram = histogram(&levels, image.bit_depth());
+
+    let total = levels.len() as f64;
+    let percentile = |p: f64| {
+        let target = math::ceil(p / 100.0 * total).max(1.0) as u64;
+        let mut sum = 0;
+        histogram
+            .iter()
+            .position(|&n| {
+                sum += n as u64;
+                sum >= target
+            })
+            .unwrap_or(0) as u16
+    };
+    let (lo, hi) = (percentile(low), percentile(high));
+    if lo >= hi {
+        return Ok(image.clone());
+    }
+
+    let maxval = image.bit_depth() as f64;
+    let span = (hi - lo) as f64;
+    let lut: Vec<u16> = (0..=image.bit_depth())
+        .map(|l| math::round(l.saturating_sub(lo) as f64 * maxval / span).min(maxval) as u16)
+        .collect();
+
+    Ok(apply(image, &levels, |_, level| lut[level as usize]))
+}
+
+/// Equalize the histogram of an image.
+pub fn equalize(image: &Image) -> Result<Image, NetpbmError> {
+    let levels = levels(image)?;
+    let lut = equalization(&histogram(&levels, image.bit_depth()), image.bit_depth());
+    Ok(apply(image, &levels, |_, level| lut[level as usize]))
+}
+
+/// Equalize the histograms of tiles of an image, limiting the
+/// contrast added to each tile.
+///
+/// The mappings of neighbouring tiles are interpolated, so there
+/// are no seams at tile edges.
+///
+pub fn clahe(image: &Image, params: Clahe) -> Result<Image, NetpbmError> {
+    if params.tiles_x == 0
+        || params.tiles_y == 0
+        || params.clip_limit.is_nan()
+        || params.clip_limit < 1.0
+    {
+        return Err(NetpbmError::InvalidArgument {
+            info: format!(
+                "CLAHE needs at least one tile and a clip limit of at least 1, found {:?}",
+                params
+            ),
+        });
+    }
+
+    let levels = levels(image)?;
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let tiles_x = (params.tiles_x as usize).min(width);
+    let tiles_y = (params.tiles_y as usize).min(height);
+    let bins = image.bit_depth() as usize + 1;
+
+    // Tile boundaries, spreading the remainder across the tiles.
+    let edges = |len: usize, tiles: usize| (0..=tiles).map(|i| i * len / tiles).collect::<Vec<_>>();
+    let xs = edges(width, tiles_x);
+    let ys = edges(height, tiles_y);
+
+    let mut luts = Vec::with_capacity(tiles_x * tiles_y);
+    for ty in 0..tiles_y {
+        for tx in 0..tiles_x {
+            let mut histogram = vec![0u32; bins];
+            for y in ys[ty]..ys[ty + 1] {
+                for &level in &levels[y * width + xs[tx]..y * width + xs[tx + 1]] {
+                    histogram[level as usize] += 1;
+                }
+            }
+
+            let pixels = ((xs[tx + 1] - xs[tx]) * (ys[ty + 1] - ys[ty])) as f64;
+            clip(
+                &mut histogram,
+                (params.clip_limit * pixels / bins as f64).max(1.0),
+            );
+            luts.push(equalization(&histogram, image.bit_depth()));
+        }
+    }
+
+    // Locate a coordinate between the centers of two tiles.
+    let locate = |p: usize, edges: &[usize]| {
+        let tiles = edges.len() - 1;
+        let centers = |i: usize| (edges[i] + edges[i + 1]) as f64 / 2.0;
+        let p = p as f64 + 0.5;
+        let i = (0..tiles).rev().find(|&i| centers(i) <= p).unwrap_or(0);
+        if i + 1 >= tiles || p < centers(0) {
+            (i, i, 0.0)
+        } else {
+            (i, i + 1, (p - centers(i)) / (centers(i + 1) - centers(i)))
+        }
+    };
+    let columns: Vec<_> = (0..width).map(|x| locate(x, &xs)).collect();
+    let rows: Vec<_> = (0..height).map(|y| locate(y, &ys)).collect();
+
+    Ok(apply(image, &levels, |pixel, level| {
+        let (x0, x1, fx) = columns[pixel % width];
+        let (y0, y1, fy) = rows[pixel / width];
+        let map = |tx: usize, ty: usize| luts[ty * tiles_x + tx][level as usize] as f64;
+        let top = map(x0, y0) * (1.0 - fx) + map(x1, y0) * fx;
+        let bottom = map(x0, y1) * (1.0 - fx) + map(x1, y1) * fx;
+        math::round(top * (1.0 - fy) + bottom * fy) as u16
+    }))
+}
+
+/// Get the level of each pixel: the sample of a grayscale image,
+/// or the luminance of a color image.
+fn levels(image: &Image) -> Result<Vec<u16>, NetpbmError> {
+    match image.channels() {
+        1 => Ok(image.samples().to_vec()),
+        3 => Ok(image
+            .samples()
+            .chunks_exact(3)
+            .map(|rgb| math::round(luminance(rgb)) as u16)
+            .collect()),
+        channels => Err(NetpbmError::InvalidArgument {
+            info: format!(
+                "Contrast can only be adjusted for 1 or 3 channels, found {}",
+                channels
+            ),
+        }),
+    }
+}
+
+/// Rec. 601 luma of an RGB tuple.
+fn luminance(rgb: &[u16]) -> f64 {
+    0.299 * rgb[0] as f64 + 0.587 * rgb[1] as f64 + 0.114 * rgb[2] as f64
+}
+
+/// Build a histogram of levels with one bin per value.
+fn histogram(levels: &[u16], bit_depth: u16) -> Vec<u32> {
+    let mut histogram = vec![0u32; bit_depth as usize + 1];
+    for &level in levels {
+        histogram[level as usize] += 1;
+    }
+    histogram
+}
+
+/// Build the mapping that equalizes a histogram.
+fn equalization(histogram: &[u32], bit_depth: u16) -> Vec<u16> {
+    let total: u64 = histogram.iter().map(|&n| n as u64).sum();
+    let first = histogram.iter().find(|&&n| n > 0).copied().unwrap_or(0) as u64;
+    if total == first {
+        // A single level cannot be spread out.
+        return (0..=bit_depth).collect();
+    }
+
+    let mut sum = 0u64;
+    histogram
+        .iter()
+        .map(|&n| {
+            sum += n as u64;
+            let cdf = sum.saturating_sub(first) as f64 / (total - first) as f64;
+            math::round(cdf * bit_depth as f64) as u16
+        })
+        .collect()
+}
+
+/// Clip histogram bins to a limit and spread the excess evenly
+/// across every bin.
+fn clip(histogram: &mut [u32], limit: f64) {
+    let limit = limit as u32;
+    let excess: u64 = histogram
+        .iter()
+        .map(|&n| n.saturating_sub(limit) as u64)
+        .sum();
+
+    let bins = histogram.len() as u64;
+    let (share, remainder) = (excess / bins, excess % bins);
+    for (i, n) in histogram.iter_mut().enumerate() {
+        *n = (*n).min(limit) + share as u32 + ((i as u64 * remainder) % bins < remainder) as u32;
+    }
+}
+
+/// Set the level of each pixel to its mapped value.
+fn apply(image: &Image, levels: &[u16], map: impl Fn(usize, u16) -> u16) -> Image {
+    let mut out = image.clone();
+    let maxval = image.bit_depth() as f64;
+
+    if image.channels() == 1 {
+        for (pixel, (sample, &level)) in out.samples_mut().iter_mut().zip(levels).enumerate() {
+            *sample = map(pixel, level);
+        }
+        return out;
+    }
+
+    for (pixel, (rgb, &level)) in out
+        .samples_mut()
+        .chunks_exact_mut(3)
+        .zip(levels)
+        .enumerate()
+    {
+        let target = map(pixel, level) as f64;
+        let current = luminance(rgb);
+        if current == 0.0 {
+            // Black has no hue, so use gray.
+            rgb.fill(target as u16);
+            continue;
+        }
+
+        let brightest = *rgb.iter().max().expect("RGB tuple") as f64;
+        let factor = (target / current).min(maxval / brightest);
+        for sample in rgb.iter_mut() {
+            *sample = math::round(*sample as f64 * factor).min(maxval) as u16;
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{pgm, ppm};
+    use crate::{Info, TypeInfo};
+
+    #[test]
+    fn test_normalize_gray() {
+        let image = pgm(5, 1, 65535, vec![20000, 20001, 20002, 20003, 20004]);
+        let normalized = normalize(&image, 0.0, 100.0).expect("normalize");
+        assert_eq!(normalized.samples(), [0, 16384, 32768, 49151, 65535]);
+        assert_eq!(normalized.bit_depth(), 65535);
+
+        let normalized = normalize(&image, 20.0, 80.0).expect("normalize");
+        assert_eq!(normalized.samples(), [0, 21845, 43690, 65535, 65535]);
+
+        assert!(normalize(&image, 50.0, 50.0).is_err());
+        assert!(normalize(&image, -1.0, 50.0).is_err());
+    }
+
+    #[test]
+    fn test_normalize_color_keeps_hue() {
+        let image = ppm(2, 1, 255, vec![20, 10, 10, 40, 20, 20]);
+
+        let normalized = normalize(&image, 0.0, 100.0).expect("normalize");
+        let rgb = normalized.samples();
+        assert_eq!(rgb[..3], [0, 0, 0]);
+        assert_eq!(rgb[3..], [255, 128, 128]);
+    }
+
+    #[test]
+    fn test_equalize() {
+        let image = pgm(4, 1, 1000, vec![10, 10, 11, 12]);
+        let equalized = equalize(&image).expect("equalize");
+        assert_eq!(equalized.samples(), [0, 0, 500, 1000]);
+
+        let flat = pgm(2, 1, 255, vec![7, 7]);
+        assert_eq!(equalize(&flat), Ok(flat));
+
+        let info = Info::new_pam(1, 1, 255, 2).expect("pam");
+        let image = Image::new(info, TypeInfo::Empty, vec![1, 2]).expect("pam");
+        assert!(equalize(&image).is_err());
+    }
+
+    #[test]
+    fn test_clahe() {
+        let flat = pgm(16, 16, 4095, vec![2000; 256]);
+        let equalized = clahe(&flat, Clahe::default()).expect("clahe");
+        assert!(equalized
+            .samples()
+            .iter()
+            .all(|&s| s == equalized.samples()[0]));
+
+        // Every tile holds the same low contrast ramp, which is
+        // stretched across the range.
+        let samples = (0..64).map(|i| 1000 + i % 4).collect();
+        let ramp = pgm(8, 8, 4095, samples);
+        let params = Clahe {
+            tiles_x: 2,
+            tiles_y: 2,
+            clip_limit: 4096.0,
+        };
+        let equalized = clahe(&ramp, params).expect("clahe");
+        let row = equalized.row(0);
+        assert_eq!(row[..4], row[4..]);
+        assert!(row[..4].windows(2).all(|w| w[0] < w[1]));
+        assert!(row[3] - row[0] > 3000);
+
+        // A lower clip limit adds less contrast.
+        let params = Clahe {
+            clip_limit: 512.0,
+            ..params
+        };
+        let clipped = clahe(&ramp, params).expect("clahe");
+        assert!(clipped.row(0)[3] - clipped.row(0)[0] < row[3] - row[0]);
+
+        let params = Clahe {
+            clip_limit: 0.5,
+            ..Clahe::default()
+        };
+        assert!(clahe(&ramp, params).is_err());
+    }
+}
@@ -0,0 +1,199 @@
+//! Format and bit depth conversion.
+//!
+//! `convert` changes the format of an image, similar to netpbm's
+//! `pamtopnm`, `ppmtopgm`, and `pgmtoppm`. Samples are converted
+//! between channel counts as follows:
+//!
+//! - Grayscale images become color images by repeating the gray
+//!   sample in each channel.
+//! - Color images become grayscale images by taking the Rec. 601
+//!   luminance.
+//! - Images become PBM images by thresholding their gray level at
+//!   half the bit depth.
+//! - PAM images keep their channels. Alpha channels are dropped
+//!   when converting to PNM formats.
+//!
+//! PBM images become PAM images with the `BLACKANDWHITE` tuple
+//! type, in which 0 is black.
+//!
+//! `rescale` changes the bit depth of an image.
+//!
+
+use crate::image::Image;
+use crate::math;
+use crate::{BitDepth, ChannelDepth, EncodingType, Info, NetpbmError, NetpbmFormat, TypeInfo};
+use alloc::format;
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Convert an image to another format.
+///
+/// PFM formats are not supported, as their samples are floating
+/// point.
+///
+pub fn convert(image: &Image, format: NetpbmFormat) -> Result<Image, NetpbmError> {
+    if format.is_pfm() {
+        return Err(NetpbmError::InvalidArgument {
+            info: format!("Cannot convert to {} images", format.magic()),
+        });
+    }
+
+    let source = image.info();
+    let bit_depth = source.bit_depth.value();
+    let bitmap = is_pbm(&source.format);
+    let channels = image.channels() as usize;
+
+    // Gray level of a tuple, where 0 is black.
+    let gray = |tuple: &[u16]| -> u16 {
+        if bitmap {
+            1 - tuple[0]
+        } else if channels >= 3 {
+            let luma = 0.299 * tuple[0] as f64 + 0.587 * tuple[1] as f64 + 0.114 * tuple[2] as f64;
+            math::round(luma) as u16
+        } else {
+            tuple[0]
+        }
+    };
+
+    let tuples = image.samples().chunks_exact(channels);
+    let (samples, target_channels, target_depth): (Vec<u16>, u32, u16) = match format {
+        NetpbmFormat::PBMRaw | NetpbmFormat::PBMPlain => (
+            tuples
+                .map(|t| (gray(t) as u32 * 2 <= bit_depth as u32) as u16)
+                .collect(),
+            1,
+            1,
+        ),
+        NetpbmFormat::PGMRaw | NetpbmFormat::PGMPlain => (tuples.map(gray).collect(), 1, bit_depth),
+        NetpbmFormat::PPMRaw | NetpbmFormat::PPMPlain => (
+            tuples
+                .flat_map(|t| {
+                    if channels >= 3 {
+                        [t[0], t[1], t[2]]
+                    } else {
+                        [gray(t); 3]
+                    }
+                })
+                .collect(),
+            3,
+            bit_depth,
+        ),
+        NetpbmFormat::PAM if bitmap => (tuples.map(gray).collect(), 1, 1),
+        NetpbmFormat::PAM => (image.samples().to_vec(), channels as u32, bit_depth),
+        NetpbmFormat::PFMColor | NetpbmFormat::PFMGray => unreachable!(),
+    };
+
+    let type_info = match format {
+        NetpbmFormat::PAM if source.format == NetpbmFormat::PAM => image.type_info().clone(),
+        NetpbmFormat::PAM => TypeInfo::Info(vec![tuple_type(&source.format).to_string()]),
+        _ => TypeInfo::Empty,
+    };
+
+    let info = Info {
+        encoding: encoding_of(&format),
+        format,
+        width: source.width,
+        height: source.height,
+        bit_depth: BitDepth::new(target_depth)?,
+        channels: ChannelDepth::new(target_channels)?,
+    };
+    Ok(Image::from_parts(info, type_info, samples))
+}
+
+/// Change the bit depth of an image, scaling samples to the same
+/// fraction of the bit depth.
+///
+/// PBM images always have a bit depth of 1.
+///
+pub fn rescale(image: &Image, bit_depth: u16) -> Result<Image, NetpbmError> {
+    let target = BitDepth::new(bit_depth)?;
+    if is_pbm(&image.info().format) && bit_depth != 1 {
+        return Err(NetpbmError::InvalidArgument {
+            info: format!("PBM images cannot have a bit depth of {}", bit_depth),
+        });
+    }
+
+    let source = image.bit_depth() as u32;
+    let target_value = target.value() as u32;
+    let samples = image
+        .samples()
+        .iter()
+        .map(|&s| ((s as u32 * target_value + source / 2) / source) as u16)
+        .collect();
+
+    let mut info = image.info().clone();
+    info.bit_depth = target;
+    Ok(Image::from_parts(info, image.type_info().clone(), samples))
+}
+
+/// Determine whether a format is PBM.
+fn is_pbm(format: &NetpbmFormat) -> bool {
+    matches!(format, NetpbmFormat::PBMRaw | NetpbmFormat::PBMPlain)
+}
+
+/// Get the encoding type of a format.
+fn encoding_of(format: &NetpbmFormat) -> EncodingType {
+    use NetpbmFormat::*;
+    match format {
+        PBMPlain | PGMPlain | PPMPlain => EncodingType::Plain,
+        _ => EncodingType::Raw,
+    }
+}
+
+/// Get the PAM tuple type of a PNM format.
+fn tuple_type(format: &NetpbmFormat) -> &'static str {
+    use NetpbmFormat::*;
+    match format {
+        PBMRaw | PBMPlain => "BLACKANDWHITE",
+        PGMRaw | PGMPlain => "GRAYSCALE",
+        _ => "RGB",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{pgm, ppm};
+
+    #[test]
+    fn test_convert() {
+        let color = ppm(2, 1, 255, vec![255, 0, 0, 0, 255, 255]);
+
+        let gray = convert(&color, NetpbmFormat::PGMPlain).expect("pgm");
+        assert_eq!(gray.info().encoding, EncodingType::Plain);
+        assert_eq!(gray.samples(), [76, 179]);
+
+        let bitmap = convert(&color, NetpbmFormat::PBMRaw).expect("pbm");
+        assert_eq!(bitmap.samples(), [1, 0]);
+
+        let back = convert(&gray, NetpbmFormat::PPMRaw).expect("ppm");
+        assert_eq!(back.samples(), [76, 76, 76, 179, 179, 179]);
+
+        let pam = convert(&bitmap, NetpbmFormat::PAM).expect("pam");
+        assert_eq!(pam.samples(), [0, 1]);
+        assert_eq!(
+            pam.type_info(),
+            &TypeInfo::Info(vec!["BLACKANDWHITE".to_string()])
+        );
+        assert_eq!(
+            convert(&pam, NetpbmFormat::PBMPlain)
+                .expect("pbm")
+                .samples(),
+            [1, 0]
+        );
+
+        assert!(convert(&color, NetpbmFormat::PFMColor).is_err());
+    }
+
+    #[test]
+    fn test_rescale() {
+        let image = pgm(3, 1, 255, vec![0, 128, 255]);
+        let wide = rescale(&image, 65535).expect("rescale");
+        assert_eq!(wide.samples(), [0, 32896, 65535]);
+        assert_eq!(rescale(&wide, 15).expect("rescale").samples(), [0, 8, 15]);
+
+        let bitmap = convert(&image, NetpbmFormat::PBMRaw).expect("pbm");
+        assert!(rescale(&bitmap, 255).is_err());
+    }
+}
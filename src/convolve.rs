@@ -0,0 +1,567 @@
+//! Image convolution.
+//!
+//! `convolve` applies a weighted kernel to every sample of an
+//! image, similar to netpbm's `pnmconvol`. Each channel is
+//! convolved separately, and results are rounded and clamped to the
+//! bit depth of the image.
+//!
+//! Kernels that are the outer product of a column and a row are
+//! applied as two one-dimensional passes, which takes `w + h`
+//! multiplications per sample instead of `w * h`. Separable kernels
+//! are detected when a kernel is created.
+//!
+//! Kernels have odd dimensions and are centered on the sample
+//! being computed. Samples past the edges of the image are taken
+//! from the image or a constant, depending on the `Edge` mode.
+//!
+
+use crate::image::Image;
+#[cfg(feature = "std")]
+use crate::io;
+use crate::math;
+use crate::NetpbmError;
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Handling of samples past the edges of an image.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Edge {
+    /// Repeat the nearest edge sample.
+    Clamp,
+
+    /// Take samples from the opposite edge, as if the image tiled
+    /// the plane.
+    Wrap,
+
+    /// Reflect the image about its edge samples, without repeating
+    /// them.
+    Mirror,
+
+    /// Use a constant tuple.
+    Constant(Vec<u16>),
+}
+
+/// Convolution kernel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Kernel {
+    width: u32,
+    height: u32,
+    weights: Vec<f64>,
+    bias: f64,
+
+    /// Row and column factors of a separable kernel.
+    factors: Option<(Vec<f64>, Vec<f64>)>,
+}
+
+impl Kernel {
+    /// Create a kernel from row-major weights.
+    ///
+    /// The width and height must be odd.
+    ///
+    pub fn new(width: u32, height: u32, weights: Vec<f64>) -> Result<Self, NetpbmError> {
+        check_dims(width, height)?;
+        if weights.len() != width as usize * height as usize {
+            return Err(NetpbmError::InvalidArgument {
+                info: format!(
+                    "Expected {} kernel weights for a {}x{} kernel, found {}",
+                    width as usize * height as usize,
+                    width,
+                    height,
+                    weights.len()
+                ),
+            });
+        }
+        check_weights(&weights)?;
+
+        let factors = factor(width as usize, &weights);
+        Ok(Kernel {
+            width,
+            height,
+            weights,
+            bias: 0.0,
+            factors,
+        })
+    }
+
+    /// Create a separable kernel from its row and column factors.
+    ///
+    /// The weight at `(x, y)` is `horizontal[x] * vertical[y]`.
+    ///
+    pub fn separable(horizontal: Vec<f64>, vertical: Vec<f64>) -> Result<Self, NetpbmError> {
+        check_dims(horizontal.len() as u32, vertical.len() as u32)?;
+        check_weights(&horizontal)?;
+        check_weights(&vertical)?;
+
+        let weights = vertical
+            .iter()
+            .flat_map(|v| horizontal.iter().map(move |h| h * v))
+            .collect();
+        Ok(Kernel {
+            width: horizontal.len() as u32,
+            height: vertical.len() as u32,
+            weights,
+            bias: 0.0,
+            factors: Some((horizontal, vertical)),
+        })
+    }
+
+    /// Create a Gaussian blur kernel with standard deviation `sigma`.
+    ///
+    /// The kernel extends to three standard deviations from its center.
+    ///
+    pub fn gaussian(sigma: f64) -> Result<Self, NetpbmError> {
+        let weights = gaussian(sigma)?;
+        Self::separable(weights.clone(), weights)
+    }
+
+    /// Create a box blur kernel that averages a square of
+    /// `2 * radius + 1` samples on each side.
+    pub fn box_blur(radius: u32) -> Result<Self, NetpbmError> {
+        let size = radius
+            .checked_mul(2)
+            .and_then(|d| d.checked_add(1))
+            .ok_or_else(|| NetpbmError::InvalidArgument {
+                info: format!("Box blur radius {} is too large", radius),
+            })?;
+        let weights = vec![1.0 / size as f64; size as usize];
+        Self::separable(weights.clone(), weights)
+    }
+
+    /// Create a 3x3 sharpening kernel.
+    pub fn sharpen() -> Self {
+        #[rustfmt::skip]
+        let weights = vec![
+             0.0, -1.0,  0.0,
+            -1.0,  5.0, -1.0,
+             0.0, -1.0,  0.0,
+        ];
+        Self::new(3, 3, weights).expect("Sharpen kernel")
+    }
+
+    /// Create an unsharp mask kernel, which adds `amount` times the
+    /// difference between the image and its Gaussian blur.
+    pub fn unsharp_mask(sigma: f64, amount: f64) -> Result<Self, NetpbmError> {
+        let blur = gaussian(sigma)?;
+        let size = blur.len();
+        let mut weights: Vec<f64> = blur
+            .iter()
+            .flat_map(|v| blur.iter().map(move |h| -amount * h * v))
+            .collect();
+        weights[size * size / 2] += 1.0 + amount;
+        Self::new(size as u32, size as u32, weights)
+    }
+
+    /// Create a Sobel kernel for horizontal gradients, which
+    /// responds to vertical edges.
+    pub fn sobel_x() -> Self {
+        Self::separable(vec![-1.0, 0.0, 1.0], vec![1.0, 2.0, 1.0]).expect("Sobel kernel")
+    }
+
+    /// Create a Sobel kernel for vertical gradients, which
+    /// responds to horizontal edges.
+    pub fn sobel_y() -> Self {
+        Self::separable(vec![1.0, 2.0, 1.0], vec![-1.0, 0.0, 1.0]).expect("Sobel kernel")
+    }
+
+    /// Create a 3x3 Laplacian kernel.
+    pub fn laplacian() -> Self {
+        #[rustfmt::skip]
+        let weights = vec![
+            0.0,  1.0, 0.0,
+            1.0, -4.0, 1.0,
+            0.0,  1.0, 0.0,
+        ];
+        Self::new(3, 3, weights).expect("Laplacian kernel")
+    }
+
+    /// Create a kernel from a grayscale image.
+    ///
+    /// As with `pnmconvol`, sample values are mapped linearly from
+    /// `[0, maxval]` to weights in `[-1, 1]`.
+    ///
+    pub fn from_pgm(image: &Image) -> Result<Self, NetpbmError> {
+        if image.channels() != 1 {
+            return Err(NetpbmError::InvalidArgument {
+                info: format!(
+                    "Expected a kernel image with 1 channel, found {}",
+                    image.channels()
+                ),
+            });
+        }
+
+        let maxval = image.bit_depth() as f64;
+        let weights = image
+            .samples()
+            .iter()
+            .map(|&s| 2.0 * s as f64 / maxval - 1.0)
+            .collect();
+        Self::new(image.width(), image.height(), weights)
+    }
+
+    /// Decode a kernel from the first grayscale image in the given
+    /// reader.
+    #[cfg(feature = "std")]
+    pub fn read<R: io::Read>(reader: R) -> Result<Self, NetpbmError> {
+        Self::from_pgm(&Image::read(reader)?)
+    }
+
+    /// Scale the weights so that they sum to 1.
+    ///
+    /// Kernels whose weights sum to 0 are left unchanged.
+    ///
+    pub fn normalized(mut self) -> Self {
+        let sum: f64 = self.weights.iter().sum();
+        if sum != 0.0 {
+            self.weights.iter_mut().for_each(|w| *w /= sum);
+            if let Some((horizontal, _)) = &mut self.factors {
+                horizontal.iter_mut().for_each(|w| *w /= sum);
+            }
+        }
+        self
+    }
+
+    /// Add a constant to every convolved sample.
+    ///
+    /// A bias of half the bit depth keeps negative responses, such
+    /// as those of the Sobel and Laplacian kernels, from clamping to 0.
+    ///
+    pub fn with_bias(mut self, bias: f64) -> Self {
+        self.bias = bias;
+        self
+    }
+
+    /// Get the kernel width.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Get the kernel height.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Get the row-major kernel weights.
+    pub fn weights(&self) -> &[f64] {
+        &self.weights
+    }
+
+    /// Get the bias added to every convolved sample.
+    pub fn bias(&self) -> f64 {
+        self.bias
+    }
+
+    /// Determine whether the kernel is applied in two passes.
+    pub fn is_separable(&self) -> bool {
+        self.factors.is_some()
+    }
+}
+
+/// Convolve an image with a kernel.
+pub fn convolve(image: &Image, kernel: &Kernel, edge: &Edge) -> Result<Image, NetpbmError> {
+    if let Edge::Constant(fill) = edge {
+        image.info().validate_tuple(fill)?;
+    }
+
+    let values = match &kernel.factors {
+        Some((horizontal, vertical)) => convolve_separable(image, horizontal, vertical, edge),
+        None => convolve_direct(image, kernel, edge),
+    };
+
+    let maxval = image.bit_depth() as f64;
+    let samples = values
+        .into_iter()
+        .map(|v| math::round(v + kernel.bias).clamp(0.0, maxval) as u16)
+        .collect();
+    Ok(Image::from_parts(
+        image.info().clone(),
+        image.type_info().clone(),
+        samples,
+    ))
+}
+
+/// Apply a kernel sample by sample.
+fn convolve_direct(image: &Image, kernel: &Kernel, edge: &Edge) -> Vec<f64> {
+    let (width, height) = (image.width(), image.height());
+    let channels = image.channels() as usize;
+    let (rx, ry) = ((kernel.width / 2) as i64, (kernel.height / 2) as i64);
+
+    let mut values = Vec::with_capacity(image.samples().len());
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            let mut sums = vec![0.0; channels];
+            for (ky, row) in kernel
+                .weights
+                .chunks_exact(kernel.width as usize)
+                .enumerate()
+            {
+                let sy = locate(y + ky as i64 - ry, height, edge);
+                for (kx, &weight) in row.iter().enumerate() {
+                    let sx = locate(x + kx as i64 - rx, width, edge);
+                    let tuple = match (sx, sy) {
+                        (Some(sx), Some(sy)) => image.tuple(sx, sy),
+                        _ => fill(edge),
+                    };
+                    for (sum, &sample) in sums.iter_mut().zip(tuple) {
+                        *sum += weight * sample as f64;
+                    }
+                }
+            }
+            values.extend(sums);
+        }
+    }
+
+    values
+}
+
+/// Apply the row factor of a separable kernel, then its column factor.
+fn convolve_separable(
+    image: &Image,
+    horizontal: &[f64],
+    vertical: &[f64],
+    edge: &Edge,
+) -> Vec<f64> {
+    let (width, height) = (image.width(), image.height());
+    let channels = image.channels() as usize;
+    let row_len = width as usize * channels;
+    let (rx, ry) = ((horizontal.len() / 2) as i64, (vertical.len() / 2) as i64);
+
+    let mut rows = Vec::with_capacity(image.samples().len());
+    for y in 0..height {
+        for x in 0..width as i64 {
+            let mut sums = vec![0.0; channels];
+            for (k, &weight) in horizontal.iter().enumerate() {
+                let tuple = match locate(x + k as i64 - rx, width, edge) {
+                    Some(sx) => image.tuple(sx, y),
+                    None => fill(edge),
+                };
+                for (sum, &sample) in sums.iter_mut().zip(tuple) {
+                    *sum += weight * sample as f64;
+                }
+            }
+            rows.extend(sums);
+        }
+    }
+
+    // Rows past the edge are constant, so their row pass is the
+    // constant scaled by the sum of the row factor.
+    let scale: f64 = horizontal.iter().sum();
+    let outside: Vec<f64> = fill(edge).iter().map(|&s| s as f64 * scale).collect();
+
+    let mut values = vec![0.0; rows.len()];
+    for y in 0..height as i64 {
+        let out = &mut values[y as usize * row_len..(y as usize + 1) * row_len];
+        for (k, &weight) in vertical.iter().enumerate() {
+            match locate(y + k as i64 - ry, height, edge) {
+                Some(sy) => {
+                    let row = &rows[sy as usize * row_len..(sy as usize + 1) * row_len];
+                    for (value, &r) in out.iter_mut().zip(row) {
+                        *value += weight * r;
+                    }
+                }
+                None => {
+                    for (value, &o) in out.iter_mut().zip(outside.iter().cycle()) {
+                        *value += weight * o;
+                    }
+                }
+            }
+        }
+    }
+
+    values
+}
+
+/// Map a coordinate to a coordinate within `[0, len)`, or `None`
+/// if the constant tuple should be used.
+fn locate(i: i64, len: u32, edge: &Edge) -> Option<u32> {
+    let len = len as i64;
+    if (0..len).contains(&i) {
+        return Some(i as u32);
+    }
+
+    match edge {
+        Edge::Clamp => Some(i.clamp(0, len - 1) as u32),
+        Edge::Wrap => Some(i.rem_euclid(len) as u32),
+        Edge::Mirror if len == 1 => Some(0),
+        Edge::Mirror => {
+            let period = 2 * (len - 1);
+            let i = i.rem_euclid(period);
+            Some(if i < len { i } else { period - i } as u32)
+        }
+        Edge::Constant(_) => None,
+    }
+}
+
+/// Get the constant tuple of an edge mode.
+fn fill(edge: &Edge) -> &[u16] {
+    match edge {
+        Edge::Constant(fill) => fill,
+        _ => &[],
+    }
+}
+
+/// Check that kernel dimensions are odd.
+fn check_dims(width: u32, height: u32) -> Result<(), NetpbmError> {
+    if width.is_multiple_of(2) || height.is_multiple_of(2) {
+        return Err(NetpbmError::InvalidArgument {
+            info: format!(
+                "Kernel dimensions should be odd, found {}x{}",
+                width, height
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// Check that kernel weights are finite.
+fn check_weights(weights: &[f64]) -> Result<(), NetpbmError> {
+    if let Some(weight) = weights.iter().find(|w| !w.is_finite()) {
+        return Err(NetpbmError::InvalidArgument {
+            info: format!("Kernel weights should be finite, found {}", weight),
+        });
+    }
+    Ok(())
+}
+
+/// Compute normalized Gaussian weights out to three standard deviations.
+fn gaussian(sigma: f64) -> Result<Vec<f64>, NetpbmError> {
+    if !sigma.is_finite() || sigma <= 0.0 {
+        return Err(NetpbmError::InvalidArgument {
+            info: format!("Gaussian sigma should be positive, found {}", sigma),
+        });
+    }
+
+    let radius = math::ceil(3.0 * sigma) as i64;
+    let weights: Vec<f64> = (-radius..=radius)
+        .map(|x| math::exp(-(x * x) as f64 / (2.0 * sigma * sigma)))
+        .collect();
+    let sum: f64 = weights.iter().sum();
+    Ok(weights.into_iter().map(|w| w / sum).collect())
+}
+
+/// Split a kernel into row and column factors if it is the outer
+/// product of the two.
+fn factor(width: usize, weights: &[f64]) -> Option<(Vec<f64>, Vec<f64>)> {
+    // Factor through the largest weight to keep rounding error small.
+    let (pivot, &largest) = weights
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))?;
+    if largest == 0.0 {
+        return None;
+    }
+
+    let (px, py) = (pivot % width, pivot / width);
+    let horizontal: Vec<f64> = weights[py * width..(py + 1) * width]
+        .iter()
+        .map(|w| w / largest)
+        .collect();
+    let vertical: Vec<f64> = weights.iter().skip(px).step_by(width).copied().collect();
+
+    let tolerance = largest.abs() * 1e-12;
+    let separable = weights.chunks_exact(width).zip(&vertical).all(|(row, v)| {
+        row.iter()
+            .zip(&horizontal)
+            .all(|(w, h)| (w - h * v).abs() <= tolerance)
+    });
+    separable.then_some((horizontal, vertical))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{pgm, ppm};
+
+    #[test]
+    fn test_edges() {
+        let image = pgm(4, 1, 255, vec![10, 20, 30, 40]);
+        let kernel = Kernel::new(5, 1, vec![1.0, 0.0, 0.0, 0.0, 0.0]).expect("kernel");
+        assert!(kernel.is_separable());
+
+        // Each output sample is the input sample two to the left.
+        let expected = [
+            (Edge::Clamp, [10, 10, 10, 20]),
+            (Edge::Wrap, [30, 40, 10, 20]),
+            (Edge::Mirror, [30, 20, 10, 20]),
+            (Edge::Constant(vec![99]), [99, 99, 10, 20]),
+        ];
+        for (edge, samples) in expected {
+            let out = convolve(&image, &kernel, &edge).expect("convolve");
+            assert_eq!(out.samples(), samples, "{:?}", edge);
+        }
+
+        assert!(convolve(&image, &kernel, &Edge::Constant(vec![256])).is_err());
+        assert!(convolve(&image, &kernel, &Edge::Constant(vec![1, 2])).is_err());
+    }
+
+    #[test]
+    fn test_separable_matches_direct() {
+        let samples = (0..60).map(|i| (i * 7919 % 65536) as u16).collect();
+        let image = ppm(5, 4, 65535, samples);
+
+        let edges = [
+            Edge::Clamp,
+            Edge::Wrap,
+            Edge::Mirror,
+            Edge::Constant(vec![1, 2, 3]),
+        ];
+        for kernel in [Kernel::gaussian(1.0).expect("gaussian"), Kernel::sobel_y()] {
+            assert!(kernel.is_separable());
+            for edge in &edges {
+                let (horizontal, vertical) = kernel.factors.as_ref().expect("factors");
+                let fast = convolve_separable(&image, horizontal, vertical, edge);
+                let slow = convolve_direct(&image, &kernel, edge);
+                for (a, b) in fast.iter().zip(&slow) {
+                    assert!((a - b).abs() < 1e-6, "{:?}: {} != {}", edge, a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_kernels() {
+        // Blurs keep flat images flat.
+        let flat = pgm(6, 6, 1000, vec![700; 36]);
+        for kernel in [
+            Kernel::gaussian(1.5).expect("gaussian"),
+            Kernel::box_blur(2).expect("box"),
+            Kernel::sharpen(),
+            Kernel::unsharp_mask(1.0, 0.5).expect("unsharp"),
+        ] {
+            assert_eq!(convolve(&flat, &kernel, &Edge::Mirror), Ok(flat.clone()));
+        }
+        assert!(!Kernel::sharpen().is_separable());
+        assert!(Kernel::gaussian(0.0).is_err());
+        assert!(matches!(
+            Kernel::box_blur(u32::MAX / 2 + 1),
+            Err(NetpbmError::InvalidArgument { .. })
+        ));
+
+        // A vertical step edge.
+        let step = pgm(4, 3, 255, [0, 0, 100, 100].repeat(3));
+        let sobel = convolve(&step, &Kernel::sobel_x(), &Edge::Clamp).expect("sobel");
+        assert_eq!(sobel.row(1), [0, 255, 255, 0]);
+
+        let laplacian = Kernel::laplacian().with_bias(128.0);
+        let edges = convolve(&step, &laplacian, &Edge::Clamp).expect("laplacian");
+        assert_eq!(edges.row(1), [128, 228, 28, 128]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_kernel_from_pgm() {
+        let image = pgm(3, 1, 4, vec![0, 2, 4]);
+        let kernel = Kernel::from_pgm(&image).expect("kernel");
+        assert_eq!(kernel.weights(), [-1.0, 0.0, 1.0]);
+
+        let mut buf = Vec::new();
+        image.write(&mut buf).expect("write");
+        assert_eq!(Kernel::read(&buf[..]), Ok(kernel));
+
+        let kernel = Kernel::new(1, 3, vec![1.0, 2.0, 1.0]).expect("kernel");
+        assert_eq!(kernel.normalized().weights(), [0.25, 0.5, 0.25]);
+
+        assert!(Kernel::from_pgm(&pgm(2, 1, 4, vec![0, 4])).is_err());
+        assert!(Kernel::new(3, 3, vec![1.0; 8]).is_err());
+    }
+}
@@ -0,0 +1,565 @@
+//! Drawing primitives.
+//!
+//! These functions draw into an image with a tuple, similar to
+//! netpbm's `ppmdraw`. The tuple must have one sample per channel,
+//! with no sample larger than the bit depth. Shapes are clipped at
+//! the edges of the image, so coordinates may be negative or past
+//! the image.
+//!
+//! Coordinates are pixel positions, with the origin at the top-left
+//! pixel.
+//!
+
+use crate::image::Image;
+use crate::layout::Rect;
+use crate::math;
+use crate::NetpbmError;
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+
+/// Draw a single pixel.
+pub fn point(image: &mut Image, x: i64, y: i64, color: &[u16]) -> Result<(), NetpbmError> {
+    image.info().validate_tuple(color)?;
+    plot(image, x, y, color);
+    Ok(())
+}
+
+/// Draw a line from `start` to `end`.
+///
+/// The line steps one pixel at a time along its major axis, with
+/// the other coordinate rounded to the nearest pixel, so it covers
+/// the same pixels as Bresenham's algorithm up to ties.
+///
+pub fn line(
+    image: &mut Image,
+    start: (i64, i64),
+    end: (i64, i64),
+    color: &[u16],
+) -> Result<(), NetpbmError> {
+    image.info().validate_tuple(color)?;
+    draw_line(image, start, end, color);
+    Ok(())
+}
+
+/// Draw an antialiased line from `start` to `end` with Wu's
+/// algorithm.
+///
+/// Each pixel is blended with the color in proportion to its
+/// coverage by the line.
+///
+pub fn line_aa(
+    image: &mut Image,
+    start: (f64, f64),
+    end: (f64, f64),
+    color: &[u16],
+) -> Result<(), NetpbmError> {
+    image.info().validate_tuple(color)?;
+    if ![start.0, start.1, end.0, end.1]
+        .iter()
+        .all(|c| c.is_finite())
+    {
+        return Err(NetpbmError::InvalidArgument {
+            info: format!("Line from {:?} to {:?} should be finite", start, end),
+        });
+    }
+
+    let (mut x0, mut y0) = start;
+    let (mut x1, mut y1) = end;
+    let steep = (y1 - y0).abs() > (x1 - x0).abs();
+    if steep {
+        (x0, y0, x1, y1) = (y0, x0, y1, x1);
+    }
+    if x0 > x1 {
+        (x0, y0, x1, y1) = (x1, y1, x0, y0);
+    }
+
+    // Only visit the columns along the major axis inside the image.
+    let limit = if steep { image.height() } else { image.width() } as f64;
+    let gradient = if x1 == x0 { 0.0 } else { (y1 - y0) / (x1 - x0) };
+    let mut blend_at = |major: i64, minor: f64| {
+        let base = math::floor(minor);
+        let coverage = minor - base;
+        for (offset, weight) in [(0, 1.0 - coverage), (1, coverage)] {
+            let (x, y) = (major, (base as i64).saturating_add(offset));
+            let (x, y) = if steep { (y, x) } else { (x, y) };
+            blend(image, x, y, color, weight);
+        }
+    };
+
+    let first = math::round(x0).max(0.0) as i64;
+    let last = math::round(x1).min(limit - 1.0) as i64;
+    for major in first..=last {
+        blend_at(major, y0 + gradient * (major as f64 - x0));
+    }
+
+    Ok(())
+}
+
+/// Draw the outline of a rectangle.
+pub fn rect(image: &mut Image, rect: Rect, color: &[u16]) -> Result<(), NetpbmError> {
+    image.info().validate_tuple(color)?;
+    if rect.width == 0 || rect.height == 0 {
+        return Ok(());
+    }
+
+    let (left, top) = (rect.x, rect.y);
+    let right = rect.x.saturating_add(rect.width as i64 - 1);
+    let bottom = rect.y.saturating_add(rect.height as i64 - 1);
+    draw_line(image, (left, top), (right, top), color);
+    draw_line(image, (left, bottom), (right, bottom), color);
+    draw_line(image, (left, top), (left, bottom), color);
+    draw_line(image, (right, top), (right, bottom), color);
+    Ok(())
+}
+
+/// Fill a rectangle.
+pub fn fill_rect(image: &mut Image, rect: Rect, color: &[u16]) -> Result<(), NetpbmError> {
+    image.info().validate_tuple(color)?;
+    for y in clip(
+        rect.y,
+        rect.y.saturating_add(rect.height as i64 - 1),
+        image.height(),
+    ) {
+        span(
+            image,
+            y,
+            rect.x,
+            rect.x.saturating_add(rect.width as i64),
+            color,
+        );
+    }
+    Ok(())
+}
+
+/// Draw the outline of a circle.
+pub fn circle(
+    image: &mut Image,
+    center: (i64, i64),
+    radius: u32,
+    color: &[u16],
+) -> Result<(), NetpbmError> {
+    ellipse(image, center, radius, radius, color)
+}
+
+/// Fill a circle.
+pub fn fill_circle(
+    image: &mut Image,
+    center: (i64, i64),
+    radius: u32,
+    color: &[u16],
+) -> Result<(), NetpbmError> {
+    fill_ellipse(image, center, radius, radius, color)
+}
+
+/// Draw the outline of an axis-aligned ellipse with radii `rx`
+/// and `ry`.
+pub fn ellipse(
+    image: &mut Image,
+    center: (i64, i64),
+    rx: u32,
+    ry: u32,
+    color: &[u16],
+) -> Result<(), NetpbmError> {
+    image.info().validate_tuple(color)?;
+    let (cx, cy) = center;
+
+    // Step along both axes so that steep and shallow arcs have no
+    // gaps, only visiting the columns and rows inside the image.
+    for x in clip(
+        cx.saturating_sub(rx as i64),
+        cx.saturating_add(rx as i64),
+        image.width(),
+    ) {
+        let dy = extent(x - cx, rx, ry);
+        plot(image, x, cy.saturating_sub(dy), color);
+        plot(image, x, cy.saturating_add(dy), color);
+    }
+    for y in clip(
+        cy.saturating_sub(ry as i64),
+        cy.saturating_add(ry as i64),
+        image.height(),
+    ) {
+        let dx = extent(y - cy, ry, rx);
+        plot(image, cx.saturating_sub(dx), y, color);
+        plot(image, cx.saturating_add(dx), y, color);
+    }
+    Ok(())
+}
+
+/// Fill an axis-aligned ellipse with radii `rx` and `ry`.
+pub fn fill_ellipse(
+    image: &mut Image,
+    center: (i64, i64),
+    rx: u32,
+    ry: u32,
+    color: &[u16],
+) -> Result<(), NetpbmError> {
+    image.info().validate_tuple(color)?;
+    let (cx, cy) = center;
+    for y in clip(
+        cy.saturating_sub(ry as i64),
+        cy.saturating_add(ry as i64),
+        image.height(),
+    ) {
+        let dx = extent(y - cy, ry, rx);
+        span(
+            image,
+            y,
+            cx.saturating_sub(dx),
+            cx.saturating_add(dx).saturating_add(1),
+            color,
+        );
+    }
+    Ok(())
+}
+
+/// Draw the outline of a closed polygon.
+pub fn polygon(image: &mut Image, points: &[(i64, i64)], color: &[u16]) -> Result<(), NetpbmError> {
+    image.info().validate_tuple(color)?;
+    for (i, &start) in points.iter().enumerate() {
+        let end = points[(i + 1) % points.len()];
+        draw_line(image, start, end, color);
+    }
+    Ok(())
+}
+
+/// Fill a closed polygon with the even-odd rule.
+///
+/// A pixel is filled if a ray from it crosses the edges of the
+/// polygon an odd number of times, so self-intersecting polygons
+/// have holes where they overlap themselves. As with `fill_rect`,
+/// the right and bottom edges are not filled.
+///
+pub fn fill_polygon(
+    image: &mut Image,
+    points: &[(i64, i64)],
+    color: &[u16],
+) -> Result<(), NetpbmError> {
+    image.info().validate_tuple(color)?;
+    if points.len() < 3 {
+        return Ok(());
+    }
+
+    let top = points
+        .iter()
+        .map(|p| p.1)
+        .min()
+        .expect("Polygon points")
+        .max(0);
+    let bottom = points
+        .iter()
+        .map(|p| p.1)
+        .max()
+        .expect("Polygon points")
+        .min(image.height() as i64 - 1);
+
+    let mut crossings = Vec::new();
+    for y in top..=bottom {
+        // Edges include their upper end but not their lower one, so
+        // each vertex on the row is crossed once or not at all.
+        crossings.clear();
+        for (i, &(x0, y0)) in points.iter().enumerate() {
+            let (x1, y1) = points[(i + 1) % points.len()];
+            if (y0 <= y) != (y1 <= y) {
+                // Differences of far apart points overflow `i64`.
+                let t = (y as i128 - y0 as i128) as f64 / (y1 as i128 - y0 as i128) as f64;
+                crossings.push(x0 as f64 + t * (x1 as i128 - x0 as i128) as f64);
+            }
+        }
+        crossings.sort_by(f64::total_cmp);
+
+        for pair in crossings.chunks_exact(2) {
+            let start = math::ceil(pair[0]) as i64;
+            let end = math::ceil(pair[1]) as i64;
+            span(image, y, start, end, color);
+        }
+    }
+    Ok(())
+}
+
+/// Fill the 4-connected region of pixels with the same tuple as the
+/// pixel at `(x, y)`.
+///
+/// Seeds outside the image fill nothing.
+///
+pub fn flood_fill(image: &mut Image, x: i64, y: i64, color: &[u16]) -> Result<(), NetpbmError> {
+    image.info().validate_tuple(color)?;
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    if !(0..width).contains(&x) || !(0..height).contains(&y) {
+        return Ok(());
+    }
+
+    let target = image.tuple(x as u32, y as u32).to_vec();
+    if target == color {
+        return Ok(());
+    }
+
+    // Fill whole spans, queueing the rows above and below each one.
+    let matches = |image: &Image, x: i64, y: i64| image.tuple(x as u32, y as u32) == target;
+    let mut stack = vec![(x, y)];
+    while let Some((x, y)) = stack.pop() {
+        if !matches(image, x, y) {
+            continue;
+        }
+
+        let mut left = x;
+        while left > 0 && matches(image, left - 1, y) {
+            left -= 1;
+        }
+        let mut right = x;
+        while right + 1 < width && matches(image, right + 1, y) {
+            right += 1;
+        }
+
+        span(image, y, left, right + 1, color);
+        for ny in [y - 1, y + 1] {
+            if !(0..height).contains(&ny) {
+                continue;
+            }
+            let mut in_run = false;
+            for nx in left..=right {
+                let hit = matches(image, nx, ny);
+                if hit && !in_run {
+                    stack.push((nx, ny));
+                }
+                in_run = hit;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Set a pixel if it is inside the image.
+fn plot(image: &mut Image, x: i64, y: i64, color: &[u16]) {
+    if (0..image.width() as i64).contains(&x) && (0..image.height() as i64).contains(&y) {
+        image.tuple_mut(x as u32, y as u32).copy_from_slice(color);
+    }
+}
+
+/// Blend a pixel toward a color by `weight` if it is inside the image.
+fn blend(image: &mut Image, x: i64, y: i64, color: &[u16], weight: f64) {
+    if (0..image.width() as i64).contains(&x) && (0..image.height() as i64).contains(&y) {
+        for (sample, &c) in image.tuple_mut(x as u32, y as u32).iter_mut().zip(color) {
+            let value = *sample as f64 + (c as f64 - *sample as f64) * weight;
+            *sample = math::round(value) as u16;
+        }
+    }
+}
+
+/// Set the pixels of row `y` from `start` up to `end`, clipped to
+/// the image.
+fn span(image: &mut Image, y: i64, start: i64, end: i64, color: &[u16]) {
+    if !(0..image.height() as i64).contains(&y) {
+        return;
+    }
+    let start = start.max(0);
+    let end = end.min(image.width() as i64);
+    for x in start..end {
+        image.tuple_mut(x as u32, y as u32).copy_from_slice(color);
+    }
+}
+
+/// Draw a line, visiting only the steps along the major axis that
+/// fall inside the image.
+fn draw_line(image: &mut Image, start: (i64, i64), end: (i64, i64), color: &[u16]) {
+    // Work in `i128`, since the distance between two points may
+    // overflow `i64`.
+    let (x0, y0) = (start.0 as i128, start.1 as i128);
+    let (dx, dy) = (end.0 as i128 - x0, end.1 as i128 - y0);
+    let steps = dx.abs().max(dy.abs());
+    if steps == 0 {
+        plot(image, start.0, start.1, color);
+        return;
+    }
+
+    // The major coordinate moves by one each step, so the steps
+    // inside the image form a single range.
+    let (origin, delta, limit) = if dx.abs() >= dy.abs() {
+        (x0, dx.signum(), image.width() as i128)
+    } else {
+        (y0, dy.signum(), image.height() as i128)
+    };
+    let (a, b) = if delta > 0 {
+        (-origin, limit - 1 - origin)
+    } else {
+        (origin - (limit - 1), origin)
+    };
+    let first = a.clamp(0, steps);
+    let last = b.clamp(-1, steps);
+
+    // Points between the ends fit in `i64`.
+    for k in first..=last {
+        let x = x0 + mul_div_round(k, dx, steps);
+        let y = y0 + mul_div_round(k, dy, steps);
+        plot(image, x as i64, y as i64, color);
+    }
+}
+
+/// Clip the inclusive range `first..=last` to `0..limit`.
+fn clip(first: i64, last: i64, limit: u32) -> RangeInclusive<i64> {
+    first.max(0)..=last.min(limit as i64 - 1)
+}
+
+/// Compute `k * n / d`, rounding halves away from zero.
+///
+/// The factors are at most `u64::MAX` in magnitude, so their
+/// product fits in `u128`.
+///
+fn mul_div_round(k: i128, n: i128, d: i128) -> i128 {
+    let product = k.unsigned_abs() * n.unsigned_abs();
+    let d = d.unsigned_abs();
+    let (q, r) = (product / d, product % d);
+    let q = (q + (r >= d - r) as u128) as i128;
+    if (k < 0) != (n < 0) {
+        -q
+    } else {
+        q
+    }
+}
+
+/// Get the half-extent of an ellipse along one axis at offset `d`
+/// along the other, where `r` is the radius along the offset and
+/// `other` the radius along the extent.
+fn extent(d: i64, r: u32, other: u32) -> i64 {
+    if r == 0 {
+        return other as i64;
+    }
+    let t = d as f64 / r as f64;
+    math::round(other as f64 * math::sqrt((1.0 - t * t).max(0.0))) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{pgm, ppm};
+    use alloc::string::String;
+
+    /// Render the pixels of a PGM image that are not 0.
+    fn ascii(image: &Image) -> Vec<String> {
+        (0..image.height())
+            .map(|y| {
+                image
+                    .row(y)
+                    .iter()
+                    .map(|&s| if s == 0 { '.' } else { 'x' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_lines() {
+        let mut image = pgm(6, 4, 255, vec![0; 24]);
+        line(&mut image, (0, 0), (5, 2), &[255]).expect("line");
+        line(&mut image, (-100, 3), (1_000_000_000, 3), &[255]).expect("line");
+        assert_eq!(ascii(&image), ["xx....", "..xx..", "....xx", "xxxxxx"]);
+
+        let mut image = pgm(3, 1, 255, vec![0; 3]);
+        line_aa(&mut image, (0.0, 0.25), (2.0, 0.25), &[200]).expect("line");
+        assert_eq!(image.row(0), [150, 150, 150]);
+
+        assert!(point(&mut image, 0, 0, &[256]).is_err());
+        assert!(point(&mut image, 0, 0, &[1, 2]).is_err());
+    }
+
+    #[test]
+    fn test_shapes() {
+        let mut image = pgm(6, 5, 255, vec![0; 30]);
+        rect(&mut image, Rect::new(-1, 1, 4, 3), &[9]).expect("rect");
+        fill_rect(&mut image, Rect::new(4, -2, 5, 4), &[9]).expect("fill");
+        assert_eq!(
+            ascii(&image),
+            ["....xx", "xxx.xx", "..x...", "xxx...", "......"]
+        );
+
+        let mut image = pgm(7, 7, 255, vec![0; 49]);
+        circle(&mut image, (3, 3), 3, &[1]).expect("circle");
+        assert_eq!(
+            ascii(&image),
+            ["..xxx..", ".x...x.", "x.....x", "x.....x", "x.....x", ".x...x.", "..xxx..",]
+        );
+
+        let mut image = pgm(7, 3, 255, vec![0; 21]);
+        fill_ellipse(&mut image, (3, 1), 3, 1, &[1]).expect("ellipse");
+        assert_eq!(ascii(&image), ["...x...", "xxxxxxx", "...x..."]);
+    }
+
+    #[test]
+    fn test_clipping() {
+        // Huge shapes only visit the pixels inside the image.
+        let mut image = pgm(3, 2, 255, vec![0; 6]);
+        let rect = Rect::new(-1000, -1000, u32::MAX, u32::MAX);
+        fill_rect(&mut image, rect, &[1]).expect("fill");
+        assert!(image.samples().iter().all(|&s| s == 1));
+        circle(&mut image, (1, 1), u32::MAX, &[2]).expect("circle");
+        fill_circle(&mut image, (1 << 40, 0), u32::MAX, &[3]).expect("fill");
+        assert_eq!(image.samples(), [1; 6]);
+
+        let result = line_aa(&mut image, (0.0, f64::NAN), (2.0, 1.0), &[4]);
+        assert!(matches!(result, Err(NetpbmError::InvalidArgument { .. })));
+        assert!(line_aa(&mut image, (0.0, 0.0), (f64::INFINITY, 1.0), &[4]).is_err());
+    }
+
+    #[test]
+    fn test_extreme_coordinates() {
+        let (min, max) = (i64::MIN, i64::MAX);
+        let mut image = pgm(3, 3, 255, vec![0; 9]);
+        line(&mut image, (min, min), (max, max), &[1]).expect("line");
+        assert_eq!(ascii(&image), ["x..", ".x.", "..x"]);
+
+        let mut image = pgm(3, 3, 255, vec![0; 9]);
+        rect(&mut image, Rect::new(max - 1, 0, u32::MAX, 2), &[1]).expect("rect");
+        fill_rect(&mut image, Rect::new(max, max, u32::MAX, u32::MAX), &[1]).expect("fill");
+        fill_polygon(&mut image, &[(min, 1), (max, 1), (max, 2), (min, 2)], &[1]).expect("polygon");
+        assert_eq!(ascii(&image), ["...", "xxx", "..."]);
+
+        circle(&mut image, (1, max), u32::MAX, &[2]).expect("circle");
+        fill_ellipse(&mut image, (max, 1), u32::MAX, 1, &[3]).expect("ellipse");
+        polygon(&mut image, &[(min, max), (max, min)], &[4]).expect("polygon");
+        line_aa(&mut image, (0.0, 1e300), (2.0, -1e300), &[5]).expect("line_aa");
+    }
+
+    #[test]
+    fn test_polygons() {
+        let mut image = pgm(5, 5, 255, vec![0; 25]);
+        polygon(&mut image, &[(0, 0), (4, 0), (4, 4), (0, 4)], &[1]).expect("polygon");
+        assert_eq!(ascii(&image), ["xxxxx", "x...x", "x...x", "x...x", "xxxxx"]);
+
+        let mut image = pgm(5, 5, 255, vec![0; 25]);
+        fill_polygon(&mut image, &[(0, 0), (4, 0), (4, 4), (0, 4)], &[1]).expect("fill");
+        assert_eq!(ascii(&image), ["xxxx.", "xxxx.", "xxxx.", "xxxx.", "....."]);
+
+        // Two overlapping squares traced as one polygon leave a hole
+        // where they overlap.
+        let mut image = pgm(6, 6, 255, vec![0; 36]);
+        let points = [
+            (0, 0),
+            (4, 0),
+            (4, 4),
+            (2, 4),
+            (2, 2),
+            (6, 2),
+            (6, 6),
+            (0, 6),
+        ];
+        fill_polygon(&mut image, &points, &[1]).expect("fill");
+        assert_eq!(image.tuple(1, 1), [1]);
+        assert_eq!(image.tuple(3, 3), [0]);
+        assert_eq!(image.tuple(5, 5), [1]);
+    }
+
+    #[test]
+    fn test_flood_fill() {
+        let mut image = ppm(5, 3, 255, vec![0; 45]);
+        line(&mut image, (2, 0), (2, 2), &[255, 255, 255]).expect("wall");
+
+        flood_fill(&mut image, 0, 0, &[255, 0, 0]).expect("fill");
+        assert_eq!(image.tuple(1, 2), [255, 0, 0]);
+        assert_eq!(image.tuple(2, 1), [255, 255, 255]);
+        assert_eq!(image.tuple(3, 1), [0, 0, 0]);
+
+        flood_fill(&mut image, 9, 9, &[255, 0, 0]).expect("fill");
+    }
+}
@@ -0,0 +1,395 @@
+//! Encoding into caller-provided buffers.
+//!
+//! `encoded_len` computes the size of an encoded image from its
+//! header info alone, and `encode_into` serializes an image into a
+//! `&mut [u8]`, producing the same bytes as the PBM, PGM, PPM, and
+//! PAM encoders. Comments, if any, are written as `#` lines after
+//! the magic number.
+//!
+//! Neither function allocates, except to build an error, so a
+//! buffer can be sized once and reused for many images with the
+//! same header.
+//!
+//! The size of a `raw` image only depends on its header. The size
+//! of a `plain` image depends on the number of digits of each
+//! sample, so `encoded_len` returns the size with every sample at
+//! the bit depth, which is enough room for any samples, and
+//! `encode_into` returns the number of bytes actually written.
+//!
+
+use alloc::format;
+
+use crate::{EncodingType, Info, NetpbmError, NetpbmFormat, TypeInfo};
+
+/// Number of PBM samples per line of a `plain` image.
+const PBM_LINE_SAMPLES: usize = 35;
+
+/// Compute the number of bytes `encode_into` needs for an image.
+///
+/// This is exact for `raw` images, and an upper bound for `plain`
+/// images. `type_info` is only written for PAM images.
+///
+pub fn encoded_len(
+    info: &Info,
+    type_info: &TypeInfo,
+    comments: &[&str],
+) -> Result<usize, NetpbmError> {
+    let max_digits = digits(info.bit_depth.value() as u32);
+    header_len(info, type_info, comments)?
+        .checked_add(raster_len(info, max_digits)?)
+        .ok_or_else(|| too_large(info))
+}
+
+/// Encode an image into the front of the buffer and return the
+/// number of bytes written.
+///
+/// Samples are validated against the header info as by the
+/// encoders. The buffer must hold at least the exact encoded size,
+/// which `encoded_len` never underestimates; nothing is written if
+/// it does not.
+///
+pub fn encode_into<S: Copy + Into<u16>>(
+    buf: &mut [u8],
+    info: &Info,
+    type_info: &TypeInfo,
+    comments: &[&str],
+    samples: &[S],
+) -> Result<usize, NetpbmError> {
+    info.validate_sample_size(samples.len())?;
+    if let Some(offset) = samples
+        .iter()
+        .position(|&s| s.into() > info.bit_depth.value())
+    {
+        return Err(NetpbmError::OversizedSample {
+            offset,
+            bit_depth: info.bit_depth,
+        });
+    }
+
+    // Plain samples take at most 6 bytes each, and are in memory.
+    let len = header_len(info, type_info, comments)?
+        .checked_add(match info.encoding {
+            EncodingType::Raw => raster_len(info, 0)?,
+            EncodingType::Plain => samples.iter().map(|&s| digits(s.into() as u32) + 1).sum(),
+        })
+        .ok_or_else(|| too_large(info))?;
+    if len > buf.len() {
+        return Err(NetpbmError::IOOperationFailed {
+            info: format!(
+                "{} bytes do not fit in a buffer of {} bytes",
+                len,
+                buf.len()
+            ),
+        });
+    }
+
+    let mut cursor = Cursor { buf, pos: 0 };
+    write_header(&mut cursor, info, type_info, comments);
+    write_raster(&mut cursor, info, samples);
+    debug_assert_eq!(cursor.pos, len);
+    Ok(len)
+}
+
+/// Compute the length of the header, validating the format and
+/// comments.
+fn header_len(info: &Info, type_info: &TypeInfo, comments: &[&str]) -> Result<usize, NetpbmError> {
+    if info.format.is_pfm() {
+        return Err(NetpbmError::InvalidArgument {
+            info: format!("{:?} images cannot be encoded into a buffer", info.format),
+        });
+    }
+    if let Some(comment) = comments.iter().find(|c| c.contains(['\n', '\r'])) {
+        return Err(NetpbmError::InvalidArgument {
+            info: format!("Comment {:?} spans several lines", comment),
+        });
+    }
+
+    // Magic number and newline, then a line per comment.
+    let mut len = 3 + comments.iter().map(|c| c.len() + 3).sum::<usize>();
+    let (width, height) = (info.width.value(), info.height.value());
+    let bit_depth = info.bit_depth.value() as u32;
+    len += match info.format {
+        NetpbmFormat::PBMRaw | NetpbmFormat::PBMPlain => digits(width) + digits(height) + 2,
+        NetpbmFormat::PAM => {
+            let tuple_types = match type_info {
+                TypeInfo::Info(types) => types.iter().map(|t| t.len() + 10).sum(),
+                TypeInfo::Empty => 0,
+            };
+            "WIDTH \nHEIGHT \nDEPTH \nMAXVAL \nENDHDR\n".len()
+                + digits(width)
+                + digits(height)
+                + digits(info.channels.value())
+                + digits(bit_depth)
+                + tuple_types
+        }
+        _ => digits(width) + digits(height) + digits(bit_depth) + 3,
+    };
+    Ok(len)
+}
+
+/// Compute the length of the raster, with `sample_digits` digits
+/// for every `plain` sample.
+fn raster_len(info: &Info, sample_digits: usize) -> Result<usize, NetpbmError> {
+    let samples = info.sample_count().ok_or_else(|| too_large(info))?;
+    let len = match (&info.format, info.encoding) {
+        (_, EncodingType::Raw) => info.raw_raster_len(),
+        // Every sample is one digit followed by a space or newline.
+        (NetpbmFormat::PBMRaw | NetpbmFormat::PBMPlain, EncodingType::Plain) => {
+            samples.checked_mul(2)
+        }
+        (_, EncodingType::Plain) => samples.checked_mul(sample_digits + 1),
+    };
+    len.ok_or_else(|| too_large(info))
+}
+
+/// Error for an image whose encoded size does not fit in a `usize`.
+fn too_large(info: &Info) -> NetpbmError {
+    NetpbmError::InvalidArgument {
+        info: format!(
+            "Image of {}x{} with {} channels is too large to encode",
+            info.width, info.height, info.channels
+        ),
+    }
+}
+
+/// Write the header, comments included.
+fn write_header(cursor: &mut Cursor, info: &Info, type_info: &TypeInfo, comments: &[&str]) {
+    cursor.put(&info.format.magic().to_bytes());
+    cursor.put(b"\n");
+    for comment in comments {
+        cursor.put(b"# ");
+        cursor.put(comment.as_bytes());
+        cursor.put(b"\n");
+    }
+
+    match &info.format {
+        NetpbmFormat::PAM => {
+            cursor.put(b"WIDTH ");
+            cursor.put_decimal(info.width.value());
+            cursor.put(b"\nHEIGHT ");
+            cursor.put_decimal(info.height.value());
+            cursor.put(b"\nDEPTH ");
+            cursor.put_decimal(info.channels.value());
+            cursor.put(b"\nMAXVAL ");
+            cursor.put_decimal(info.bit_depth.value() as u32);
+            cursor.put(b"\n");
+            if let TypeInfo::Info(types) = type_info {
+                for tuple_type in types {
+                    cursor.put(b"TUPLTYPE ");
+                    cursor.put(tuple_type.as_bytes());
+                    cursor.put(b"\n");
+                }
+            }
+            cursor.put(b"ENDHDR\n");
+        }
+        format => {
+            cursor.put_decimal(info.width.value());
+            cursor.put(b" ");
+            cursor.put_decimal(info.height.value());
+            if !matches!(format, NetpbmFormat::PBMRaw | NetpbmFormat::PBMPlain) {
+                cursor.put(b" ");
+                cursor.put_decimal(info.bit_depth.value() as u32);
+            }
+            cursor.put(b"\n");
+        }
+    }
+}
+
+/// Write the raster in the layout of the encoders.
+fn write_raster<S: Copy + Into<u16>>(cursor: &mut Cursor, info: &Info, samples: &[S]) {
+    let width = info.width.value() as usize;
+    let channels = info.channels.value() as usize;
+    match (&info.format, info.encoding) {
+        (NetpbmFormat::PBMRaw | NetpbmFormat::PBMPlain, EncodingType::Raw) => {
+            // Each row is packed separately, with right-side padding.
+            for row in samples.chunks(width) {
+                for bits in row.chunks(8) {
+                    let byte = bits
+                        .iter()
+                        .enumerate()
+                        .fold(0, |a, (i, &b)| a | ((b.into() as u8) << (7 - i)));
+                    cursor.put(&[byte]);
+                }
+            }
+        }
+        (NetpbmFormat::PBMRaw | NetpbmFormat::PBMPlain, EncodingType::Plain) => {
+            for line in samples.chunks(PBM_LINE_SAMPLES) {
+                for (i, &sample) in line.iter().enumerate() {
+                    let separator = if i + 1 == line.len() { b'\n' } else { b' ' };
+                    cursor.put(&[b'0' + sample.into() as u8, separator]);
+                }
+            }
+        }
+        (_, EncodingType::Raw) if info.bit_depth.is_multi_byte() => {
+            // netpbm specifies that multi-byte samples are big-endian.
+            for &sample in samples {
+                cursor.put(&sample.into().to_be_bytes());
+            }
+        }
+        (_, EncodingType::Raw) => {
+            for &sample in samples {
+                cursor.put(&[sample.into() as u8]);
+            }
+        }
+        // PGM has one sample per line, and PPM one triplet per line.
+        (format, EncodingType::Plain) => {
+            let line = if *format == NetpbmFormat::PPMPlain {
+                channels
+            } else {
+                1
+            };
+            for tuple in samples.chunks(line) {
+                for (i, &sample) in tuple.iter().enumerate() {
+                    cursor.put_decimal(sample.into() as u32);
+                    cursor.put(if i + 1 == tuple.len() { b"\n" } else { b" " });
+                }
+            }
+        }
+    }
+}
+
+/// Count the decimal digits of a value.
+fn digits(value: u32) -> usize {
+    value.checked_ilog10().unwrap_or(0) as usize + 1
+}
+
+/// A write position in a buffer known to be large enough.
+struct Cursor<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl Cursor<'_> {
+    /// Copy bytes at the write position.
+    fn put(&mut self, bytes: &[u8]) {
+        self.buf[self.pos..self.pos + bytes.len()].copy_from_slice(bytes);
+        self.pos += bytes.len();
+    }
+
+    /// Write a value in decimal ASCII.
+    fn put_decimal(&mut self, value: u32) {
+        let len = digits(value);
+        let mut rest = value;
+        for byte in self.buf[self.pos..self.pos + len].iter_mut().rev() {
+            *byte = b'0' + (rest % 10) as u8;
+            rest /= 10;
+        }
+        self.pos += len;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::Image;
+    use crate::{pam, pbm, pgm, ppm};
+    use alloc::string::ToString;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    /// Encode into an exactly sized buffer.
+    fn encode<S: Copy + Into<u16>>(info: &Info, type_info: &TypeInfo, samples: &[S]) -> Vec<u8> {
+        let mut buf = vec![0; encoded_len(info, type_info, &[]).expect("len")];
+        let len = encode_into(&mut buf, info, type_info, &[], samples).expect("encode");
+        buf.truncate(len);
+        buf
+    }
+
+    #[test]
+    fn test_matches_encoders() {
+        let bits = [1u8, 0, 1, 1, 0, 0, 1, 0, 1, 1, 1, 0];
+        for encoding in [EncodingType::Raw, EncodingType::Plain] {
+            let mut out = Vec::new();
+            pbm::Encoder::new(&mut out)
+                .write(encoding, 6, 2, &bits)
+                .expect("pbm");
+            let info = Info::new_pbm(encoding, 6, 2).expect("pbm");
+            assert_eq!(encode(&info, &TypeInfo::Empty, &bits), out);
+
+            let gray = [0u16, 7, 300, 65535, 12, 9];
+            let mut out = Vec::new();
+            pgm::Encoder::new(&mut out)
+                .write_wide(encoding, 3, 2, 65535, &gray)
+                .expect("pgm");
+            let info = Info::new_pgm(encoding, 3, 2, 65535).expect("pgm");
+            assert_eq!(encode(&info, &TypeInfo::Empty, &gray), out);
+
+            let rgb = [0u8, 1, 22, 255, 254, 99];
+            let mut out = Vec::new();
+            ppm::Encoder::new(&mut out)
+                .write(encoding, 2, 1, 255, &rgb)
+                .expect("ppm");
+            let info = Info::new_ppm(encoding, 2, 1, 255).expect("ppm");
+            assert_eq!(encode(&info, &TypeInfo::Empty, &rgb), out);
+        }
+
+        let type_info = TypeInfo::Info(vec!["GRAYSCALE_ALPHA".to_string()]);
+        let samples = [3u8, 15, 0, 15];
+        let mut out = Vec::new();
+        pam::Encoder::new(&mut out)
+            .write(2, 1, 15, 2, &type_info, &samples)
+            .expect("pam");
+        let info = Info::new_pam(2, 1, 15, 2).expect("pam");
+        assert_eq!(encoded_len(&info, &type_info, &[]), Ok(out.len()));
+        assert_eq!(encode(&info, &type_info, &samples), out);
+    }
+
+    #[test]
+    fn test_comments() {
+        let info = Info::new_pam(1, 1, 255, 1).expect("pam");
+        let mut buf = [0; 64];
+        let len = encode_into(&mut buf, &info, &TypeInfo::Empty, &["tile 3", ""], &[42u8])
+            .expect("encode");
+        assert_eq!(
+            len,
+            encoded_len(&info, &TypeInfo::Empty, &["tile 3", ""]).expect("len")
+        );
+        assert!(buf[..len].starts_with(b"P7\n# tile 3\n# \nWIDTH 1\n"));
+        let (image, _) = Image::decode(&buf[..len]).expect("decode");
+        assert_eq!(image.samples(), [42]);
+
+        let info = Info::new_pgm(EncodingType::Plain, 1, 1, 255).expect("pgm");
+        let len = encode_into(&mut buf, &info, &TypeInfo::Empty, &["a"], &[5u8]).expect("encode");
+        assert_eq!(&buf[..len], b"P2\n# a\n1 1 255\n5\n");
+
+        let result = encode_into(&mut buf, &info, &TypeInfo::Empty, &["a\nb"], &[5u8]);
+        assert!(matches!(result, Err(NetpbmError::InvalidArgument { .. })));
+    }
+
+    #[test]
+    fn test_errors() {
+        let info = Info::new_pgm(EncodingType::Raw, 2, 1, 255).expect("pgm");
+        let mut small = [0; 12];
+        let result = encode_into(&mut small, &info, &TypeInfo::Empty, &[], &[7u8, 9]);
+        assert!(matches!(result, Err(NetpbmError::IOOperationFailed { .. })));
+        assert_eq!(small, [0; 12]);
+
+        let mut buf = [0; 13];
+        let result = encode_into(&mut buf, &info, &TypeInfo::Empty, &[], &[7u16, 256]);
+        assert!(matches!(
+            result,
+            Err(NetpbmError::OversizedSample { offset: 1, .. })
+        ));
+        assert_eq!(
+            encode_into(&mut buf, &info, &TypeInfo::Empty, &[], &[7u8, 9]),
+            Ok(13)
+        );
+        assert_eq!(&buf, b"P5\n2 1 255\n\x07\x09");
+
+        // Sizes that overflow are errors, not panics or wrapped values.
+        let info = Info::new_pgm(EncodingType::Raw, u32::MAX, u32::MAX, 65535).expect("pgm");
+        assert!(matches!(
+            encoded_len(&info, &TypeInfo::Empty, &[]),
+            Err(NetpbmError::InvalidArgument { .. })
+        ));
+        let info = Info::new_pgm(EncodingType::Raw, 65536, 65536, 255).expect("pgm");
+        let result = encode_into(&mut buf, &info, &TypeInfo::Empty, &[], &[7u8, 9]);
+        assert!(matches!(
+            result,
+            Err(NetpbmError::MalformedInitArray { .. })
+        ));
+
+        let info = Info::new_pfm(1, 1, 1).expect("pfm");
+        assert!(encoded_len(&info, &TypeInfo::Empty, &[]).is_err());
+    }
+}
@@ -1,5 +1,8 @@
 use crate::{EncodingType, NetpbmError};
-use std::fmt;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
 
 /// netpbm supports 4 types of images: PBM, PGM, PPM, and PAM.
 /// PBM, PGM, and PPM are further divided into their `raw` and
@@ -8,12 +11,17 @@ use std::fmt;
 /// There is an additional cateogry, PNM, which refers to
 /// any of PBM, PGM, or PPM.
 ///
+/// PFM (Portable Float Map) is a floating point relative of
+/// PPM and PGM. It is not part of the netpbm spec proper, but
+/// is recognized by netpbm tools.
+///
 /// netpbm files consist of a sequence of netpbm images.
 /// Each image has a header, sample data, whitespace, and
 /// optional comments in the header and before the sample
 /// data.
 ///
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum NetpbmFormat {
     PBMRaw,
     PBMPlain,
@@ -22,6 +30,8 @@ pub enum NetpbmFormat {
     PPMRaw,
     PPMPlain,
     PAM,
+    PFMColor,
+    PFMGray,
 }
 
 impl NetpbmFormat {
@@ -34,6 +44,12 @@ impl NetpbmFormat {
         )
     }
 
+    /// PFM (Portable Float Map) stores floating point samples.
+    pub fn is_pfm(&self) -> bool {
+        use NetpbmFormat::*;
+        matches!(self, PFMColor | PFMGray)
+    }
+
     /// Get the magic number associated with the format.
     pub fn magic(&self) -> MagicNumber {
         use MagicNumber::*;
@@ -46,6 +62,8 @@ impl NetpbmFormat {
             PGMRaw => P5,
             PPMRaw => P6,
             PAM => P7,
+            PFMColor => PF,
+            PFMGray => Pf,
         }
     }
 }
@@ -56,7 +74,10 @@ impl NetpbmFormat {
 /// netpbm magic number consists of the two bytes
 /// `PN`, where N is a natural number represented in ASCII.
 ///
+/// PFM uses `PF` for color images and `Pf` for grayscale images.
+///
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MagicNumber {
     /// PBM Plain
     P1,
@@ -72,6 +93,10 @@ pub enum MagicNumber {
     P6,
     /// PAM
     P7,
+    /// PFM Color
+    PF,
+    /// PFM Grayscale
+    Pf,
 }
 
 impl MagicNumber {
@@ -85,6 +110,8 @@ impl MagicNumber {
             Self::P5 => *b"P5",
             Self::P6 => *b"P6",
             Self::P7 => *b"P7",
+            Self::PF => *b"PF",
+            Self::Pf => *b"Pf",
         }
     }
 
@@ -98,6 +125,8 @@ impl MagicNumber {
             b"P5" => Some(Self::P5),
             b"P6" => Some(Self::P6),
             b"P7" => Some(Self::P7),
+            b"PF" => Some(Self::PF),
+            b"Pf" => Some(Self::Pf),
             _ => None,
         }
     }
@@ -123,6 +152,8 @@ impl fmt::Display for MagicNumber {
 /// The bit depth must be between 1 and 65535 inclusive.
 ///
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "u16", into = "u16"))]
 pub struct BitDepth(u16);
 
 impl BitDepth {
@@ -155,6 +186,20 @@ impl fmt::Display for BitDepth {
     }
 }
 
+impl TryFrom<u16> for BitDepth {
+    type Error = NetpbmError;
+
+    fn try_from(value: u16) -> Result<Self, NetpbmError> {
+        Self::new(value)
+    }
+}
+
+impl From<BitDepth> for u16 {
+    fn from(value: BitDepth) -> Self {
+        value.0
+    }
+}
+
 /// Image dimension field.
 ///
 /// Image dimensions in netpbm must be positive integers. There is
@@ -162,6 +207,8 @@ impl fmt::Display for BitDepth {
 /// bound is used.
 ///
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "u32", into = "u32"))]
 pub struct ImageDim(u32);
 
 impl ImageDim {
@@ -186,11 +233,27 @@ impl fmt::Display for ImageDim {
     }
 }
 
+impl TryFrom<u32> for ImageDim {
+    type Error = NetpbmError;
+
+    fn try_from(value: u32) -> Result<Self, NetpbmError> {
+        Self::new(value)
+    }
+}
+
+impl From<ImageDim> for u32 {
+    fn from(value: ImageDim) -> Self {
+        value.0
+    }
+}
+
 /// Channel depth field.
 ///
 /// The number of channels in a PAM image must be positive.
 ///
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "u32", into = "u32"))]
 pub struct ChannelDepth(u32);
 
 impl ChannelDepth {
@@ -215,7 +278,29 @@ impl fmt::Display for ChannelDepth {
     }
 }
 
+impl TryFrom<u32> for ChannelDepth {
+    type Error = NetpbmError;
+
+    fn try_from(value: u32) -> Result<Self, NetpbmError> {
+        Self::new(value)
+    }
+}
+
+impl From<ChannelDepth> for u32 {
+    fn from(value: ChannelDepth) -> Self {
+        value.0
+    }
+}
+
 pub mod decode {
+    use super::{Info, MagicNumber, NetpbmFormat, TypeInfo};
+    use crate::{EncodingType, NetpbmError};
+    use alloc::format;
+    use alloc::string::ToString;
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use core::str::FromStr;
+
     const SPACE: u8 = b' ';
     const TAB: u8 = b'\t';
     const LF: u8 = b'\n';
@@ -228,6 +313,467 @@ pub mod decode {
     pub fn is_whitespace(byte: u8) -> bool {
         matches!(byte, SPACE | TAB | LF | VT | FF | CR)
     }
+
+    /// Byte scanner over an encoded netpbm image.
+    ///
+    /// The scanner tracks its offset into the buffer so that
+    /// decoding errors can report where they occurred.
+    ///
+    #[derive(Debug, Clone)]
+    pub struct Scanner<'a> {
+        buf: &'a [u8],
+        offset: usize,
+    }
+
+    impl<'a> Scanner<'a> {
+        /// Create a new scanner positioned at the start of the buffer.
+        pub fn new(buf: &'a [u8]) -> Self {
+            Scanner { buf, offset: 0 }
+        }
+
+        /// Get the current byte offset into the buffer.
+        pub fn offset(&self) -> usize {
+            self.offset
+        }
+
+        /// Get the bytes that have not been consumed yet.
+        pub fn remaining(&self) -> &'a [u8] {
+            &self.buf[self.offset..]
+        }
+
+        /// Determine whether every byte has been consumed.
+        pub fn is_empty(&self) -> bool {
+            self.offset >= self.buf.len()
+        }
+
+        /// Read the two magic number bytes.
+        pub fn read_magic(&mut self) -> Result<[u8; 2], NetpbmError> {
+            let bytes = self.take(2)?;
+            Ok([bytes[0], bytes[1]])
+        }
+
+        /// Skip whitespace and comments.
+        ///
+        /// A comment starts with `#` and runs until the next CR or LF.
+        ///
+        pub fn skip_whitespace(&mut self) {
+            while let Some(&byte) = self.buf.get(self.offset) {
+                if byte == b'#' {
+                    while let Some(&byte) = self.buf.get(self.offset) {
+                        if byte == LF || byte == CR {
+                            break;
+                        }
+                        self.offset += 1;
+                    }
+                } else if is_whitespace(byte) {
+                    self.offset += 1;
+                } else {
+                    break;
+                }
+            }
+        }
+
+        /// Read the next whitespace delimited token.
+        ///
+        /// Leading whitespace and comments are skipped.
+        ///
+        pub fn read_token(&mut self) -> Result<&'a str, NetpbmError> {
+            self.skip_whitespace();
+            let start = self.offset;
+            while let Some(&byte) = self.buf.get(self.offset) {
+                if is_whitespace(byte) || byte == b'#' {
+                    break;
+                }
+                self.offset += 1;
+            }
+
+            if start == self.offset {
+                return Err(NetpbmError::UnexpectedEof { offset: start });
+            }
+
+            core::str::from_utf8(&self.buf[start..self.offset]).map_err(|_| {
+                NetpbmError::MalformedHeader {
+                    offset: start,
+                    info: "Token is not valid ASCII".to_string(),
+                }
+            })
+        }
+
+        /// Read the next token as a decimal number.
+        pub fn read_decimal<T: FromStr>(&mut self) -> Result<T, NetpbmError> {
+            let token = self.read_token()?;
+            token.parse().map_err(|_| NetpbmError::MalformedHeader {
+                offset: self.offset - token.len(),
+                info: format!("Expected a decimal number, found {:?}", token),
+            })
+        }
+
+        /// Read the single whitespace character that separates
+        /// the header from the raster.
+        pub fn read_separator(&mut self) -> Result<(), NetpbmError> {
+            match self.buf.get(self.offset) {
+                Some(&byte) if is_whitespace(byte) => {
+                    self.offset += 1;
+                    Ok(())
+                }
+                Some(_) => Err(NetpbmError::MalformedHeader {
+                    offset: self.offset,
+                    info: "Expected whitespace before the raster".to_string(),
+                }),
+                None => Err(NetpbmError::UnexpectedEof {
+                    offset: self.offset,
+                }),
+            }
+        }
+
+        /// Read the next line without its LF terminator.
+        pub fn read_line(&mut self) -> Result<&'a str, NetpbmError> {
+            let start = self.offset;
+            let len = self.remaining().iter().position(|&b| b == LF).ok_or(
+                NetpbmError::UnexpectedEof {
+                    offset: self.buf.len(),
+                },
+            )?;
+            let line = self.take(len)?;
+            self.offset += 1;
+
+            core::str::from_utf8(line).map_err(|_| NetpbmError::MalformedHeader {
+                offset: start,
+                info: "Line is not valid ASCII".to_string(),
+            })
+        }
+
+        /// Take the next `len` bytes.
+        pub fn take(&mut self, len: usize) -> Result<&'a [u8], NetpbmError> {
+            let end = self
+                .offset
+                .checked_add(len)
+                .filter(|&end| end <= self.buf.len())
+                .ok_or(NetpbmError::UnexpectedEof {
+                    offset: self.buf.len(),
+                })?;
+            let bytes = &self.buf[self.offset..end];
+            self.offset = end;
+            Ok(bytes)
+        }
+    }
+
+    /// Read the header of a PBM, PGM, PPM, or PAM image.
+    ///
+    /// The scanner must be positioned at the magic number. On success,
+    /// the scanner is positioned at the start of the raster.
+    ///
+    pub fn read_header(scanner: &mut Scanner) -> Result<(Info, TypeInfo), NetpbmError> {
+        let offset = scanner.offset();
+        let magic_number = scanner.read_magic()?;
+        let magic_number =
+            MagicNumber::from_bytes(&magic_number).ok_or(NetpbmError::MalformedHeader {
+                offset,
+                info: format!(
+                    "Unexpected bytes at magic number position: {:?}",
+                    magic_number
+                ),
+            })?;
+
+        let info = match magic_number {
+            MagicNumber::P1 | MagicNumber::P4 => {
+                let encoding = encoding_of(magic_number);
+                let width = scanner.read_decimal()?;
+                let height = scanner.read_decimal()?;
+                Info::new_pbm(encoding, width, height)?
+            }
+            MagicNumber::P2 | MagicNumber::P5 => {
+                let encoding = encoding_of(magic_number);
+                let width = scanner.read_decimal()?;
+                let height = scanner.read_decimal()?;
+                let bit_depth = scanner.read_decimal()?;
+                Info::new_pgm(encoding, width, height, bit_depth)?
+            }
+            MagicNumber::P3 | MagicNumber::P6 => {
+                let encoding = encoding_of(magic_number);
+                let width = scanner.read_decimal()?;
+                let height = scanner.read_decimal()?;
+                let bit_depth = scanner.read_decimal()?;
+                Info::new_ppm(encoding, width, height, bit_depth)?
+            }
+            MagicNumber::P7 => return read_pam_header(scanner),
+            MagicNumber::PF | MagicNumber::Pf => {
+                return Err(NetpbmError::MalformedHeader {
+                    offset,
+                    info: format!("{} images have floating point samples", magic_number),
+                })
+            }
+        };
+
+        // Plain rasters may be preceded by any amount of whitespace.
+        if info.encoding == EncodingType::Raw {
+            scanner.read_separator()?;
+        }
+
+        Ok((info, TypeInfo::Empty))
+    }
+
+    /// Read the remainder of a PAM header after the magic number.
+    fn read_pam_header(scanner: &mut Scanner) -> Result<(Info, TypeInfo), NetpbmError> {
+        let mut width = None;
+        let mut height = None;
+        let mut depth = None;
+        let mut maxval = None;
+        let mut type_info = Vec::new();
+
+        loop {
+            let offset = scanner.offset();
+            let line = scanner
+                .read_line()?
+                .trim_matches(|c: char| c.is_ascii_whitespace());
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once(|c: char| c.is_ascii_whitespace())
+                .map(|(key, value)| (key, value.trim_start()))
+                .unwrap_or((line, ""));
+
+            let parse = |value: &str| {
+                value
+                    .parse::<u32>()
+                    .map_err(|_| NetpbmError::MalformedHeader {
+                        offset,
+                        info: format!("Expected a decimal number for {}, found {:?}", key, value),
+                    })
+            };
+
+            match key {
+                "ENDHDR" => break,
+                "WIDTH" => width = Some(parse(value)?),
+                "HEIGHT" => height = Some(parse(value)?),
+                "DEPTH" => depth = Some(parse(value)?),
+                "MAXVAL" => {
+                    maxval = Some(u16::try_from(parse(value)?).map_err(|_| {
+                        NetpbmError::MalformedHeader {
+                            offset,
+                            info: format!("MAXVAL {} is out of range", value),
+                        }
+                    })?)
+                }
+                "TUPLTYPE" => type_info.push(value.to_string()),
+                _ => {
+                    return Err(NetpbmError::MalformedHeader {
+                        offset,
+                        info: format!("Unrecognized PAM header line {:?}", line),
+                    })
+                }
+            }
+        }
+
+        let missing = |field: &str| NetpbmError::MalformedHeader {
+            offset: scanner.offset(),
+            info: format!("PAM header is missing {}", field),
+        };
+        let info = Info::new_pam(
+            width.ok_or_else(|| missing("WIDTH"))?,
+            height.ok_or_else(|| missing("HEIGHT"))?,
+            maxval.ok_or_else(|| missing("MAXVAL"))?,
+            depth.ok_or_else(|| missing("DEPTH"))?,
+        )?;
+
+        let type_info = if type_info.is_empty() {
+            TypeInfo::Empty
+        } else {
+            TypeInfo::Info(type_info)
+        };
+
+        Ok((info, type_info))
+    }
+
+    /// Read the raster of an image whose header has been read.
+    ///
+    /// Samples are checked against the bit depth. PBM samples are
+    /// unpacked to one sample per pixel.
+    ///
+    pub fn read_samples(scanner: &mut Scanner, info: &Info) -> Result<Vec<u16>, NetpbmError> {
+        let width = info.width.value() as usize;
+        let len = info
+            .sample_count()
+            .ok_or_else(|| oversized_raster(scanner, info))?;
+        let maxval = info.bit_depth.value();
+
+        let oversized = |offset| NetpbmError::OversizedSample {
+            offset,
+            bit_depth: info.bit_depth,
+        };
+
+        // Cap the allocation by the available data in case the header
+        // claims a much larger image than was provided.
+        let mut samples = Vec::with_capacity(len.min(scanner.remaining().len().saturating_mul(8)));
+        match (&info.format, &info.encoding) {
+            (NetpbmFormat::PBMRaw, _) => {
+                // Each row is padded to a whole number of bytes.
+                let row_len = width.div_ceil(8);
+                let raster_len = info
+                    .raw_raster_len()
+                    .ok_or_else(|| oversized_raster(scanner, info))?;
+                let raster = scanner.take(raster_len)?;
+                for row in raster.chunks_exact(row_len) {
+                    samples.extend((0..width).map(|x| ((row[x / 8] >> (7 - x % 8)) & 1) as u16));
+                }
+            }
+            (NetpbmFormat::PBMPlain, _) => {
+                // Plain PBM bits need not be separated by whitespace.
+                for _ in 0..len {
+                    scanner.skip_whitespace();
+                    let offset = scanner.offset();
+                    match scanner.take(1)?[0] {
+                        b'0' => samples.push(0),
+                        b'1' => samples.push(1),
+                        byte => {
+                            return Err(NetpbmError::MalformedRaster {
+                                offset,
+                                info: format!("Expected a bit, found {:?}", byte as char),
+                            })
+                        }
+                    }
+                }
+            }
+            (_, EncodingType::Plain) => {
+                for _ in 0..len {
+                    scanner.skip_whitespace();
+                    let offset = scanner.offset();
+                    let token = scanner.read_token()?;
+                    let sample =
+                        token
+                            .parse::<u16>()
+                            .map_err(|_| NetpbmError::MalformedRaster {
+                                offset,
+                                info: format!("Expected a sample, found {:?}", token),
+                            })?;
+                    if sample > maxval {
+                        return Err(oversized(offset));
+                    }
+                    samples.push(sample);
+                }
+            }
+            (_, EncodingType::Raw) => {
+                let start = scanner.offset();
+                let raster_len = info
+                    .raw_raster_len()
+                    .ok_or_else(|| oversized_raster(scanner, info))?;
+                let raster = scanner.take(raster_len)?;
+                if info.bit_depth.is_multi_byte() {
+                    for (i, bytes) in raster.chunks_exact(2).enumerate() {
+                        // netpbm specifies that multi-byte samples are big-endian.
+                        let sample = u16::from_be_bytes([bytes[0], bytes[1]]);
+                        if sample > maxval {
+                            return Err(oversized(start + 2 * i));
+                        }
+                        samples.push(sample);
+                    }
+                } else {
+                    for (i, &byte) in raster.iter().enumerate() {
+                        if byte as u16 > maxval {
+                            return Err(oversized(start + i));
+                        }
+                        samples.push(byte as u16);
+                    }
+                }
+            }
+        }
+
+        Ok(samples)
+    }
+
+    /// Read the raster of a PBM image whose header has been read
+    /// as byte-packed rows.
+    ///
+    /// Each row is `ceil(width / 8)` bytes long, with the leftmost
+    /// pixel in the most significant bit. Padding bits are zero.
+    ///
+    pub fn read_packed_samples(scanner: &mut Scanner, info: &Info) -> Result<Vec<u8>, NetpbmError> {
+        let width = info.width.value() as usize;
+        let height = info.height.value() as usize;
+        let row_len = width.div_ceil(8);
+        let packed_len = info
+            .raw_raster_len()
+            .ok_or_else(|| oversized_raster(scanner, info))?;
+
+        // Mask for the bits of the last byte of each row that hold pixels.
+        let last_mask = 0xFFu8 << ((8 - width % 8) % 8);
+
+        match info.format {
+            NetpbmFormat::PBMRaw => {
+                let mut packed = scanner.take(packed_len)?.to_vec();
+                for row in packed.chunks_exact_mut(row_len) {
+                    row[row_len - 1] &= last_mask;
+                }
+                Ok(packed)
+            }
+            NetpbmFormat::PBMPlain => {
+                // Every bit takes at least one byte, so a truncated
+                // raster is rejected before allocating for the header.
+                if width.saturating_mul(height) > scanner.remaining().len() {
+                    return Err(NetpbmError::UnexpectedEof {
+                        offset: scanner.offset() + scanner.remaining().len(),
+                    });
+                }
+                let mut packed = vec![0; packed_len];
+                for row in packed.chunks_exact_mut(row_len) {
+                    for x in 0..width {
+                        scanner.skip_whitespace();
+                        let offset = scanner.offset();
+                        match scanner.take(1)?[0] {
+                            b'0' => {}
+                            b'1' => row[x / 8] |= 0x80 >> (x % 8),
+                            byte => {
+                                return Err(NetpbmError::MalformedRaster {
+                                    offset,
+                                    info: format!("Expected a bit, found {:?}", byte as char),
+                                })
+                            }
+                        }
+                    }
+                }
+                Ok(packed)
+            }
+            _ => Err(NetpbmError::IOOperationFailed {
+                info: format!(
+                    "{} images cannot be read as packed bits",
+                    info.format.magic()
+                ),
+            }),
+        }
+    }
+
+    /// Read one image from the start of the buffer.
+    ///
+    /// Returns the image metadata, samples, and the number of
+    /// bytes consumed.
+    ///
+    pub fn read_image(buf: &[u8]) -> Result<(Info, TypeInfo, Vec<u16>, usize), NetpbmError> {
+        let mut scanner = Scanner::new(buf);
+        let (info, type_info) = read_header(&mut scanner)?;
+        let samples = read_samples(&mut scanner, &info)?;
+        Ok((info, type_info, samples, scanner.offset()))
+    }
+
+    /// Error for a header whose raster does not fit in memory.
+    pub(crate) fn oversized_raster(scanner: &Scanner, info: &Info) -> NetpbmError {
+        NetpbmError::MalformedHeader {
+            offset: scanner.offset(),
+            info: format!(
+                "Image of {}x{} with {} channels is too large",
+                info.width, info.height, info.channels
+            ),
+        }
+    }
+
+    /// Get the encoding type associated with a PNM magic number.
+    fn encoding_of(magic_number: MagicNumber) -> EncodingType {
+        match magic_number {
+            MagicNumber::P1 | MagicNumber::P2 | MagicNumber::P3 => EncodingType::Plain,
+            _ => EncodingType::Raw,
+        }
+    }
 }
 
 /// Type info field.
@@ -238,13 +784,21 @@ pub mod decode {
 /// Type info is optional.
 ///
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TypeInfo {
     Info(Vec<String>),
     Empty,
 }
 
 /// Metadata used during encoding and decoding.
+///
+/// With the `serde` feature, deserialized values are validated
+/// like those of the constructors, and the format must agree with
+/// the other fields.
+///
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "InfoFields"))]
 pub struct Info {
     /// The image format.
     pub format: NetpbmFormat,
@@ -270,6 +824,60 @@ pub struct Info {
 
 type InfoRes = Result<Info, NetpbmError>;
 
+/// Unvalidated `Info` fields, deserialized before `Info` is built
+/// with its constructors.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct InfoFields {
+    format: NetpbmFormat,
+    encoding: EncodingType,
+    width: u32,
+    height: u32,
+    bit_depth: u16,
+    channels: u32,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<InfoFields> for Info {
+    type Error = NetpbmError;
+
+    fn try_from(fields: InfoFields) -> InfoRes {
+        use NetpbmFormat::*;
+        let InfoFields {
+            format,
+            encoding,
+            width,
+            height,
+            bit_depth,
+            channels,
+        } = fields;
+        let info = match format {
+            PBMRaw | PBMPlain => Info::new_pbm(encoding, width, height)?,
+            PGMRaw | PGMPlain => Info::new_pgm(encoding, width, height, bit_depth)?,
+            PPMRaw | PPMPlain => Info::new_ppm(encoding, width, height, bit_depth)?,
+            PAM => Info::new_pam(width, height, bit_depth, channels)?,
+            PFMColor | PFMGray => Info::new_pfm(width, height, channels)?,
+        };
+
+        // The constructors derive the format from the encoding and
+        // fix the bit depth or channels of some formats.
+        if info.format != format
+            || info.encoding != encoding
+            || info.bit_depth.value() != bit_depth
+            || info.channels.value() != channels
+        {
+            return Err(NetpbmError::InvalidArgument {
+                info: format!(
+                    "{:?} images cannot have {:?} encoding, bit depth {} and {} channels",
+                    format, encoding, bit_depth, channels
+                ),
+            });
+        }
+
+        Ok(info)
+    }
+}
+
 impl Info {
     /// Create a new info struct for PBM images.
     pub fn new_pbm(encoding: EncodingType, width: u32, height: u32) -> InfoRes {
@@ -334,6 +942,29 @@ impl Info {
         })
     }
 
+    /// Create a new info struct for PFM images.
+    ///
+    /// PFM images have either 1 (grayscale) or 3 (color) channels.
+    /// PFM samples are floating point and have no maxval, so the
+    /// bit depth is set to 1 and carries no meaning.
+    ///
+    pub fn new_pfm(width: u32, height: u32, channels: u32) -> InfoRes {
+        let format = match channels {
+            1 => NetpbmFormat::PFMGray,
+            3 => NetpbmFormat::PFMColor,
+            value => return Err(NetpbmError::InvalidChannelDepth { value }),
+        };
+
+        Ok(Info {
+            encoding: EncodingType::Raw,
+            format,
+            width: ImageDim::new(width)?,
+            height: ImageDim::new(height)?,
+            bit_depth: BitDepth::new(1).expect("Float map bit depth"),
+            channels: ChannelDepth::new(channels)?,
+        })
+    }
+
     /// Validate that u8 sample values agree with header info.
     pub fn validate_u8_samples(&self, samples: &[u8]) -> Result<(), NetpbmError> {
         // Check that the sample size is correct.
@@ -370,10 +1001,56 @@ impl Info {
         Ok(())
     }
 
+    /// Validate that a tuple has one sample per channel and that its
+    /// samples agree with the bit depth.
+    pub fn validate_tuple(&self, tuple: &[u16]) -> Result<(), NetpbmError> {
+        if tuple.len() != self.channels.value() as usize {
+            return Err(NetpbmError::InvalidArgument {
+                info: format!(
+                    "Tuple has {} samples, expected {}",
+                    tuple.len(),
+                    self.channels
+                ),
+            });
+        }
+        if let Some(offset) = tuple.iter().position(|&s| s > self.bit_depth.value()) {
+            return Err(NetpbmError::OversizedSample {
+                offset,
+                bit_depth: self.bit_depth,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Get the number of samples of the image, `width * height *
+    /// channels`, or `None` if it does not fit in a `usize`.
+    pub fn sample_count(&self) -> Option<usize> {
+        (self.width.value() as usize)
+            .checked_mul(self.height.value() as usize)?
+            .checked_mul(self.channels.value() as usize)
+    }
+
+    /// Get the byte length of the raster with `raw` encoding, or
+    /// `None` if it does not fit in a `usize`.
+    ///
+    /// PBM rows are packed 8 pixels to a byte. PFM samples take 4
+    /// bytes, and other samples 1 or 2 depending on the bit depth.
+    ///
+    pub(crate) fn raw_raster_len(&self) -> Option<usize> {
+        match self.format {
+            NetpbmFormat::PBMRaw | NetpbmFormat::PBMPlain => (self.width.value() as usize)
+                .div_ceil(8)
+                .checked_mul(self.height.value() as usize),
+            _ if self.format.is_pfm() => self.sample_count()?.checked_mul(4),
+            _ if self.bit_depth.is_multi_byte() => self.sample_count()?.checked_mul(2),
+            _ => self.sample_count(),
+        }
+    }
+
     /// Validate that the number of samples corresponds to the image dimensions.
-    fn validate_sample_size(&self, samples_len: usize) -> Result<(), NetpbmError> {
-        let expected_samples = self.width.value() * self.height.value() * self.channels.value();
-        if expected_samples != samples_len as u32 {
+    pub(crate) fn validate_sample_size(&self, samples_len: usize) -> Result<(), NetpbmError> {
+        if self.sample_count() != Some(samples_len) {
             return Err(NetpbmError::MalformedInitArray {
                 data_size: samples_len,
                 width: self.width,
@@ -385,7 +1062,7 @@ impl Info {
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
 
@@ -412,6 +1089,45 @@ mod tests {
         assert!(ChannelDepth::new(100).is_ok());
     }
 
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let info = Info::new_pam(3, 2, 255, 4).expect("pam");
+        let json = serde_json::to_string(&info).expect("serialize");
+        assert_eq!(
+            json,
+            r#"{"format":"PAM","encoding":"Raw","width":3,"height":2,"bit_depth":255,"channels":4}"#
+        );
+        assert_eq!(serde_json::from_str::<Info>(&json).ok(), Some(info));
+
+        let zero = json.replace(r#""width":3"#, r#""width":0"#);
+        let err = serde_json::from_str::<Info>(&zero).expect_err("zero width");
+        assert!(err.to_string().contains("Image dimension 0"));
+        assert!(serde_json::from_str::<BitDepth>("0").is_err());
+
+        // Fields must agree with the format, as with the constructors.
+        let mismatched = [
+            r#"{"format":"PBMRaw","encoding":"Plain","width":2,"height":1,"bit_depth":1,"channels":1}"#,
+            r#"{"format":"PBMRaw","encoding":"Raw","width":2,"height":1,"bit_depth":255,"channels":3}"#,
+            r#"{"format":"PPMPlain","encoding":"Plain","width":2,"height":1,"bit_depth":255,"channels":1}"#,
+            r#"{"format":"PFMGray","encoding":"Raw","width":2,"height":1,"bit_depth":1,"channels":3}"#,
+        ];
+        for json in mismatched {
+            assert!(serde_json::from_str::<Info>(json).is_err(), "{}", json);
+        }
+
+        let type_info = TypeInfo::Info(vec!["RGB_ALPHA".to_string()]);
+        let json = serde_json::to_string(&type_info).expect("serialize");
+        assert_eq!(
+            serde_json::from_str::<TypeInfo>(&json).ok(),
+            Some(type_info)
+        );
+        assert_eq!(
+            serde_json::from_str::<MagicNumber>(r#""Pf""#).ok(),
+            Some(MagicNumber::Pf)
+        );
+    }
+
     #[test]
     fn test_magic() {
         use MagicNumber::*;
@@ -423,6 +1139,10 @@ mod tests {
         assert_eq!(PPMRaw.magic(), P6);
         assert_eq!(PPMPlain.magic(), P3);
         assert_eq!(PAM.magic(), P7);
+        assert_eq!(PFMColor.magic(), PF);
+        assert_eq!(PFMGray.magic(), Pf);
+        assert_eq!(MagicNumber::from_bytes(b"Pf"), Some(Pf));
+        assert_eq!(MagicNumber::from_bytes(b"PG"), None);
     }
 
     #[test]
@@ -435,6 +1155,55 @@ mod tests {
         assert!(PPMRaw.is_pnm());
         assert!(PPMPlain.is_pnm());
         assert!(!PAM.is_pnm());
+        assert!(!PFMColor.is_pnm());
+        assert!(PFMGray.is_pfm());
+    }
+
+    #[test]
+    fn test_oversized_header() {
+        let data = b"P7\nWIDTH 2147483648\nHEIGHT 2147483648\nDEPTH 4\nMAXVAL 255\nENDHDR\n";
+        assert!(matches!(
+            decode::read_image(data),
+            Err(NetpbmError::MalformedHeader { offset, .. }) if offset == data.len()
+        ));
+        assert!(matches!(
+            decode::read_image(b"P5 4294967295 4294967295 65535 "),
+            Err(NetpbmError::MalformedHeader { .. })
+        ));
+        assert!(matches!(
+            decode::read_image(b"P1 4294967295 4294967295 0"),
+            Err(NetpbmError::UnexpectedEof { .. })
+        ));
+        assert_eq!(
+            Info::new_pam(u32::MAX, u32::MAX, 255, u32::MAX)
+                .expect("pam")
+                .sample_count(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_scanner() {
+        use decode::Scanner;
+        let mut scanner = Scanner::new(b"P5 # comment\n 12\t34\n#x\n255\nabc");
+        assert_eq!(scanner.read_magic(), Ok(*b"P5"));
+        assert_eq!(scanner.read_decimal::<u32>(), Ok(12));
+        assert_eq!(scanner.read_decimal::<u32>(), Ok(34));
+        assert_eq!(scanner.read_decimal::<u16>(), Ok(255));
+        assert_eq!(scanner.read_separator(), Ok(()));
+        assert_eq!(scanner.take(3), Ok(&b"abc"[..]));
+        assert!(scanner.is_empty());
+        assert_eq!(
+            scanner.read_token(),
+            Err(NetpbmError::UnexpectedEof { offset: 30 })
+        );
+
+        let mut scanner = Scanner::new(b"P5 x");
+        scanner.read_magic().expect("magic");
+        assert!(matches!(
+            scanner.read_decimal::<u32>(),
+            Err(NetpbmError::MalformedHeader { offset: 3, .. })
+        ));
     }
 
     #[test]
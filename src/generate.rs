@@ -0,0 +1,379 @@
+//! Procedural image generators.
+//!
+//! These functions create synthetic images, similar to netpbm's
+//! `pbmmake`, `pgmramp`, `ppmpat`, `pgmnoise`, and `pamseq`. Each
+//! generator takes the `Info` of the image to create, so images
+//! can have any format, size, bit depth, and channel count, and
+//! can be written with any encoder. Tuples must have one sample per
+//! channel, with no sample larger than the bit depth.
+//!
+//! Noise generators take a seed, and always create the same image
+//! for the same seed.
+//!
+
+use crate::image::Image;
+use crate::layout::Direction;
+use crate::math;
+use crate::{BitDepth, ChannelDepth, Info, NetpbmError, NetpbmFormat, TypeInfo};
+use alloc::format;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+/// Gradient shape of a ramp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Ramp {
+    /// Ramp from the left edge to the right edge.
+    Horizontal,
+
+    /// Ramp from the top edge to the bottom edge.
+    Vertical,
+
+    /// Ramp from the top-left corner to the bottom-right corner.
+    Diagonal,
+
+    /// Ramp from the center to the corners.
+    Radial,
+}
+
+/// Create an image filled with one tuple.
+pub fn solid(info: Info, color: &[u16]) -> Result<Image, NetpbmError> {
+    info.validate_tuple(color)?;
+    generate(info, |_, _, tuple| tuple.copy_from_slice(color))
+}
+
+/// Create a gradient from the tuple `from` to the tuple `to`.
+pub fn ramp(info: Info, shape: Ramp, from: &[u16], to: &[u16]) -> Result<Image, NetpbmError> {
+    info.validate_tuple(from)?;
+    info.validate_tuple(to)?;
+
+    let (width, height) = (info.width.value() as f64, info.height.value() as f64);
+    let (cx, cy) = ((width - 1.0) / 2.0, (height - 1.0) / 2.0);
+    let radius = math::hypot(cx, cy);
+    let position = |x: f64, y: f64| -> f64 {
+        let (t, length) = match shape {
+            Ramp::Horizontal => (x, width - 1.0),
+            Ramp::Vertical => (y, height - 1.0),
+            Ramp::Diagonal => (x + y, width + height - 2.0),
+            Ramp::Radial => (math::hypot(x - cx, y - cy), radius),
+        };
+        if length > 0.0 {
+            t / length
+        } else {
+            0.0
+        }
+    };
+
+    generate(info, |x, y, tuple| {
+        let t = position(x as f64, y as f64);
+        for ((sample, &a), &b) in tuple.iter_mut().zip(from).zip(to) {
+            *sample = math::round(a as f64 + (b as f64 - a as f64) * t) as u16;
+        }
+    })
+}
+
+/// Create a checkerboard of `size` by `size` squares, with the
+/// top-left square filled with `first`.
+pub fn checkerboard(
+    info: Info,
+    size: u32,
+    first: &[u16],
+    second: &[u16],
+) -> Result<Image, NetpbmError> {
+    info.validate_tuple(first)?;
+    info.validate_tuple(second)?;
+    if size == 0 {
+        return Err(NetpbmError::InvalidArgument {
+            info: "Checkerboard squares must be at least 1 pixel".to_string(),
+        });
+    }
+
+    generate(info, |x, y, tuple| {
+        let square = x / size + y / size;
+        let color = if square.is_multiple_of(2) {
+            first
+        } else {
+            second
+        };
+        tuple.copy_from_slice(color);
+    })
+}
+
+/// Create stripes of `size` pixels that cycle through `colors`.
+///
+/// `Direction::Horizontal` places stripes left to right, and
+/// `Direction::Vertical` places them top to bottom.
+///
+pub fn stripes(
+    info: Info,
+    direction: Direction,
+    size: u32,
+    colors: &[&[u16]],
+) -> Result<Image, NetpbmError> {
+    for color in colors {
+        info.validate_tuple(color)?;
+    }
+    if size == 0 || colors.is_empty() {
+        return Err(NetpbmError::InvalidArgument {
+            info: "Stripes need at least one color and 1 pixel".to_string(),
+        });
+    }
+
+    generate(info, |x, y, tuple| {
+        let position = match direction {
+            Direction::Horizontal => x,
+            Direction::Vertical => y,
+        };
+        let stripe = (position / size) as usize % colors.len();
+        tuple.copy_from_slice(colors[stripe]);
+    })
+}
+
+/// Create a color bar test pattern.
+///
+/// The image has eight vertical bars of equal width: white, yellow,
+/// cyan, green, magenta, red, blue, and black. Images with one
+/// channel get the Rec. 601 luminance of each bar. Other channels
+/// than the first three are set to the bit depth, so alpha channels
+/// are opaque.
+///
+pub fn color_bars(info: Info) -> Result<Image, NetpbmError> {
+    const BARS: [[u16; 3]; 8] = [
+        [1, 1, 1],
+        [1, 1, 0],
+        [0, 1, 1],
+        [0, 1, 0],
+        [1, 0, 1],
+        [1, 0, 0],
+        [0, 0, 1],
+        [0, 0, 0],
+    ];
+
+    let channels = info.channels.value() as usize;
+    if channels == 2 {
+        return Err(NetpbmError::InvalidArgument {
+            info: "Color bars need 1 or at least 3 channels".to_string(),
+        });
+    }
+    let bit_depth = info.bit_depth.value();
+    let width = info.width.value() as u64;
+    let bitmap = matches!(info.format, NetpbmFormat::PBMRaw | NetpbmFormat::PBMPlain);
+
+    generate(info, |x, _, tuple| {
+        let bar = BARS[(x as u64 * BARS.len() as u64 / width) as usize];
+        let rgb = bar.map(|s| s * bit_depth);
+        if channels == 1 {
+            let luma = 0.299 * rgb[0] as f64 + 0.587 * rgb[1] as f64 + 0.114 * rgb[2] as f64;
+            let luma = math::round(luma) as u16;
+
+            // PBM samples are 1 for black.
+            tuple[0] = if bitmap { bit_depth - luma } else { luma };
+        } else {
+            tuple[..3].copy_from_slice(&rgb);
+            tuple[3..].fill(bit_depth);
+        }
+    })
+}
+
+/// Create an image of uniformly distributed samples between 0 and
+/// the bit depth.
+pub fn uniform_noise(info: Info, seed: u64) -> Result<Image, NetpbmError> {
+    let mut rng = Rng::new(seed);
+    let range = info.bit_depth.value() as u64 + 1;
+    generate(info, |_, _, tuple| {
+        for sample in tuple {
+            *sample = (rng.next() % range) as u16;
+        }
+    })
+}
+
+/// Create an image of normally distributed samples with the given
+/// mean and standard deviation, clamped to the bit depth.
+pub fn gaussian_noise(info: Info, mean: f64, sigma: f64, seed: u64) -> Result<Image, NetpbmError> {
+    if !mean.is_finite() || !sigma.is_finite() || sigma < 0.0 {
+        return Err(NetpbmError::InvalidArgument {
+            info: format!(
+                "Mean {} and standard deviation {} should be finite and non-negative",
+                mean, sigma
+            ),
+        });
+    }
+
+    let mut rng = Rng::new(seed);
+    let bit_depth = info.bit_depth.value() as f64;
+    generate(info, |_, _, tuple| {
+        for sample in tuple {
+            let value = mean + sigma * rng.next_gaussian();
+            *sample = math::round(value).clamp(0.0, bit_depth) as u16;
+        }
+    })
+}
+
+/// Create a PAM image with one row holding every tuple of `channels`
+/// samples up to `bit_depth`, like netpbm's `pamseq`.
+///
+/// Tuples are in ascending order, with the last channel varying
+/// fastest. The image is `(bit_depth + 1) ^ channels` pixels wide.
+///
+pub fn sequence(bit_depth: u16, channels: u32) -> Result<Image, NetpbmError> {
+    let values = BitDepth::new(bit_depth)?.value() as u32 + 1;
+    let width = values
+        .checked_pow(ChannelDepth::new(channels)?.value())
+        .ok_or_else(|| NetpbmError::InvalidArgument {
+            info: format!(
+                "A sequence of {} channels up to {} is too wide",
+                channels, bit_depth
+            ),
+        })?;
+
+    let info = Info::new_pam(width, 1, bit_depth, channels)?;
+    generate(info, |x, _, tuple| {
+        let mut rest = x;
+        for sample in tuple.iter_mut().rev() {
+            *sample = (rest % values) as u16;
+            rest /= values;
+        }
+    })
+}
+
+/// Create an image by setting every tuple with `f`.
+fn generate<F>(info: Info, mut f: F) -> Result<Image, NetpbmError>
+where
+    F: FnMut(u32, u32, &mut [u16]),
+{
+    if info.format.is_pfm() {
+        return Err(NetpbmError::InvalidArgument {
+            info: format!("{} images have floating point samples", info.format.magic()),
+        });
+    }
+
+    let (width, height) = (info.width.value(), info.height.value());
+    let channels = info.channels.value() as usize;
+    let too_large = || NetpbmError::InvalidArgument {
+        info: format!(
+            "Image of {}x{} with {} channels is too large",
+            width, height, channels
+        ),
+    };
+    let len = info.sample_count().ok_or_else(too_large)?;
+    let mut samples = Vec::new();
+    samples.try_reserve_exact(len).map_err(|_| too_large())?;
+    samples.resize(len, 0);
+    for (i, tuple) in samples.chunks_exact_mut(channels).enumerate() {
+        let (x, y) = (i % width as usize, i / width as usize);
+        f(x as u32, y as u32, tuple);
+    }
+    Ok(Image::from_parts(info, TypeInfo::Empty, samples))
+}
+
+/// A SplitMix64 random number generator.
+struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Get a uniform number in `(0, 1]`.
+    fn next_unit(&mut self) -> f64 {
+        ((self.next() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Get a standard normal number with the Box-Muller transform.
+    fn next_gaussian(&mut self) -> f64 {
+        let (u, v) = (self.next_unit(), self.next_unit());
+        math::sqrt(-2.0 * math::ln(u)) * math::cos(2.0 * core::f64::consts::PI * v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::pgm_info;
+    use crate::EncodingType;
+
+    #[test]
+    fn test_solid_and_ramps() {
+        let info = Info::new_ppm(EncodingType::Plain, 2, 2, 15).expect("ppm");
+        let image = solid(info.clone(), &[1, 2, 3]).expect("solid");
+        assert_eq!(image.samples(), [1, 2, 3].repeat(4));
+        assert!(solid(info, &[1, 2, 16]).is_err());
+
+        let image = ramp(pgm_info(5, 2, 100), Ramp::Horizontal, &[0], &[100]).expect("ramp");
+        assert_eq!(image.row(1), [0, 25, 50, 75, 100]);
+        let image = ramp(pgm_info(2, 3, 100), Ramp::Vertical, &[100], &[0]).expect("ramp");
+        assert_eq!(image.samples(), [100, 100, 50, 50, 0, 0]);
+        let image = ramp(pgm_info(3, 2, 30), Ramp::Diagonal, &[0], &[30]).expect("ramp");
+        assert_eq!(image.samples(), [0, 10, 20, 10, 20, 30]);
+        let image = ramp(pgm_info(3, 3, 10), Ramp::Radial, &[10], &[0]).expect("ramp");
+        assert_eq!(image.samples(), [0, 3, 0, 3, 10, 3, 0, 3, 0]);
+
+        let pfm = Info::new_pfm(1, 1, 1).expect("pfm");
+        assert!(solid(pfm, &[0]).is_err());
+
+        // The sample count overflows `usize`.
+        let huge = Info::new_pam(u32::MAX, u32::MAX, 255, 4).expect("pam");
+        assert!(matches!(
+            solid(huge, &[0; 4]),
+            Err(NetpbmError::InvalidArgument { .. })
+        ));
+    }
+
+    #[test]
+    fn test_patterns() {
+        let image = checkerboard(pgm_info(4, 3, 9), 2, &[9], &[0]).expect("checkerboard");
+        assert_eq!(image.samples(), [9, 9, 0, 0, 9, 9, 0, 0, 0, 0, 9, 9]);
+
+        let colors: [&[u16]; 3] = [&[1], &[2], &[3]];
+        let image = stripes(pgm_info(2, 4, 3), Direction::Vertical, 1, &colors).expect("stripes");
+        assert_eq!(image.samples(), [1, 1, 2, 2, 3, 3, 1, 1]);
+
+        let info = Info::new_pam(8, 1, 255, 4).expect("pam");
+        let image = color_bars(info).expect("bars");
+        assert_eq!(image.tuple(1, 0), [255, 255, 0, 255]);
+        assert_eq!(image.tuple(7, 0), [0, 0, 0, 255]);
+
+        let info = Info::new_pbm(EncodingType::Raw, 8, 1).expect("pbm");
+        let image = color_bars(info).expect("bars");
+        assert_eq!(image.samples(), [0, 0, 0, 0, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_noise() {
+        let info = pgm_info(64, 64, 255);
+        let image = uniform_noise(info.clone(), 7).expect("noise");
+        assert_eq!(uniform_noise(info.clone(), 7), Ok(image.clone()));
+        assert_ne!(uniform_noise(info.clone(), 8), Ok(image.clone()));
+        let mean = image.samples().iter().map(|&s| s as f64).sum::<f64>() / 4096.0;
+        assert!((mean - 127.5).abs() < 5.0);
+
+        let image = gaussian_noise(info.clone(), 100.0, 10.0, 7).expect("noise");
+        let samples: Vec<f64> = image.samples().iter().map(|&s| s as f64).collect();
+        let mean = samples.iter().sum::<f64>() / 4096.0;
+        let variance = samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / 4096.0;
+        assert!((mean - 100.0).abs() < 1.0);
+        assert!((variance.sqrt() - 10.0).abs() < 1.0);
+        assert!(gaussian_noise(info, 0.0, -1.0, 7).is_err());
+    }
+
+    #[test]
+    fn test_sequence() {
+        let image = sequence(2, 2).expect("sequence");
+        assert_eq!(image.width(), 9);
+        assert_eq!(image.info().format, NetpbmFormat::PAM);
+        assert_eq!(
+            image.samples(),
+            [0, 0, 0, 1, 0, 2, 1, 0, 1, 1, 1, 2, 2, 0, 2, 1, 2, 2]
+        );
+        assert!(sequence(65535, 3).is_err());
+    }
+}
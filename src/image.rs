@@ -0,0 +1,353 @@
+//! Decoded netpbm images.
+//!
+//! An `Image` holds the `Info` and `TypeInfo` of one PBM, PGM,
+//! PPM, or PAM image along with its samples. Image operations
+//! in this crate take and return `Image`s, so the result of any
+//! operation can be passed to another or written with `write`.
+//!
+//! Samples are stored as `u16` regardless of the bit depth, in
+//! row-major order with the samples of each tuple adjacent.
+//! There are `height` number of rows, each with `width` tuples
+//! of `channels` samples.
+//!
+//! PBM samples follow the netpbm convention: 1 is black and
+//! 0 is white. This is the opposite of PGM, where 0 is black.
+//!
+//! netpbm files may hold a sequence of images. `Images` decodes
+//! them one at a time.
+//!
+
+use crate::formats::decode::{self, Scanner};
+use crate::io;
+use crate::{pam, pbm, pgm, ppm};
+use crate::{Info, NetpbmError, NetpbmFormat, TypeInfo};
+use alloc::format;
+use alloc::vec::Vec;
+
+/// A decoded netpbm image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    info: Info,
+    type_info: TypeInfo,
+    samples: Vec<u16>,
+}
+
+impl Image {
+    /// Create a new image from its metadata and samples.
+    ///
+    /// Samples are validated against the image dimensions and bit depth.
+    ///
+    pub fn new(info: Info, type_info: TypeInfo, samples: Vec<u16>) -> Result<Self, NetpbmError> {
+        if info.format.is_pfm() {
+            return Err(NetpbmError::IOOperationFailed {
+                info: format!("{} images have floating point samples", info.format.magic()),
+            });
+        }
+        info.validate_u16_samples(&samples)?;
+
+        Ok(Image {
+            info,
+            type_info,
+            samples,
+        })
+    }
+
+    /// Create an image without validating its samples.
+    ///
+    /// Callers must ensure that the samples agree with `info`.
+    ///
+    pub(crate) fn from_parts(info: Info, type_info: TypeInfo, samples: Vec<u16>) -> Self {
+        debug_assert!(info.validate_u16_samples(&samples).is_ok());
+        Image {
+            info,
+            type_info,
+            samples,
+        }
+    }
+
+    /// Decode the first image from the given reader.
+    #[cfg(feature = "std")]
+    pub fn read<R: io::Read>(mut reader: R) -> Result<Self, NetpbmError> {
+        let mut img_buf = Vec::new();
+        reader.read_to_end(&mut img_buf)?;
+        let (image, _) = Self::decode(&img_buf)?;
+        Ok(image)
+    }
+
+    /// Decode every image in the reader.
+    #[cfg(feature = "std")]
+    pub fn read_all<R: io::Read>(mut reader: R) -> Result<Vec<Self>, NetpbmError> {
+        let mut img_buf = Vec::new();
+        reader.read_to_end(&mut img_buf)?;
+        Images::new(&img_buf).collect()
+    }
+
+    /// Decode one image from the start of the buffer.
+    ///
+    /// Returns the image and the number of bytes consumed.
+    ///
+    pub fn decode(buf: &[u8]) -> Result<(Self, usize), NetpbmError> {
+        let (info, type_info, samples, len) = decode::read_image(buf)?;
+        Ok((Self::from_parts(info, type_info, samples), len))
+    }
+
+    /// Encode the image with the encoder for its format.
+    pub fn write<W: io::Write>(&self, writer: W) -> Result<(), NetpbmError> {
+        let info = &self.info;
+        let (width, height) = (self.width(), self.height());
+        match info.format {
+            NetpbmFormat::PBMRaw | NetpbmFormat::PBMPlain => {
+                let samples: Vec<u8> = self.samples.iter().map(|&s| s as u8).collect();
+                pbm::Encoder::new(writer).write(info.encoding, width, height, &samples)
+            }
+            NetpbmFormat::PGMRaw | NetpbmFormat::PGMPlain => pgm::Encoder::new(writer).write_wide(
+                info.encoding,
+                width,
+                height,
+                self.bit_depth(),
+                &self.samples,
+            ),
+            NetpbmFormat::PPMRaw | NetpbmFormat::PPMPlain => ppm::Encoder::new(writer).write_wide(
+                info.encoding,
+                width,
+                height,
+                self.bit_depth(),
+                &self.samples,
+            ),
+            NetpbmFormat::PAM => pam::Encoder::new(writer).write_wide(
+                width,
+                height,
+                self.bit_depth(),
+                self.channels(),
+                &self.type_info,
+                &self.samples,
+            ),
+            NetpbmFormat::PFMColor | NetpbmFormat::PFMGray => unreachable!(),
+        }
+    }
+
+    /// Get the image metadata.
+    pub fn info(&self) -> &Info {
+        &self.info
+    }
+
+    /// Get the image type info.
+    pub fn type_info(&self) -> &TypeInfo {
+        &self.type_info
+    }
+
+    /// Get the image width.
+    pub fn width(&self) -> u32 {
+        self.info.width.value()
+    }
+
+    /// Get the image height.
+    pub fn height(&self) -> u32 {
+        self.info.height.value()
+    }
+
+    /// Get the number of samples per tuple.
+    pub fn channels(&self) -> u32 {
+        self.info.channels.value()
+    }
+
+    /// Get the image bit depth. This is the maximum value
+    /// a sample can hold.
+    pub fn bit_depth(&self) -> u16 {
+        self.info.bit_depth.value()
+    }
+
+    /// Get the samples of the image.
+    pub fn samples(&self) -> &[u16] {
+        &self.samples
+    }
+
+    /// Get the samples of the image for modification.
+    ///
+    /// Samples must not exceed the bit depth.
+    ///
+    pub fn samples_mut(&mut self) -> &mut [u16] {
+        &mut self.samples
+    }
+
+    /// Consume the image and return its samples.
+    pub fn into_samples(self) -> Vec<u16> {
+        self.samples
+    }
+
+    /// Get the samples of row `y`.
+    pub fn row(&self, y: u32) -> &[u16] {
+        let row_len = self.row_len();
+        &self.samples[y as usize * row_len..(y as usize + 1) * row_len]
+    }
+
+    /// Get the tuple at `(x, y)`.
+    pub fn tuple(&self, x: u32, y: u32) -> &[u16] {
+        let offset = self.offset(x, y);
+        &self.samples[offset..offset + self.channels() as usize]
+    }
+
+    /// Get the tuple at `(x, y)` for modification.
+    ///
+    /// Samples must not exceed the bit depth.
+    ///
+    pub fn tuple_mut(&mut self, x: u32, y: u32) -> &mut [u16] {
+        let offset = self.offset(x, y);
+        let channels = self.channels() as usize;
+        &mut self.samples[offset..offset + channels]
+    }
+
+    /// Get the number of samples in a row.
+    fn row_len(&self) -> usize {
+        self.width() as usize * self.channels() as usize
+    }
+
+    /// Get the sample offset of the tuple at `(x, y)`.
+    fn offset(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.width() && y < self.height(), "tuple out of bounds");
+        y as usize * self.row_len() + x as usize * self.channels() as usize
+    }
+}
+
+/// Iterator over the images of a netpbm file.
+///
+/// Images may be separated by whitespace and comments. The
+/// iterator stops after the last image, or after the first error.
+/// A buffer without any image is an error.
+///
+/// Error offsets are relative to the start of the buffer.
+///
+#[derive(Debug, Clone)]
+pub struct Images<'a> {
+    scanner: Scanner<'a>,
+    count: usize,
+    done: bool,
+}
+
+impl<'a> Images<'a> {
+    /// Create an iterator over the images in the buffer.
+    pub fn new(buf: &'a [u8]) -> Self {
+        Images {
+            scanner: Scanner::new(buf),
+            count: 0,
+            done: false,
+        }
+    }
+
+    /// Get the byte offset of the next image.
+    pub fn offset(&self) -> usize {
+        self.scanner.offset()
+    }
+
+    /// Decode the image after any whitespace and comments.
+    ///
+    /// Returns `None` if only whitespace and comments remain,
+    /// unless this is the `first` image. The scanner is advanced
+    /// past the image, or to the error.
+    ///
+    pub(crate) fn read_next(
+        scanner: &mut Scanner,
+        first: bool,
+    ) -> Option<Result<Image, NetpbmError>> {
+        scanner.skip_whitespace();
+        if scanner.is_empty() && !first {
+            return None;
+        }
+
+        Some(decode::read_header(scanner).and_then(|(info, type_info)| {
+            let samples = decode::read_samples(scanner, &info)?;
+            Ok(Image::from_parts(info, type_info, samples))
+        }))
+    }
+}
+
+impl Iterator for Images<'_> {
+    type Item = Result<Image, NetpbmError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let image = Self::read_next(&mut self.scanner, self.count == 0);
+        self.count += 1;
+        self.done = !matches!(image, Some(Ok(_)));
+        image
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::EncodingType;
+
+    #[test]
+    fn test_new_image() {
+        let info = Info::new_ppm(EncodingType::Raw, 2, 1, 100).expect("ppm");
+        let image = Image::new(info.clone(), TypeInfo::Empty, vec![1, 2, 3, 4, 5, 6]);
+        let image = image.expect("image");
+        assert_eq!(image.tuple(1, 0), [4, 5, 6]);
+        assert_eq!(image.row(0), [1, 2, 3, 4, 5, 6]);
+
+        assert!(Image::new(info.clone(), TypeInfo::Empty, vec![1, 2, 3]).is_err());
+        assert!(Image::new(info, TypeInfo::Empty, vec![1, 2, 3, 4, 5, 101]).is_err());
+
+        let info = Info::new_pfm(1, 1, 1).expect("pfm");
+        assert!(Image::new(info, TypeInfo::Empty, vec![0]).is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let images = [
+            (Info::new_pbm(EncodingType::Raw, 9, 2).expect("pbm"), 1),
+            (Info::new_pbm(EncodingType::Plain, 3, 2).expect("pbm"), 1),
+            (
+                Info::new_pgm(EncodingType::Raw, 3, 2, 300).expect("pgm"),
+                300,
+            ),
+            (
+                Info::new_ppm(EncodingType::Plain, 2, 2, 255).expect("ppm"),
+                255,
+            ),
+            (Info::new_pam(2, 3, 65535, 2).expect("pam"), 65535),
+        ];
+
+        for (info, maxval) in images {
+            let len = (info.width.value() * info.height.value() * info.channels.value()) as usize;
+            let samples = (0..len).map(|i| (i * 37 % (maxval + 1)) as u16).collect();
+            let image = Image::new(info, TypeInfo::Empty, samples).expect("image");
+
+            let mut buf = Vec::new();
+            image.write(&mut buf).expect("write");
+            assert_eq!(Image::read(&buf[..]), Ok(image));
+        }
+    }
+
+    #[test]
+    fn test_images() {
+        let gray = Info::new_pgm(EncodingType::Plain, 2, 1, 9).expect("pgm");
+        let gray = Image::new(gray, TypeInfo::Empty, vec![1, 9]).expect("pgm");
+        let color = Info::new_ppm(EncodingType::Raw, 1, 1, 255).expect("ppm");
+        let color = Image::new(color, TypeInfo::Empty, vec![1, 2, 3]).expect("ppm");
+
+        let mut buf = Vec::new();
+        gray.write(&mut buf).expect("write");
+        color.write(&mut buf).expect("write");
+        buf.extend(b"\n# trailing comment\n");
+        assert_eq!(Image::read_all(&buf[..]), Ok(vec![gray, color]));
+
+        // Errors are reported at their offset in the whole buffer.
+        let len = buf.len();
+        buf.extend(b"P5 1 1 255\n");
+        let mut images = Images::new(&buf);
+        assert!(images.next().expect("gray").is_ok());
+        assert!(images.next().expect("color").is_ok());
+        assert_eq!(
+            images.next(),
+            Some(Err(NetpbmError::UnexpectedEof { offset: len + 11 }))
+        );
+        assert_eq!(images.next(), None);
+
+        assert!(Image::read_all(&b" \n"[..]).is_err());
+    }
+}
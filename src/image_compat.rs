@@ -0,0 +1,403 @@
+//! Interoperability with the `image` crate.
+//!
+//! Enabled by the `image` feature. Decoded images convert to and
+//! from `ImageBuffer`s of `Luma`, `LumaA`, `Rgb`, and `Rgba`
+//! pixels with `u8` or `u16` subpixels, and to and from
+//! `DynamicImage`.
+//!
+//! `image` only supports bit depths of 255 and 65535, so samples
+//! of other bit depths are rescaled to the same fraction of the
+//! bit depth. PBM images convert as grayscale, with 0 as black.
+//!
+//! `Decoder` and `Encoder` implement `ImageDecoder` and
+//! `ImageEncoder`, so that this crate can serve as the PNM backend
+//! of `image`.
+//!
+
+use crate::image::Image;
+use crate::{EncodingType, Info, NetpbmError, NetpbmFormat, TypeInfo};
+use ::image::error::{
+    DecodingError, EncodingError, ImageFormatHint, UnsupportedError, UnsupportedErrorKind,
+};
+use ::image::{
+    ColorType, DynamicImage, ExtendedColorType, ImageBuffer, ImageDecoder, ImageEncoder,
+    ImageError, ImageFormat, ImageResult, Luma, LumaA, Rgb, Rgba,
+};
+use std::io;
+
+/// Implement conversions between `Image` and an `ImageBuffer` of
+/// one pixel type.
+macro_rules! impl_buffer {
+    ($pixel:ident, $sub:ty, $channels:expr) => {
+        impl TryFrom<&Image> for ImageBuffer<$pixel<$sub>, Vec<$sub>> {
+            type Error = NetpbmError;
+
+            fn try_from(image: &Image) -> Result<Self, NetpbmError> {
+                check_channels(image, $channels)?;
+                let samples = rescaled(image, <$sub>::MAX as u16)
+                    .map(|s| s as $sub)
+                    .collect();
+                Ok(
+                    ImageBuffer::from_raw(image.width(), image.height(), samples)
+                        .expect("sample count matches the image dimensions"),
+                )
+            }
+        }
+
+        impl TryFrom<&ImageBuffer<$pixel<$sub>, Vec<$sub>>> for Image {
+            type Error = NetpbmError;
+
+            fn try_from(
+                buffer: &ImageBuffer<$pixel<$sub>, Vec<$sub>>,
+            ) -> Result<Self, NetpbmError> {
+                let info = info_for(
+                    $channels,
+                    EncodingType::Raw,
+                    buffer.width(),
+                    buffer.height(),
+                    <$sub>::MAX as u16,
+                )?;
+                let samples = buffer.as_raw().iter().map(|&s| s as u16).collect();
+                Ok(Image::from_parts(info, type_info_for($channels), samples))
+            }
+        }
+    };
+}
+
+impl_buffer!(Luma, u8, 1);
+impl_buffer!(Luma, u16, 1);
+impl_buffer!(LumaA, u8, 2);
+impl_buffer!(LumaA, u16, 2);
+impl_buffer!(Rgb, u8, 3);
+impl_buffer!(Rgb, u16, 3);
+impl_buffer!(Rgba, u8, 4);
+impl_buffer!(Rgba, u16, 4);
+
+impl TryFrom<&Image> for DynamicImage {
+    type Error = NetpbmError;
+
+    /// Convert an image with 1 to 4 channels.
+    ///
+    /// Images with a bit depth up to 255 become 8-bit images, and
+    /// others become 16-bit images.
+    ///
+    fn try_from(image: &Image) -> Result<Self, NetpbmError> {
+        let narrow = image.bit_depth() <= u8::MAX as u16;
+        Ok(match (image.channels(), narrow) {
+            (1, true) => DynamicImage::ImageLuma8(image.try_into()?),
+            (1, false) => DynamicImage::ImageLuma16(image.try_into()?),
+            (2, true) => DynamicImage::ImageLumaA8(image.try_into()?),
+            (2, false) => DynamicImage::ImageLumaA16(image.try_into()?),
+            (3, true) => DynamicImage::ImageRgb8(image.try_into()?),
+            (3, false) => DynamicImage::ImageRgb16(image.try_into()?),
+            (4, true) => DynamicImage::ImageRgba8(image.try_into()?),
+            (4, false) => DynamicImage::ImageRgba16(image.try_into()?),
+            (channels, _) => {
+                return Err(NetpbmError::InvalidArgument {
+                    info: format!("Images with {} channels have no image color type", channels),
+                })
+            }
+        })
+    }
+}
+
+impl TryFrom<&DynamicImage> for Image {
+    type Error = NetpbmError;
+
+    /// Convert a dynamic image to a PGM, PPM, or PAM image.
+    ///
+    /// Floating point images are converted to 16 bits.
+    ///
+    fn try_from(image: &DynamicImage) -> Result<Self, NetpbmError> {
+        match image {
+            DynamicImage::ImageLuma8(buffer) => buffer.try_into(),
+            DynamicImage::ImageLuma16(buffer) => buffer.try_into(),
+            DynamicImage::ImageLumaA8(buffer) => buffer.try_into(),
+            DynamicImage::ImageLumaA16(buffer) => buffer.try_into(),
+            DynamicImage::ImageRgb8(buffer) => buffer.try_into(),
+            DynamicImage::ImageRgb16(buffer) => buffer.try_into(),
+            DynamicImage::ImageRgba8(buffer) => buffer.try_into(),
+            DynamicImage::ImageRgba16(buffer) => buffer.try_into(),
+            image if image.color().has_alpha() => (&image.to_rgba16()).try_into(),
+            image => (&image.to_rgb16()).try_into(),
+        }
+    }
+}
+
+/// A PNM decoder for the `image` crate.
+///
+/// The first image of the data is decoded up front. 16-bit color
+/// types are used for bit depths above 255.
+///
+pub struct Decoder {
+    image: Image,
+    color_type: ColorType,
+}
+
+impl Decoder {
+    /// Decode the first image from the given reader.
+    pub fn new<R: io::Read>(reader: R) -> ImageResult<Self> {
+        let image = Image::read(reader).map_err(decoding_error)?;
+        let narrow = image.bit_depth() <= u8::MAX as u16;
+        let color_type = match (image.channels(), narrow) {
+            (1, true) => ColorType::L8,
+            (1, false) => ColorType::L16,
+            (2, true) => ColorType::La8,
+            (2, false) => ColorType::La16,
+            (3, true) => ColorType::Rgb8,
+            (3, false) => ColorType::Rgb16,
+            (4, true) => ColorType::Rgba8,
+            (4, false) => ColorType::Rgba16,
+            (channels, _) => {
+                return Err(unsupported(UnsupportedErrorKind::GenericFeature(format!(
+                    "images with {} channels",
+                    channels
+                ))))
+            }
+        };
+        Ok(Decoder { image, color_type })
+    }
+
+    /// Get the decoded image.
+    pub fn into_image(self) -> Image {
+        self.image
+    }
+}
+
+impl ImageDecoder for Decoder {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.image.width(), self.image.height())
+    }
+
+    fn color_type(&self) -> ColorType {
+        self.color_type
+    }
+
+    fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+        assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+        if self.color_type.bytes_per_pixel() == self.color_type.channel_count() {
+            for (out, s) in buf.iter_mut().zip(rescaled(&self.image, u8::MAX as u16)) {
+                *out = s as u8;
+            }
+        } else {
+            for (out, s) in buf.chunks_exact_mut(2).zip(rescaled(&self.image, u16::MAX)) {
+                out.copy_from_slice(&s.to_ne_bytes());
+            }
+        }
+        Ok(())
+    }
+
+    fn read_image_boxed(self: Box<Self>, buf: &mut [u8]) -> ImageResult<()> {
+        (*self).read_image(buf)
+    }
+}
+
+/// A PNM encoder for the `image` crate.
+///
+/// Gray images are written as PGM, color images as PPM, and images
+/// with alpha as PAM.
+///
+pub struct Encoder<W: io::Write> {
+    writer: W,
+    encoding: EncodingType,
+}
+
+impl<W: io::Write> Encoder<W> {
+    /// Create a new encoder that writes raw images.
+    pub fn new(writer: W) -> Self {
+        Encoder {
+            writer,
+            encoding: EncodingType::Raw,
+        }
+    }
+
+    /// Set the encoding type of PGM and PPM images.
+    ///
+    /// PAM images are always raw.
+    ///
+    pub fn with_encoding(mut self, encoding: EncodingType) -> Self {
+        self.encoding = encoding;
+        self
+    }
+}
+
+impl<W: io::Write> ImageEncoder for Encoder<W> {
+    fn write_image(
+        self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ExtendedColorType,
+    ) -> ImageResult<()> {
+        let (channels, wide) = match color_type {
+            ExtendedColorType::L8 => (1, false),
+            ExtendedColorType::L16 => (1, true),
+            ExtendedColorType::La8 => (2, false),
+            ExtendedColorType::La16 => (2, true),
+            ExtendedColorType::Rgb8 => (3, false),
+            ExtendedColorType::Rgb16 => (3, true),
+            ExtendedColorType::Rgba8 => (4, false),
+            ExtendedColorType::Rgba16 => (4, true),
+            _ => return Err(unsupported(UnsupportedErrorKind::Color(color_type))),
+        };
+        let expected = width as u64 * height as u64 * color_type.bits_per_pixel() as u64 / 8;
+        assert_eq!(buf.len() as u64, expected);
+
+        let (samples, bit_depth): (Vec<u16>, u16) = if wide {
+            let samples = buf
+                .chunks_exact(2)
+                .map(|b| u16::from_ne_bytes([b[0], b[1]]))
+                .collect();
+            (samples, u16::MAX)
+        } else {
+            (buf.iter().map(|&b| b as u16).collect(), u8::MAX as u16)
+        };
+
+        let info =
+            info_for(channels, self.encoding, width, height, bit_depth).map_err(encoding_error)?;
+        Image::from_parts(info, type_info_for(channels), samples)
+            .write(self.writer)
+            .map_err(encoding_error)
+    }
+}
+
+/// Check that an image has the channel count of a pixel type.
+fn check_channels(image: &Image, channels: u32) -> Result<(), NetpbmError> {
+    if image.channels() != channels {
+        return Err(NetpbmError::InvalidArgument {
+            info: format!(
+                "Images with {} channels cannot convert to pixels with {} channels",
+                image.channels(),
+                channels
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// Iterate over the samples of an image rescaled to `max`.
+///
+/// PBM samples are inverted, so that 0 is black.
+///
+fn rescaled(image: &Image, max: u16) -> impl Iterator<Item = u16> + '_ {
+    let bitmap = matches!(
+        image.info().format,
+        NetpbmFormat::PBMRaw | NetpbmFormat::PBMPlain
+    );
+    let bit_depth = image.bit_depth() as u32;
+    image.samples().iter().map(move |&s| {
+        let s = if bitmap { 1 - s } else { s } as u32;
+        ((s * max as u32 + bit_depth / 2) / bit_depth) as u16
+    })
+}
+
+/// Get the info of an `image` pixel layout.
+///
+/// Gray and color images are PGM and PPM, and images with alpha
+/// are PAM.
+///
+fn info_for(
+    channels: u32,
+    encoding: EncodingType,
+    width: u32,
+    height: u32,
+    bit_depth: u16,
+) -> Result<Info, NetpbmError> {
+    match channels {
+        1 => Info::new_pgm(encoding, width, height, bit_depth),
+        3 => Info::new_ppm(encoding, width, height, bit_depth),
+        _ => Info::new_pam(width, height, bit_depth, channels),
+    }
+}
+
+/// Get the PAM tuple type of an `image` pixel layout.
+fn type_info_for(channels: u32) -> TypeInfo {
+    match channels {
+        2 => TypeInfo::Info(vec!["GRAYSCALE_ALPHA".to_string()]),
+        4 => TypeInfo::Info(vec!["RGB_ALPHA".to_string()]),
+        _ => TypeInfo::Empty,
+    }
+}
+
+fn decoding_error(err: NetpbmError) -> ImageError {
+    ImageError::Decoding(DecodingError::new(
+        ImageFormatHint::Exact(ImageFormat::Pnm),
+        err,
+    ))
+}
+
+fn encoding_error(err: NetpbmError) -> ImageError {
+    ImageError::Encoding(EncodingError::new(
+        ImageFormatHint::Exact(ImageFormat::Pnm),
+        err,
+    ))
+}
+
+fn unsupported(kind: UnsupportedErrorKind) -> ImageError {
+    ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+        ImageFormatHint::Exact(ImageFormat::Pnm),
+        kind,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{pgm, ppm};
+    use ::image::{GrayImage, RgbaImage};
+
+    #[test]
+    fn test_buffers() {
+        let image = pgm(3, 1, 15, vec![0, 8, 15]);
+        let gray = GrayImage::try_from(&image).expect("gray");
+        assert_eq!(gray.as_raw(), &[0, 136, 255]);
+        assert!(RgbaImage::try_from(&image).is_err());
+
+        let back = Image::try_from(&gray).expect("image");
+        assert_eq!(back.info().format, NetpbmFormat::PGMRaw);
+        assert_eq!(back.samples(), [0, 136, 255]);
+
+        let rgba = RgbaImage::from_raw(1, 1, vec![1, 2, 3, 4]).expect("rgba");
+        let pam = Image::try_from(&rgba).expect("image");
+        assert_eq!(pam.info().format, NetpbmFormat::PAM);
+        assert_eq!(
+            pam.type_info(),
+            &TypeInfo::Info(vec!["RGB_ALPHA".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_dynamic_image() {
+        let info = Info::new_pbm(EncodingType::Raw, 2, 1).expect("pbm");
+        let bitmap = Image::new(info, TypeInfo::Empty, vec![1, 0]).expect("pbm");
+        let dynamic = DynamicImage::try_from(&bitmap).expect("dynamic");
+        assert_eq!(dynamic.as_luma8().expect("luma8").as_raw(), &[0, 255]);
+
+        let wide = ppm(1, 1, 1000, vec![0, 500, 1000]);
+        let dynamic = DynamicImage::try_from(&wide).expect("dynamic");
+        assert_eq!(
+            dynamic.as_rgb16().expect("rgb16").as_raw(),
+            &[0, 32768, 65535]
+        );
+        let back = Image::try_from(&dynamic).expect("image");
+        assert_eq!(back.bit_depth(), 65535);
+    }
+
+    #[test]
+    fn test_decoder_and_encoder() {
+        let data = b"P2\n2 1\n1000\n0 1000\n";
+        let decoder = Decoder::new(&data[..]).expect("decoder");
+        assert_eq!(decoder.color_type(), ColorType::L16);
+        let dynamic = DynamicImage::from_decoder(decoder).expect("decode");
+        assert_eq!(dynamic.as_luma16().expect("luma16").as_raw(), &[0, 65535]);
+
+        let mut out = Vec::new();
+        Encoder::new(&mut out)
+            .with_encoding(EncodingType::Plain)
+            .write_image(&[0, 128, 255], 3, 1, ExtendedColorType::L8)
+            .expect("encode");
+        let (image, _) = Image::decode(&out).expect("decode");
+        assert_eq!(image.info().format, NetpbmFormat::PGMPlain);
+        assert_eq!(image.samples(), [0, 128, 255]);
+    }
+}
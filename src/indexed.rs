@@ -0,0 +1,358 @@
+//! Indexed-color images.
+//!
+//! An `IndexedImage` stores one palette index per pixel alongside a
+//! `Palette`. Indices are stored as bytes when the palette has at
+//! most 256 colors, and as 16-bit words otherwise, so palettes have
+//! at most 65536 colors.
+//!
+//! Netpbm has no indexed format, so indexed images are expanded to
+//! PPM or PAM images on write. They can also be written as two
+//! images: the indices as a PGM image, and the palette as a PPM
+//! image with one row, following the `pnmcolormap` convention.
+//!
+
+use crate::image::Image;
+use crate::io;
+use crate::math;
+use crate::quantize::{self, Dither, Palette};
+#[cfg(feature = "std")]
+use crate::ChannelDepth;
+use crate::{EncodingType, ImageDim, Info, NetpbmError, TypeInfo};
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Palette indices of every pixel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Indices {
+    /// Indices into palettes of at most 256 colors.
+    U8(Vec<u8>),
+
+    /// Indices into palettes of at most 65536 colors.
+    U16(Vec<u16>),
+}
+
+impl Indices {
+    /// Get the number of indices.
+    pub fn len(&self) -> usize {
+        match self {
+            Indices::U8(indices) => indices.len(),
+            Indices::U16(indices) => indices.len(),
+        }
+    }
+
+    /// Determine whether there are no indices.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the index at position `i`.
+    pub fn get(&self, i: usize) -> usize {
+        match self {
+            Indices::U8(indices) => indices[i] as usize,
+            Indices::U16(indices) => indices[i] as usize,
+        }
+    }
+
+    /// Iterate over the indices.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.len()).map(|i| self.get(i))
+    }
+
+    /// Store indices in the smallest type that holds palettes of
+    /// `colors` colors.
+    ///
+    /// Indices are checked against the palette before they are
+    /// narrowed, so that an out of range index cannot wrap around.
+    ///
+    fn from_usize(indices: Vec<usize>, colors: usize) -> Result<Self, NetpbmError> {
+        if let Some(&index) = indices.iter().find(|&&i| i >= colors) {
+            return Err(out_of_range(index, colors));
+        }
+        Ok(if colors <= 1 << 8 {
+            Indices::U8(indices.into_iter().map(|i| i as u8).collect())
+        } else {
+            Indices::U16(indices.into_iter().map(|i| i as u16).collect())
+        })
+    }
+}
+
+/// An image of palette indices.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexedImage {
+    width: ImageDim,
+    height: ImageDim,
+    palette: Palette,
+    indices: Indices,
+}
+
+impl IndexedImage {
+    /// Create an indexed image from row-major indices.
+    pub fn new(
+        width: u32,
+        height: u32,
+        palette: Palette,
+        indices: Indices,
+    ) -> Result<Self, NetpbmError> {
+        let width = ImageDim::new(width)?;
+        let height = ImageDim::new(height)?;
+        if indices.len() != width.value() as usize * height.value() as usize {
+            return Err(NetpbmError::MalformedInitArray {
+                data_size: indices.len(),
+                width,
+                height,
+            });
+        }
+        if let Some(index) = indices.iter().find(|&i| i >= palette.len()) {
+            return Err(out_of_range(index, palette.len()));
+        }
+
+        Ok(IndexedImage {
+            width,
+            height,
+            palette,
+            indices,
+        })
+    }
+
+    /// Create an indexed image from the colors of an RGB image.
+    ///
+    /// The palette holds each color of the image once, in the order
+    /// they first appear. Images with more than 65536 colors must be
+    /// quantized first.
+    ///
+    pub fn from_image(image: &Image) -> Result<Self, NetpbmError> {
+        quantize::check_rgb(image)?;
+
+        let mut lookup: BTreeMap<[u16; 3], usize> = BTreeMap::new();
+        let mut colors = Vec::new();
+        let mut indices = Vec::with_capacity(image.samples().len() / 3);
+        for rgb in image.samples().chunks_exact(3) {
+            let color = [rgb[0], rgb[1], rgb[2]];
+            let index = *lookup.entry(color).or_insert_with(|| {
+                colors.push(color);
+                colors.len() - 1
+            });
+            if index > u16::MAX as usize {
+                return Err(NetpbmError::InvalidArgument {
+                    info: "Indexed images have at most 65536 colors".to_string(),
+                });
+            }
+            indices.push(index);
+        }
+
+        let indices = Indices::from_usize(indices, colors.len())?;
+        let palette = Palette::new(image.bit_depth(), colors)?;
+        Self::new(image.width(), image.height(), palette, indices)
+    }
+
+    /// Map an RGB image onto a palette, as with `quantize::remap`.
+    pub fn quantize(image: &Image, palette: &Palette, dither: Dither) -> Result<Self, NetpbmError> {
+        let remapped = quantize::remap(image, palette, dither)?;
+
+        // Remapped colors are rescaled to the image bit depth, so
+        // look them up the same way. Repeated colors use their first index.
+        let scale = image.bit_depth() as f64 / palette.bit_depth() as f64;
+        let mut lookup = BTreeMap::new();
+        for (index, color) in palette.colors().iter().enumerate() {
+            let color = color.map(|s| math::round(s as f64 * scale) as u16);
+            lookup.entry(color).or_insert(index);
+        }
+
+        let indices = remapped
+            .samples()
+            .chunks_exact(3)
+            .map(|rgb| lookup[&[rgb[0], rgb[1], rgb[2]]])
+            .collect();
+        let indices = Indices::from_usize(indices, palette.len())?;
+        Self::new(image.width(), image.height(), palette.clone(), indices)
+    }
+
+    /// Decode an indexed image from a PGM image of indices and a
+    /// palette image.
+    #[cfg(feature = "std")]
+    pub fn read_split<R: io::Read, P: io::Read>(
+        indices: R,
+        palette: P,
+    ) -> Result<Self, NetpbmError> {
+        let image = Image::read(indices)?;
+        let palette = Palette::read(palette)?;
+        if image.channels() != 1 {
+            return Err(NetpbmError::ChannelMismatch {
+                expected: ChannelDepth::new(1).expect("Greyscale channel depth"),
+                found: image.info().channels,
+            });
+        }
+
+        let indices = image.samples().iter().map(|&i| i as usize).collect();
+        let indices = Indices::from_usize(indices, palette.len())?;
+        Self::new(image.width(), image.height(), palette, indices)
+    }
+
+    /// Encode the image as a PGM image of indices and a palette image.
+    ///
+    /// The bit depth of the index image is the largest palette
+    /// index, or 1 for palettes with one color.
+    ///
+    pub fn write_split<W: io::Write, P: io::Write>(
+        &self,
+        indices: W,
+        palette: P,
+        encoding: EncodingType,
+    ) -> Result<(), NetpbmError> {
+        let bit_depth = (self.palette.len() - 1).max(1) as u16;
+        let info = Info::new_pgm(encoding, self.width(), self.height(), bit_depth)?;
+        let samples = self.indices.iter().map(|i| i as u16).collect();
+        Image::from_parts(info, TypeInfo::Empty, samples).write(indices)?;
+        self.palette.to_image(encoding).write(palette)
+    }
+
+    /// Encode the image as a PPM image.
+    pub fn write<W: io::Write>(
+        &self,
+        writer: W,
+        encoding: EncodingType,
+    ) -> Result<(), NetpbmError> {
+        self.to_ppm(encoding).write(writer)
+    }
+
+    /// Expand the image to a PPM image.
+    pub fn to_ppm(&self, encoding: EncodingType) -> Image {
+        let info = Info::new_ppm(
+            encoding,
+            self.width(),
+            self.height(),
+            self.palette.bit_depth(),
+        )
+        .expect("Indexed image info");
+        Image::from_parts(info, TypeInfo::Empty, self.expand())
+    }
+
+    /// Expand the image to a PAM image with the `RGB` tuple type.
+    pub fn to_pam(&self) -> Image {
+        let info = Info::new_pam(self.width(), self.height(), self.palette.bit_depth(), 3)
+            .expect("Indexed image info");
+        let type_info = TypeInfo::Info(vec!["RGB".to_string()]);
+        Image::from_parts(info, type_info, self.expand())
+    }
+
+    /// Get the image width.
+    pub fn width(&self) -> u32 {
+        self.width.value()
+    }
+
+    /// Get the image height.
+    pub fn height(&self) -> u32 {
+        self.height.value()
+    }
+
+    /// Get the palette.
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    /// Get the row-major indices.
+    pub fn indices(&self) -> &Indices {
+        &self.indices
+    }
+
+    /// Get the palette index of the pixel at `(x, y)`.
+    pub fn get(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.width() && y < self.height(), "pixel out of bounds");
+        self.indices
+            .get(y as usize * self.width() as usize + x as usize)
+    }
+
+    /// Get the color of the pixel at `(x, y)`.
+    pub fn color(&self, x: u32, y: u32) -> [u16; 3] {
+        self.palette.colors()[self.get(x, y)]
+    }
+
+    /// Look up the color of every index.
+    fn expand(&self) -> Vec<u16> {
+        let colors = self.palette.colors();
+        self.indices.iter().flat_map(|i| colors[i]).collect()
+    }
+}
+
+/// Error for an index that is not in the palette.
+fn out_of_range(index: usize, colors: usize) -> NetpbmError {
+    NetpbmError::InvalidArgument {
+        info: format!(
+            "Index {} is out of range for a palette of {} colors",
+            index, colors
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::ppm;
+    use crate::NetpbmFormat;
+
+    #[test]
+    fn test_from_image() {
+        let image = ppm(3, 1, 255, vec![1, 2, 3, 4, 5, 6, 1, 2, 3]);
+        let indexed = IndexedImage::from_image(&image).expect("indexed");
+        assert_eq!(indexed.indices(), &Indices::U8(vec![0, 1, 0]));
+        assert_eq!(indexed.palette().colors(), [[1, 2, 3], [4, 5, 6]]);
+        assert_eq!(indexed.color(2, 0), [1, 2, 3]);
+        assert_eq!(indexed.to_ppm(EncodingType::Raw), image);
+
+        let pam = indexed.to_pam();
+        assert_eq!(pam.info().format, NetpbmFormat::PAM);
+        assert_eq!(pam.samples(), image.samples());
+
+        // More than 256 colors need 16-bit indices.
+        let samples = (0..300).flat_map(|i| [i, 0, 0]).collect();
+        let indexed = IndexedImage::from_image(&ppm(300, 1, 1000, samples)).expect("indexed");
+        assert!(matches!(indexed.indices(), Indices::U16(_)));
+        assert_eq!(indexed.get(299, 0), 299);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_split_round_trip() {
+        let palette =
+            Palette::new(255, vec![[255, 0, 0], [0, 255, 0], [0, 0, 255]]).expect("palette");
+        let indexed =
+            IndexedImage::new(2, 2, palette, Indices::U8(vec![0, 1, 2, 1])).expect("indexed");
+
+        let (mut indices, mut palette) = (Vec::new(), Vec::new());
+        indexed
+            .write_split(&mut indices, &mut palette, EncodingType::Plain)
+            .expect("write");
+        assert_eq!(indices, b"P2\n2 2 2\n0\n1\n2\n1\n");
+        assert_eq!(palette, b"P3\n3 1 255\n255 0 0\n0 255 0\n0 0 255\n");
+        assert_eq!(
+            IndexedImage::read_split(&indices[..], &palette[..]),
+            Ok(indexed)
+        );
+
+        // Indices past the palette are rejected.
+        let palette = Palette::new(255, vec![[0, 0, 0]]).expect("palette");
+        assert!(IndexedImage::new(1, 1, palette, Indices::U8(vec![1])).is_err());
+
+        // Index 256 must not wrap around to 0 when narrowed to a byte.
+        let mut palette = Vec::new();
+        Palette::new(255, vec![[0, 0, 0], [9, 9, 9]])
+            .expect("palette")
+            .to_image(EncodingType::Raw)
+            .write(&mut palette)
+            .expect("palette");
+        let result = IndexedImage::read_split(&b"P2 2 1 300 1 256"[..], &palette[..]);
+        assert!(matches!(result, Err(NetpbmError::InvalidArgument { .. })));
+    }
+
+    #[test]
+    fn test_quantize() {
+        let palette = Palette::new(1, vec![[0, 0, 0], [1, 1, 1]]).expect("palette");
+        let image = ppm(2, 1, 255, vec![10, 20, 30, 200, 220, 250]);
+        let indexed = IndexedImage::quantize(&image, &palette, Dither::None).expect("indexed");
+        assert_eq!(indexed.indices(), &Indices::U8(vec![0, 1]));
+        assert_eq!(indexed.palette(), &palette);
+    }
+}
@@ -0,0 +1,101 @@
+//! Byte I/O for encoders and decoders.
+//!
+//! With the `std` feature, this module re-exports `std::io`, so
+//! encoders write to any `std::io::Write` and decoders read from
+//! any `std::io::Read`.
+//!
+//! Without `std`, decoders are not available and encoders write to
+//! the minimal `Write` trait defined here. It is implemented for
+//! `Vec<u8>`, for `&mut [u8]`, which is filled from the front and
+//! advanced past the written bytes, and for mutable references to
+//! other writers. Implement it for a UART or other byte sink to
+//! stream images out without buffering them.
+//!
+
+#[cfg(feature = "std")]
+pub use std::io::*;
+
+#[cfg(not(feature = "std"))]
+pub use self::no_std::Write;
+
+#[cfg(not(feature = "std"))]
+mod no_std {
+    use crate::NetpbmError;
+    use alloc::format;
+    use alloc::vec::Vec;
+
+    /// A byte sink for encoders.
+    pub trait Write {
+        /// Write every byte of the buffer.
+        fn write_all(&mut self, buf: &[u8]) -> Result<(), NetpbmError>;
+    }
+
+    impl Write for Vec<u8> {
+        fn write_all(&mut self, buf: &[u8]) -> Result<(), NetpbmError> {
+            self.extend_from_slice(buf);
+            Ok(())
+        }
+    }
+
+    impl Write for &mut [u8] {
+        fn write_all(&mut self, buf: &[u8]) -> Result<(), NetpbmError> {
+            if buf.len() > self.len() {
+                return Err(NetpbmError::IOOperationFailed {
+                    info: format!(
+                        "{} bytes do not fit in the remaining {} bytes",
+                        buf.len(),
+                        self.len()
+                    ),
+                });
+            }
+            let (head, tail) = core::mem::take(self).split_at_mut(buf.len());
+            head.copy_from_slice(buf);
+            *self = tail;
+            Ok(())
+        }
+    }
+
+    impl<W: Write + ?Sized> Write for &mut W {
+        fn write_all(&mut self, buf: &[u8]) -> Result<(), NetpbmError> {
+            (**self).write_all(buf)
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "std")))]
+mod tests {
+    use crate::image::Image;
+    use crate::text::{self, Font};
+    use crate::{pgm, EncodingType, NetpbmError};
+
+    #[test]
+    fn test_slice_writer() {
+        let mut buf = [0; 16];
+        let mut out = &mut buf[..];
+        pgm::Encoder::new(&mut out)
+            .write(EncodingType::Raw, 2, 1, 255, &[7, 9])
+            .expect("write");
+        assert_eq!(out.len(), 3);
+        assert_eq!(&buf[..13], b"P5\n2 1 255\n\x07\x09");
+
+        let mut small = [0; 8];
+        let result = pgm::Encoder::new(&mut small[..]).write(EncodingType::Raw, 2, 1, 255, &[7, 9]);
+        assert!(matches!(result, Err(NetpbmError::IOOperationFailed { .. })));
+    }
+
+    #[test]
+    fn test_status_image() {
+        let bitmap = text::render("OK 42", &Font::builtin()).expect("render");
+        let mut buf = [0; 1024];
+        let mut out = &mut buf[..];
+        bitmap.write(&mut out, EncodingType::Raw).expect("write");
+        bitmap.write(&mut out, EncodingType::Plain).expect("write");
+        let remaining = out.len();
+        let len = buf.len() - remaining;
+
+        let (raw, offset) = Image::decode(&buf[..len]).expect("raw");
+        let (plain, _) = Image::decode(&buf[offset..len]).expect("plain");
+        assert_eq!(raw, bitmap.to_image(EncodingType::Raw));
+        assert_eq!(plain, bitmap.to_image(EncodingType::Plain));
+    }
+}
@@ -0,0 +1,577 @@
+//! Cropping, padding, concatenation, and stacking.
+//!
+//! These operations are similar to netpbm's `pamcut`, `pnmpad`,
+//! `pnmcat`, and `pamstack`. They keep the bit depth of their input
+//! images.
+//!
+//! Concatenated images must agree on their channel depth and bit
+//! depth. PBM, PGM, and PPM images are reconciled the way `pnmcat`
+//! does: PBM images are promoted to PGM, PGM images are promoted to
+//! PPM, and samples are rescaled to the largest bit depth. PAM
+//! images carry a tuple type that gives their samples meaning, so
+//! they are never promoted. Concatenating a PAM image with an image
+//! of a different channel depth or bit depth fails.
+//!
+//! Stacked images must have the same dimensions and bit depth, and
+//! are never promoted.
+//!
+
+use crate::arith;
+use crate::image::Image;
+use crate::{ImageDim, Info, NetpbmError, NetpbmFormat, TypeInfo};
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+/// A rectangle in image coordinates.
+///
+/// The origin may be negative, so that rectangles can extend
+/// past the top and left edges of an image.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Rect {
+    pub x: i64,
+    pub y: i64,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    /// Create a new rectangle.
+    pub fn new(x: i64, y: i64, width: u32, height: u32) -> Self {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Get the overlap of this rectangle with another, if any.
+    pub fn intersect(&self, other: &Rect) -> Option<Rect> {
+        let left = self.x.max(other.x);
+        let top = self.y.max(other.y);
+        let right = (self.x + self.width as i64).min(other.x + other.width as i64);
+        let bottom = (self.y + self.height as i64).min(other.y + other.height as i64);
+
+        if left < right && top < bottom {
+            Some(Rect::new(
+                left,
+                top,
+                (right - left) as u32,
+                (bottom - top) as u32,
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// How to handle a crop rectangle that extends past the image.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CropMode {
+    /// Fail unless the rectangle lies within the image.
+    Strict,
+
+    /// Shrink the rectangle to the part that lies within the image.
+    Clamp,
+
+    /// Keep the rectangle, and fill the part outside the image
+    /// with the given tuple.
+    Fill(Vec<u16>),
+}
+
+/// Padding added to each side of an image.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Padding {
+    pub left: u32,
+    pub right: u32,
+    pub top: u32,
+    pub bottom: u32,
+}
+
+impl Padding {
+    /// Create padding with the same size on every side.
+    pub fn uniform(size: u32) -> Self {
+        Padding {
+            left: size,
+            right: size,
+            top: size,
+            bottom: size,
+        }
+    }
+}
+
+/// Direction in which images are concatenated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Direction {
+    /// Place images left to right.
+    Horizontal,
+
+    /// Place images top to bottom.
+    Vertical,
+}
+
+/// Alignment of images that are smaller than the concatenated image.
+///
+/// Images concatenated horizontally are aligned vertically, and
+/// images concatenated vertically are aligned horizontally.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Align {
+    /// Align to the top or left edge.
+    Start,
+
+    /// Center the image.
+    Center,
+
+    /// Align to the bottom or right edge.
+    End,
+}
+
+/// Crop an image to a rectangle.
+pub fn crop(image: &Image, rect: Rect, mode: &CropMode) -> Result<Image, NetpbmError> {
+    let bounds = Rect::new(0, 0, image.width(), image.height());
+    let outside = || NetpbmError::InvalidArgument {
+        info: format!(
+            "Crop rectangle {:?} does not lie within the {}x{} image",
+            rect,
+            image.width(),
+            image.height()
+        ),
+    };
+
+    match mode {
+        CropMode::Strict => {
+            if bounds.intersect(&rect) != Some(rect) {
+                return Err(outside());
+            }
+            copy_rect(image, rect)
+        }
+        CropMode::Clamp => copy_rect(image, bounds.intersect(&rect).ok_or_else(outside)?),
+        CropMode::Fill(fill) => {
+            image.info().validate_tuple(fill)?;
+            let mut canvas = canvas(image.info(), rect.width, rect.height, fill)?;
+            blit(&mut canvas, image, -rect.x, -rect.y);
+            Image::new(canvas.info, image.type_info().clone(), canvas.samples)
+        }
+    }
+}
+
+/// Pad an image with the given tuple on each side.
+pub fn pad(image: &Image, padding: Padding, fill: &[u16]) -> Result<Image, NetpbmError> {
+    image.info().validate_tuple(fill)?;
+
+    let too_large = || NetpbmError::InvalidArgument {
+        info: format!("Padding {:?} is too large", padding),
+    };
+    let width = image
+        .width()
+        .checked_add(padding.left)
+        .and_then(|w| w.checked_add(padding.right))
+        .ok_or_else(too_large)?;
+    let height = image
+        .height()
+        .checked_add(padding.top)
+        .and_then(|h| h.checked_add(padding.bottom))
+        .ok_or_else(too_large)?;
+
+    let mut canvas = canvas(image.info(), width, height, fill)?;
+    blit(&mut canvas, image, padding.left as i64, padding.top as i64);
+    Ok(Image::from_parts(
+        canvas.info,
+        image.type_info().clone(),
+        canvas.samples,
+    ))
+}
+
+/// Concatenate images in the given direction.
+///
+/// Images smaller than the result across the direction of
+/// concatenation are aligned with `align`, and the remaining
+/// area is filled with `background`. The background tuple must
+/// agree with the reconciled channel depth and bit depth.
+///
+pub fn concat(
+    images: &[Image],
+    direction: Direction,
+    align: Align,
+    background: &[u16],
+) -> Result<Image, NetpbmError> {
+    let (info, type_info, images) = reconcile(images)?;
+
+    let too_large = || NetpbmError::InvalidArgument {
+        info: "Concatenated image is too large".to_string(),
+    };
+    let (along, across): (Vec<u32>, Vec<u32>) = images
+        .iter()
+        .map(|image| match direction {
+            Direction::Horizontal => (image.width(), image.height()),
+            Direction::Vertical => (image.height(), image.width()),
+        })
+        .unzip();
+    let length = along
+        .iter()
+        .try_fold(0u32, |sum, &len| sum.checked_add(len))
+        .ok_or_else(too_large)?;
+    let breadth = across.iter().copied().max().unwrap_or(1);
+
+    let (width, height) = match direction {
+        Direction::Horizontal => (length, breadth),
+        Direction::Vertical => (breadth, length),
+    };
+
+    info.validate_tuple(background)?;
+    let mut canvas = canvas(&info, width, height, background)?;
+
+    let mut position = 0i64;
+    for (image, (&along, &across)) in images.iter().zip(along.iter().zip(&across)) {
+        let offset = match align {
+            Align::Start => 0,
+            Align::Center => (breadth - across) as i64 / 2,
+            Align::End => (breadth - across) as i64,
+        };
+        match direction {
+            Direction::Horizontal => blit(&mut canvas, image, position, offset),
+            Direction::Vertical => blit(&mut canvas, image, offset, position),
+        }
+        position += along as i64;
+    }
+
+    Ok(Image::from_parts(canvas.info, type_info, canvas.samples))
+}
+
+/// Stack the channels of images into one PAM image.
+///
+/// Each tuple of the result holds the tuples of every image at
+/// the same position, in order.
+///
+pub fn stack(images: &[Image], type_info: TypeInfo) -> Result<Image, NetpbmError> {
+    let first = images.first().ok_or(NetpbmError::InvalidArgument {
+        info: "No images to stack".to_string(),
+    })?;
+
+    let mut channels = 0u32;
+    for image in images {
+        let mut expected = first.info().clone();
+        expected.channels = image.info().channels;
+        arith::check_compatible(&expected, image.info())?;
+        channels = channels
+            .checked_add(image.channels())
+            .ok_or(NetpbmError::InvalidArgument {
+                info: "Stacked image has too many channels".to_string(),
+            })?;
+    }
+
+    let pixels = first.width() as usize * first.height() as usize;
+    let mut samples = Vec::with_capacity(pixels * channels as usize);
+    for i in 0..pixels {
+        for image in images {
+            let depth = image.channels() as usize;
+            samples.extend_from_slice(&image.samples()[i * depth..(i + 1) * depth]);
+        }
+    }
+
+    let info = Info::new_pam(first.width(), first.height(), first.bit_depth(), channels)?;
+    Ok(Image::from_parts(info, type_info, samples))
+}
+
+/// Samples of an image under construction.
+struct Canvas {
+    info: Info,
+    samples: Vec<u16>,
+}
+
+/// Create a canvas with the metadata of `info`, filled with a tuple.
+fn canvas(info: &Info, width: u32, height: u32, fill: &[u16]) -> Result<Canvas, NetpbmError> {
+    let mut info = info.clone();
+    info.width = ImageDim::new(width)?;
+    info.height = ImageDim::new(height)?;
+
+    let samples = fill.repeat(width as usize * height as usize);
+    Ok(Canvas { info, samples })
+}
+
+/// Copy an image onto a canvas with its top left corner at
+/// `(x, y)`, clipping at the canvas bounds.
+fn blit(canvas: &mut Canvas, image: &Image, x: i64, y: i64) {
+    let bounds = Rect::new(0, 0, canvas.info.width.value(), canvas.info.height.value());
+    let Some(overlap) = bounds.intersect(&Rect::new(x, y, image.width(), image.height())) else {
+        return;
+    };
+
+    let channels = image.channels() as usize;
+    let row_len = bounds.width as usize * channels;
+    for dy in overlap.y..overlap.y + overlap.height as i64 {
+        let src = &image.row((dy - y) as u32)[(overlap.x - x) as usize * channels..]
+            [..overlap.width as usize * channels];
+        let start = dy as usize * row_len + overlap.x as usize * channels;
+        canvas.samples[start..start + src.len()].copy_from_slice(src);
+    }
+}
+
+/// Copy a rectangle that lies within an image.
+fn copy_rect(image: &Image, rect: Rect) -> Result<Image, NetpbmError> {
+    let mut info = image.info().clone();
+    info.width = ImageDim::new(rect.width)?;
+    info.height = ImageDim::new(rect.height)?;
+
+    let channels = image.channels() as usize;
+    let samples = (rect.y..rect.y + rect.height as i64)
+        .flat_map(|y| {
+            image.row(y as u32)[rect.x as usize * channels..][..rect.width as usize * channels]
+                .iter()
+                .copied()
+        })
+        .collect();
+
+    Ok(Image::from_parts(info, image.type_info().clone(), samples))
+}
+
+/// Determine whether an image is a PBM image.
+fn is_pbm(image: &Image) -> bool {
+    matches!(
+        image.info().format,
+        NetpbmFormat::PBMRaw | NetpbmFormat::PBMPlain
+    )
+}
+
+/// Convert images to a common channel depth and bit depth.
+///
+/// Returns the metadata and type info shared by the converted images.
+///
+fn reconcile(images: &[Image]) -> Result<(Info, TypeInfo, Vec<Cow<'_, Image>>), NetpbmError> {
+    let first = images.first().ok_or(NetpbmError::InvalidArgument {
+        info: "No images to concatenate".to_string(),
+    })?;
+
+    if let Some(pam) = images
+        .iter()
+        .find(|image| image.info().format == NetpbmFormat::PAM)
+    {
+        let info = pam.info();
+        let images = images
+            .iter()
+            .map(|image| {
+                let image = if is_pbm(image) {
+                    let gray =
+                        Info::new_pgm(image.info().encoding, image.width(), image.height(), 1)?;
+                    Cow::Owned(promote(image, &gray))
+                } else {
+                    Cow::Borrowed(image)
+                };
+                if image.info().channels != info.channels {
+                    return Err(NetpbmError::ChannelMismatch {
+                        expected: info.channels,
+                        found: image.info().channels,
+                    });
+                }
+                if image.info().bit_depth != info.bit_depth {
+                    return Err(NetpbmError::BitDepthMismatch {
+                        expected: info.bit_depth,
+                        found: image.info().bit_depth,
+                    });
+                }
+                Ok(image)
+            })
+            .collect::<Result<_, _>>()?;
+        return Ok((info.clone(), pam.type_info().clone(), images));
+    }
+
+    if images.iter().all(is_pbm) {
+        let images = images.iter().map(Cow::Borrowed).collect();
+        return Ok((first.info().clone(), TypeInfo::Empty, images));
+    }
+
+    // Promote to the richest PNM format among the images.
+    let color = images.iter().any(|image| image.channels() == 3);
+    let bit_depth = images.iter().map(Image::bit_depth).max().unwrap_or(1);
+    let (encoding, width, height) = (first.info().encoding, first.width(), first.height());
+    let info = if color {
+        Info::new_ppm(encoding, width, height, bit_depth)?
+    } else {
+        Info::new_pgm(encoding, width, height, bit_depth)?
+    };
+
+    let images = images
+        .iter()
+        .map(|image| {
+            if image.info().format.magic() == info.format.magic()
+                && image.info().bit_depth == info.bit_depth
+            {
+                Cow::Borrowed(image)
+            } else {
+                Cow::Owned(promote(image, &info))
+            }
+        })
+        .collect();
+
+    Ok((info, TypeInfo::Empty, images))
+}
+
+/// Convert a PNM image to the format, channel depth, and bit depth
+/// of `info`.
+///
+/// PBM samples are inverted, since 0 is black in every other format.
+///
+fn promote(image: &Image, info: &Info) -> Image {
+    let pbm = is_pbm(image);
+    let from = image.bit_depth() as u32;
+    let to = info.bit_depth.value() as u32;
+    let repeat = info.channels.value() as usize / image.channels() as usize;
+
+    let samples = image
+        .samples()
+        .iter()
+        .flat_map(|&s| {
+            let s = if pbm { 1 - s } else { s } as u32;
+            core::iter::repeat_n(((s * to + from / 2) / from) as u16, repeat)
+        })
+        .collect();
+
+    let mut info = info.clone();
+    info.width = image.info().width;
+    info.height = image.info().height;
+
+    Image::from_parts(info, TypeInfo::Empty, samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{pgm, ppm};
+    use crate::EncodingType;
+    use alloc::vec;
+
+    #[test]
+    fn test_crop() {
+        let image = pgm(3, 3, 255, vec![1, 2, 3, 4, 5, 6, 7, 8, 9]);
+
+        let cropped = crop(&image, Rect::new(1, 1, 2, 2), &CropMode::Strict).expect("crop");
+        assert_eq!(cropped.samples(), [5, 6, 8, 9]);
+        assert!(crop(&image, Rect::new(2, 2, 2, 2), &CropMode::Strict).is_err());
+
+        let cropped = crop(&image, Rect::new(-1, 2, 3, 4), &CropMode::Clamp).expect("crop");
+        assert_eq!(cropped.samples(), [7, 8]);
+        assert!(crop(&image, Rect::new(3, 0, 2, 2), &CropMode::Clamp).is_err());
+
+        let fill = CropMode::Fill(vec![255]);
+        let cropped = crop(&image, Rect::new(-1, 2, 3, 2), &fill).expect("crop");
+        assert_eq!(cropped.samples(), [255, 7, 8, 255, 255, 255]);
+        assert!(crop(&image, Rect::new(0, 0, 1, 1), &CropMode::Fill(vec![256])).is_err());
+    }
+
+    #[test]
+    fn test_pad() {
+        let image = ppm(1, 1, 1000, vec![1, 2, 3]);
+
+        let padding = Padding {
+            left: 1,
+            top: 1,
+            ..Padding::default()
+        };
+        let padded = pad(&image, padding, &[0, 0, 1000]).expect("pad");
+        assert_eq!((padded.width(), padded.height()), (2, 2));
+        assert_eq!(
+            padded.samples(),
+            [0, 0, 1000, 0, 0, 1000, 0, 0, 1000, 1, 2, 3]
+        );
+        assert_eq!(padded.bit_depth(), 1000);
+
+        assert!(pad(&image, Padding::uniform(1), &[0]).is_err());
+    }
+
+    #[test]
+    fn test_concat() {
+        let a = pgm(1, 3, 255, vec![1, 2, 3]);
+        let b = pgm(2, 1, 255, vec![4, 5]);
+
+        let joined = concat(
+            &[a.clone(), b.clone()],
+            Direction::Horizontal,
+            Align::Center,
+            &[0],
+        )
+        .expect("concat");
+        assert_eq!((joined.width(), joined.height()), (3, 3));
+        assert_eq!(joined.samples(), [1, 0, 0, 2, 4, 5, 3, 0, 0]);
+
+        let joined = concat(&[a, b], Direction::Vertical, Align::End, &[9]).expect("concat");
+        assert_eq!((joined.width(), joined.height()), (2, 4));
+        assert_eq!(joined.samples(), [9, 1, 9, 2, 9, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_concat_reconcile() {
+        let bits = Info::new_pbm(EncodingType::Raw, 2, 1).expect("pbm");
+        let bits = Image::new(bits, TypeInfo::Empty, vec![1, 0]).expect("pbm");
+        let gray = pgm(1, 1, 100, vec![50]);
+        let color = ppm(1, 1, 10, vec![10, 5, 0]);
+
+        let joined = concat(
+            &[bits.clone(), gray.clone(), color],
+            Direction::Horizontal,
+            Align::Start,
+            &[0, 0, 0],
+        )
+        .expect("concat");
+        assert_eq!(joined.info().format, NetpbmFormat::PPMRaw);
+        assert_eq!(joined.bit_depth(), 100);
+        assert_eq!(
+            joined.samples(),
+            [0, 0, 0, 100, 100, 100, 50, 50, 50, 100, 50, 0]
+        );
+
+        let joined = concat(
+            &[bits.clone(), bits.clone()],
+            Direction::Vertical,
+            Align::Start,
+            &[0],
+        )
+        .expect("concat");
+        assert_eq!(joined.info().format, NetpbmFormat::PBMRaw);
+
+        let info = Info::new_pam(1, 1, 255, 2).expect("pam");
+        let pam = Image::new(info, TypeInfo::Empty, vec![1, 2]).expect("pam");
+        assert!(matches!(
+            concat(
+                &[gray, pam.clone()],
+                Direction::Horizontal,
+                Align::Start,
+                &[0, 0]
+            ),
+            Err(NetpbmError::ChannelMismatch { .. })
+        ));
+        let info = Info::new_pam(1, 1, 100, 2).expect("pam");
+        let other = Image::new(info, TypeInfo::Empty, vec![1, 2]).expect("pam");
+        assert!(matches!(
+            concat(&[pam, other], Direction::Horizontal, Align::Start, &[0, 0]),
+            Err(NetpbmError::BitDepthMismatch { .. })
+        ));
+        assert!(concat(&[], Direction::Horizontal, Align::Start, &[0]).is_err());
+    }
+
+    #[test]
+    fn test_stack() {
+        let gray = pgm(2, 1, 255, vec![1, 2]);
+        let color = ppm(2, 1, 255, vec![3, 4, 5, 6, 7, 8]);
+
+        let type_info = TypeInfo::Info(vec!["RGB_ALPHA".to_string()]);
+        let stacked = stack(&[color, gray.clone()], type_info.clone()).expect("stack");
+        assert_eq!(stacked.info().format, NetpbmFormat::PAM);
+        assert_eq!(stacked.channels(), 4);
+        assert_eq!(stacked.samples(), [3, 4, 5, 1, 6, 7, 8, 2]);
+        assert_eq!(stacked.type_info(), &type_info);
+
+        assert!(matches!(
+            stack(&[gray.clone(), pgm(1, 2, 255, vec![1, 2])], TypeInfo::Empty),
+            Err(NetpbmError::DimensionMismatch { .. })
+        ));
+        assert!(matches!(
+            stack(&[gray, pgm(2, 1, 100, vec![1, 2])], TypeInfo::Empty),
+            Err(NetpbmError::BitDepthMismatch { .. })
+        ));
+        assert!(stack(&[], TypeInfo::Empty).is_err());
+    }
+}
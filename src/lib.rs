@@ -1,14 +1,51 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::string::{String, ToString};
+use core::array::TryFromSliceError;
+use core::fmt;
 use formats::*;
-use std::array::TryFromSliceError;
+#[cfg(feature = "std")]
 use std::error::Error;
-use std::fmt;
-use std::io;
 
+pub mod arith;
+pub mod bitmap;
+pub mod components;
+pub mod contrast;
+pub mod convert;
+pub mod convolve;
+pub mod draw;
+pub mod encode;
 pub mod formats;
+pub mod generate;
+pub mod image;
+#[cfg(feature = "image")]
+pub mod image_compat;
+pub mod indexed;
+pub mod io;
+pub mod layout;
+mod math;
+pub mod metrics;
+pub mod morphology;
+#[cfg(feature = "ndarray")]
+pub mod ndarray_compat;
 pub mod pam;
 pub mod pbm;
+pub mod pfm;
 pub mod pgm;
 pub mod ppm;
+pub mod quantize;
+pub mod scale;
+pub mod stats;
+#[cfg(test)]
+mod test_support;
+pub mod text;
+#[cfg(feature = "tokio")]
+pub mod tokio_compat;
+pub mod tone;
+pub mod transform;
+pub mod view;
 
 /// Encoding type refers to whether the netpbm image is
 /// `raw` or `plain`.
@@ -16,6 +53,7 @@ pub mod ppm;
 /// Although never specified, PAM is considered `raw`.
 ///
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum EncodingType {
     /// Sample data is serialized as bytes.
     ///
@@ -57,8 +95,31 @@ pub enum NetpbmError {
     },
     /// Encoding or decoding operation failed.
     IOOperationFailed { info: String },
+    /// The header could not be parsed.
+    MalformedHeader { offset: usize, info: String },
+    /// The data ended before the image was complete.
+    UnexpectedEof { offset: usize },
+    /// The raster could not be parsed.
+    MalformedRaster { offset: usize, info: String },
+    /// An argument to an image operation is out of range.
+    InvalidArgument { info: String },
+    /// Images combined by an operation have different channel depths.
+    ChannelMismatch {
+        expected: ChannelDepth,
+        found: ChannelDepth,
+    },
+    /// Images combined by an operation have different bit depths.
+    BitDepthMismatch { expected: BitDepth, found: BitDepth },
+    /// Images combined by an operation have different dimensions.
+    DimensionMismatch {
+        expected: (ImageDim, ImageDim),
+        found: (ImageDim, ImageDim),
+    },
+    /// A font file could not be parsed.
+    MalformedFont { line: usize, info: String },
 }
 
+#[cfg(feature = "std")]
 impl Error for NetpbmError {
     fn description(&self) -> &str {
         "netpbm error"
@@ -116,12 +177,64 @@ impl fmt::Display for NetpbmError {
             IOOperationFailed { ref info } => {
                 write!(f, "IO operation failed: {}", info)
             }
+            MalformedHeader {
+                ref offset,
+                ref info,
+            } => {
+                write!(f, "Malformed header at byte [{}]: {}", offset, info)
+            }
+            UnexpectedEof { ref offset } => {
+                write!(f, "Unexpected end of data at byte [{}]", offset)
+            }
+            MalformedRaster {
+                ref offset,
+                ref info,
+            } => {
+                write!(f, "Malformed raster at byte [{}]: {}", offset, info)
+            }
+            InvalidArgument { ref info } => {
+                write!(f, "Invalid argument: {}", info)
+            }
+            ChannelMismatch {
+                ref expected,
+                ref found,
+            } => {
+                write!(
+                    f,
+                    "Channel depth {} does not match the expected channel depth {}",
+                    found, expected
+                )
+            }
+            BitDepthMismatch {
+                ref expected,
+                ref found,
+            } => {
+                write!(
+                    f,
+                    "Bit depth {} does not match the expected bit depth {}",
+                    found, expected
+                )
+            }
+            DimensionMismatch {
+                ref expected,
+                ref found,
+            } => {
+                write!(
+                    f,
+                    "Image dimensions ({}, {}) do not match the expected dimensions ({}, {})",
+                    found.0, found.1, expected.0, expected.1
+                )
+            }
+            MalformedFont { ref line, ref info } => {
+                write!(f, "Malformed font at line [{}]: {}", line, info)
+            }
         }
     }
 }
 
-impl From<io::Error> for NetpbmError {
-    fn from(err: io::Error) -> NetpbmError {
+#[cfg(feature = "std")]
+impl From<std::io::Error> for NetpbmError {
+    fn from(err: std::io::Error) -> NetpbmError {
         NetpbmError::IOOperationFailed {
             info: err.to_string(),
         }
@@ -0,0 +1,76 @@
+//! Floating point functions for the processing modules.
+//!
+//! `core` has no `round`, `sqrt`, or transcendental functions on
+//! `f64`. With the `std` feature, these forward to the inherent
+//! methods; without it, they use the `libm` crate, so scaling,
+//! drawing, and the other processing modules work on targets
+//! that only have `alloc`.
+//!
+
+macro_rules! forward {
+    ($($name:ident => $libm:ident,)*) => {
+        $(
+            #[inline]
+            pub fn $name(x: f64) -> f64 {
+                #[cfg(feature = "std")]
+                return x.$name();
+                #[cfg(not(feature = "std"))]
+                return libm::$libm(x);
+            }
+        )*
+    };
+}
+
+forward! {
+    ceil => ceil,
+    cos => cos,
+    exp => exp,
+    floor => floor,
+    ln => log,
+    log10 => log10,
+    round => round,
+    sin => sin,
+    sqrt => sqrt,
+}
+
+#[inline]
+pub fn hypot(x: f64, y: f64) -> f64 {
+    #[cfg(feature = "std")]
+    return x.hypot(y);
+    #[cfg(not(feature = "std"))]
+    return libm::hypot(x, y);
+}
+
+#[inline]
+pub fn powf(x: f64, n: f64) -> f64 {
+    #[cfg(feature = "std")]
+    return x.powf(n);
+    #[cfg(not(feature = "std"))]
+    return libm::pow(x, n);
+}
+
+#[inline]
+pub fn powi(x: f64, n: i32) -> f64 {
+    #[cfg(feature = "std")]
+    return x.powi(n);
+    #[cfg(not(feature = "std"))]
+    return libm::pow(x, f64::from(n));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_math() {
+        assert_eq!(round(2.5), 3.0);
+        assert_eq!(round(-2.5), -3.0);
+        assert_eq!(floor(-0.5), -1.0);
+        assert_eq!(ceil(0.25), 1.0);
+        assert_eq!(sqrt(16.0), 4.0);
+        assert_eq!(hypot(3.0, 4.0), 5.0);
+        assert_eq!(powi(3.0, 3), 27.0);
+        assert_eq!(powf(4.0, 0.5), 2.0);
+        assert_eq!(ln(1.0), 0.0);
+    }
+}
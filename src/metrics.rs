@@ -0,0 +1,260 @@
+//! Image comparison metrics.
+//!
+//! PSNR and SSIM compare two images of the same width, height, and
+//! channel count, similar to netpbm's `pnmpsnr`. Samples are scaled
+//! by the bit depth of their image, so images with different bit
+//! depths can be compared.
+//!
+
+use crate::arith::check_shape;
+use crate::image::Image;
+use crate::math;
+use crate::{EncodingType, Info, NetpbmError, TypeInfo};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Peak signal-to-noise ratios in decibels.
+///
+/// Ratios of identical images are infinite.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Psnr {
+    /// The ratio of each channel.
+    pub channels: Vec<f64>,
+
+    /// The ratio over all channels.
+    pub combined: f64,
+}
+
+/// Style of a difference image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DiffStyle {
+    /// A PGM image, from black for no difference to white for the
+    /// largest difference.
+    Gray,
+
+    /// A PPM image, from black through red and yellow to white for
+    /// the largest difference.
+    Heat,
+}
+
+/// Compute the peak signal-to-noise ratio of two images.
+pub fn psnr(left: &Image, right: &Image) -> Result<Psnr, NetpbmError> {
+    check_shape(left.info(), right.info())?;
+
+    let channels = left.channels() as usize;
+    let mut errors = vec![0.0; channels];
+    for (i, (a, b)) in normalized(left).zip(normalized(right)).enumerate() {
+        errors[i % channels] += (a - b) * (a - b);
+    }
+
+    let count = (left.samples().len() / channels) as f64;
+    let ratio = |error: f64| -10.0 * math::log10(error);
+    Ok(Psnr {
+        combined: ratio(errors.iter().sum::<f64>() / (count * channels as f64)),
+        channels: errors.into_iter().map(|e| ratio(e / count)).collect(),
+    })
+}
+
+/// Compute the mean structural similarity of two images.
+///
+/// SSIM is computed over every 8 by 8 window in each channel, or
+/// over the whole channel for smaller images, and averaged. The
+/// result is 1 for identical images.
+///
+pub fn ssim(left: &Image, right: &Image) -> Result<f64, NetpbmError> {
+    const C1: f64 = 0.01 * 0.01;
+    const C2: f64 = 0.03 * 0.03;
+
+    check_shape(left.info(), right.info())?;
+    let (width, height) = (left.width() as usize, left.height() as usize);
+    let channels = left.channels() as usize;
+    let (window_x, window_y) = (width.min(8), height.min(8));
+    let a: Vec<f64> = normalized(left).collect();
+    let b: Vec<f64> = normalized(right).collect();
+
+    let mut total = 0.0;
+    for channel in 0..channels {
+        let sample =
+            |samples: &[f64], x: usize, y: usize| samples[(y * width + x) * channels + channel];
+        let sums = [
+            SummedArea::new(width, height, |x, y| sample(&a, x, y)),
+            SummedArea::new(width, height, |x, y| sample(&b, x, y)),
+            SummedArea::new(width, height, |x, y| math::powi(sample(&a, x, y), 2)),
+            SummedArea::new(width, height, |x, y| math::powi(sample(&b, x, y), 2)),
+            SummedArea::new(width, height, |x, y| sample(&a, x, y) * sample(&b, x, y)),
+        ];
+
+        let n = (window_x * window_y) as f64;
+        for y in 0..=height - window_y {
+            for x in 0..=width - window_x {
+                let [sa, sb, saa, sbb, sab] =
+                    sums.each_ref().map(|s| s.sum(x, y, window_x, window_y) / n);
+                let (var_a, var_b, covar) = (saa - sa * sa, sbb - sb * sb, sab - sa * sb);
+                total += ((2.0 * sa * sb + C1) * (2.0 * covar + C2))
+                    / ((sa * sa + sb * sb + C1) * (var_a + var_b + C2));
+            }
+        }
+    }
+
+    let windows = (width - window_x + 1) * (height - window_y + 1) * channels;
+    Ok(total / windows as f64)
+}
+
+/// Create an image of the differences between two images.
+///
+/// Each pixel shows the largest difference over its channels,
+/// scaled so that the largest difference in the image is the
+/// brightest.
+///
+pub fn diff_image(left: &Image, right: &Image, style: DiffStyle) -> Result<Image, NetpbmError> {
+    check_shape(left.info(), right.info())?;
+
+    let channels = left.channels() as usize;
+    let a: Vec<f64> = normalized(left).collect();
+    let b: Vec<f64> = normalized(right).collect();
+    let differences: Vec<f64> = a
+        .chunks_exact(channels)
+        .zip(b.chunks_exact(channels))
+        .map(|(a, b)| {
+            a.iter()
+                .zip(b)
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0, f64::max)
+        })
+        .collect();
+    let largest = differences.iter().copied().fold(0.0, f64::max);
+    let scale = if largest > 0.0 { 1.0 / largest } else { 0.0 };
+
+    let (width, height) = (left.width(), left.height());
+    let to_sample = |t: f64| math::round(t.clamp(0.0, 1.0) * 255.0) as u16;
+    let (info, samples) = match style {
+        DiffStyle::Gray => (
+            Info::new_pgm(EncodingType::Raw, width, height, 255)?,
+            differences.iter().map(|d| to_sample(d * scale)).collect(),
+        ),
+        DiffStyle::Heat => (
+            Info::new_ppm(EncodingType::Raw, width, height, 255)?,
+            differences
+                .iter()
+                .flat_map(|d| {
+                    let t = d * scale * 3.0;
+                    [to_sample(t), to_sample(t - 1.0), to_sample(t - 2.0)]
+                })
+                .collect(),
+        ),
+    };
+    Ok(Image::from_parts(info, TypeInfo::Empty, samples))
+}
+
+/// Determine whether two images have the same shape and bit depth,
+/// and samples that differ by at most `tolerance`.
+///
+/// This is meant for assertions in tests, such as
+/// `assert!(images_equal(&decoded, &golden, 1))`.
+///
+pub fn images_equal(left: &Image, right: &Image, tolerance: u16) -> bool {
+    let (a, b) = (left.info(), right.info());
+    (a.width, a.height, a.channels, a.bit_depth) == (b.width, b.height, b.channels, b.bit_depth)
+        && left
+            .samples()
+            .iter()
+            .zip(right.samples())
+            .all(|(&a, &b)| a.abs_diff(b) <= tolerance)
+}
+
+/// Iterate over the samples of an image as fractions of its bit depth.
+fn normalized(image: &Image) -> impl Iterator<Item = f64> + '_ {
+    let bit_depth = image.bit_depth() as f64;
+    image.samples().iter().map(move |&s| s as f64 / bit_depth)
+}
+
+/// A summed-area table, for sums over rectangles in constant time.
+struct SummedArea {
+    width: usize,
+    sums: Vec<f64>,
+}
+
+impl SummedArea {
+    fn new<F: Fn(usize, usize) -> f64>(width: usize, height: usize, value: F) -> Self {
+        // Sums have an extra row and column of zeros at the top left.
+        let stride = width + 1;
+        let mut sums = vec![0.0; stride * (height + 1)];
+        for y in 0..height {
+            for x in 0..width {
+                sums[(y + 1) * stride + x + 1] =
+                    value(x, y) + sums[y * stride + x + 1] + sums[(y + 1) * stride + x]
+                        - sums[y * stride + x];
+            }
+        }
+        SummedArea { width, sums }
+    }
+
+    /// Sum the values of a rectangle.
+    fn sum(&self, x: usize, y: usize, width: usize, height: usize) -> f64 {
+        let stride = self.width + 1;
+        let at = |x: usize, y: usize| self.sums[y * stride + x];
+        at(x + width, y + height) - at(x, y + height) - at(x + width, y) + at(x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate;
+    use crate::test_support::{pgm_info, pgm_row, ppm};
+
+    #[test]
+    fn test_psnr() {
+        let a = pgm_row(255, vec![0, 255, 100, 100]);
+        assert_eq!(psnr(&a, &a).expect("psnr").combined, f64::INFINITY);
+
+        // A mean squared error of 0.01 is 20 dB.
+        let a = pgm_row(10, vec![0, 0, 0, 0]);
+        let b = pgm_row(10, vec![2, 0, 0, 0]);
+        let result = psnr(&a, &b).expect("psnr");
+        assert!((result.combined - 20.0).abs() < 1e-9);
+
+        // Samples are compared as fractions of the bit depth.
+        let c = pgm_row(20, vec![4, 0, 0, 0]);
+        assert!((psnr(&a, &c).expect("psnr").combined - 20.0).abs() < 1e-9);
+
+        let a = ppm(1, 1, 10, vec![0, 0, 0]);
+        let b = ppm(1, 1, 10, vec![0, 1, 0]);
+        let result = psnr(&a, &b).expect("psnr");
+        assert_eq!(result.channels[0], f64::INFINITY);
+        assert!((result.channels[1] - 20.0).abs() < 1e-9);
+        assert!((result.combined - 24.771).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_ssim() {
+        let info = pgm_info(32, 32, 255);
+        let image = generate::uniform_noise(info.clone(), 1).expect("noise");
+        assert!((ssim(&image, &image).expect("ssim") - 1.0).abs() < 1e-9);
+
+        let other = generate::uniform_noise(info, 2).expect("noise");
+        let score = ssim(&image, &other).expect("ssim");
+        assert!(score < 0.1);
+
+        assert!(matches!(
+            ssim(&image, &pgm_row(255, vec![0])),
+            Err(NetpbmError::DimensionMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_diff_and_equal() {
+        let a = pgm_row(100, vec![0, 50, 100]);
+        let b = pgm_row(100, vec![0, 60, 80]);
+        let gray = diff_image(&a, &b, DiffStyle::Gray).expect("diff");
+        assert_eq!(gray.samples(), [0, 128, 255]);
+        let heat = diff_image(&a, &b, DiffStyle::Heat).expect("diff");
+        assert_eq!(heat.samples(), [0, 0, 0, 255, 128, 0, 255, 255, 255]);
+
+        assert!(images_equal(&a, &a, 0));
+        assert!(!images_equal(&a, &b, 10));
+        assert!(images_equal(&a, &b, 20));
+        assert!(!images_equal(&a, &pgm_row(255, vec![0, 50, 100]), 0));
+    }
+}
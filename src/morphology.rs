@@ -0,0 +1,567 @@
+//! Mathematical morphology.
+//!
+//! Bitmaps are eroded, dilated, opened, and closed directly on their
+//! byte-packed rows: each set cell of the structuring element shifts
+//! whole rows, which are combined a byte at a time. Black pixels are
+//! the foreground, so erosion shrinks black regions and dilation
+//! grows them.
+//!
+//! The grayscale operations take the minimum (erosion) or maximum
+//! (dilation) sample under a flat structuring element, for each
+//! channel of an image. Bright regions are the foreground.
+//!
+//! Pixels past the edges of the image do not affect erosion or
+//! dilation, so erosion does not eat into regions that touch an
+//! edge. Closing treats them as background, so regions near an
+//! edge are not joined to it.
+//!
+
+use crate::bitmap::Bitmap;
+use crate::image::Image;
+use crate::layout::{self, CropMode, Padding, Rect};
+use crate::NetpbmError;
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Flat structuring element.
+///
+/// The origin is the cell placed over each pixel. It defaults to
+/// the center of the element.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Element {
+    width: u32,
+    height: u32,
+    origin: (u32, u32),
+    cells: Vec<bool>,
+}
+
+impl Element {
+    /// Create a structuring element from row-major cells.
+    pub fn new(width: u32, height: u32, cells: Vec<bool>) -> Result<Self, NetpbmError> {
+        if width == 0 || height == 0 || cells.len() != width as usize * height as usize {
+            return Err(NetpbmError::InvalidArgument {
+                info: format!(
+                    "Expected {} cells for a {}x{} structuring element, found {}",
+                    width as usize * height as usize,
+                    width,
+                    height,
+                    cells.len()
+                ),
+            });
+        }
+
+        Ok(Element {
+            width,
+            height,
+            origin: (width / 2, height / 2),
+            cells,
+        })
+    }
+
+    /// Create a structuring element from rows of text, where `1` or
+    /// `x` is a set cell and `0` or `.` is an unset cell.
+    pub fn from_pattern(rows: &[&str]) -> Result<Self, NetpbmError> {
+        let width = rows.first().map_or(0, |row| row.chars().count());
+        let mut cells = Vec::with_capacity(width * rows.len());
+        for row in rows {
+            if row.chars().count() != width {
+                return Err(NetpbmError::InvalidArgument {
+                    info: format!(
+                        "Pattern rows should be {} cells long, found {:?}",
+                        width, row
+                    ),
+                });
+            }
+            for c in row.chars() {
+                cells.push(match c {
+                    '1' | 'x' | 'X' => true,
+                    '0' | '.' => false,
+                    _ => {
+                        return Err(NetpbmError::InvalidArgument {
+                            info: format!("Invalid pattern cell {:?}", c),
+                        })
+                    }
+                });
+            }
+        }
+
+        Self::new(width as u32, rows.len() as u32, cells)
+    }
+
+    /// Create a rectangular structuring element.
+    pub fn rect(width: u32, height: u32) -> Result<Self, NetpbmError> {
+        Self::new(width, height, vec![true; width as usize * height as usize])
+    }
+
+    /// Create a plus-shaped structuring element with arms of the
+    /// given length.
+    pub fn cross(radius: u32) -> Result<Self, NetpbmError> {
+        let size = diameter(radius)?;
+        let cells = (0..size)
+            .flat_map(|y| (0..size).map(move |x| x == radius || y == radius))
+            .collect();
+        Self::new(size, size, cells)
+    }
+
+    /// Create a disk-shaped structuring element.
+    pub fn disk(radius: u32) -> Result<Self, NetpbmError> {
+        let size = diameter(radius)?;
+        let r = radius as i64;
+        let cells = (0..size as i64)
+            .flat_map(|y| (0..size as i64).map(move |x| (x - r).pow(2) + (y - r).pow(2) <= r * r))
+            .collect();
+        Self::new(size, size, cells)
+    }
+
+    /// Move the origin of the structuring element.
+    pub fn with_origin(mut self, x: u32, y: u32) -> Result<Self, NetpbmError> {
+        if x >= self.width || y >= self.height {
+            return Err(NetpbmError::InvalidArgument {
+                info: format!(
+                    "Origin ({}, {}) is outside the {}x{} structuring element",
+                    x, y, self.width, self.height
+                ),
+            });
+        }
+        self.origin = (x, y);
+        Ok(self)
+    }
+
+    /// Get the element width.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Get the element height.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Get the origin of the element.
+    pub fn origin(&self) -> (u32, u32) {
+        self.origin
+    }
+
+    /// Determine whether the cell at `(x, y)` is set.
+    pub fn get(&self, x: u32, y: u32) -> bool {
+        assert!(x < self.width && y < self.height, "cell out of bounds");
+        self.cells[y as usize * self.width as usize + x as usize]
+    }
+
+    /// Get the offsets of the set cells from the origin.
+    fn offsets(&self) -> impl Iterator<Item = (i64, i64)> + '_ {
+        let (ox, oy) = (self.origin.0 as i64, self.origin.1 as i64);
+        let width = self.width as usize;
+        self.cells
+            .iter()
+            .enumerate()
+            .filter(|(_, &set)| set)
+            .map(move |(i, _)| ((i % width) as i64 - ox, (i / width) as i64 - oy))
+    }
+}
+
+/// Erode the black regions of a bitmap.
+pub fn erode(bitmap: &Bitmap, element: &Element) -> Bitmap {
+    apply(bitmap, element, true, true)
+}
+
+/// Dilate the black regions of a bitmap.
+pub fn dilate(bitmap: &Bitmap, element: &Element) -> Bitmap {
+    apply(bitmap, element, false, false)
+}
+
+/// Open a bitmap, which removes black details smaller than the
+/// structuring element.
+pub fn open(bitmap: &Bitmap, element: &Element) -> Bitmap {
+    dilate(&erode(bitmap, element), element)
+}
+
+/// Close a bitmap, which fills white details smaller than the
+/// structuring element.
+pub fn close(bitmap: &Bitmap, element: &Element) -> Bitmap {
+    // Dilation can grow regions past the edges, where the erosion
+    // must see them, so close a bitmap with a white border.
+    let (px, py) = extent(element);
+    let (width, height) = (bitmap.width(), bitmap.height());
+    let padded = reframe(
+        bitmap,
+        width + 2 * px,
+        height + 2 * py,
+        -(px as i64),
+        -(py as i64),
+    );
+    let closed = erode(&dilate(&padded, element), element);
+    reframe(&closed, width, height, px as i64, py as i64)
+}
+
+/// Find the pixels where every cell of `hits` is black and every
+/// cell of `misses` is white.
+///
+/// Pixels past the edges of the bitmap count as white.
+///
+pub fn hit_or_miss(bitmap: &Bitmap, hits: &Element, misses: &Element) -> Bitmap {
+    let inverse = bitmap.as_bytes().iter().map(|b| !b).collect();
+    let inverse =
+        Bitmap::from_packed(bitmap.width(), bitmap.height(), inverse).expect("Inverse bitmap");
+
+    let hit = apply(bitmap, hits, true, false);
+    let miss = apply(&inverse, misses, true, true);
+    let data = hit
+        .as_bytes()
+        .iter()
+        .zip(miss.as_bytes())
+        .map(|(h, m)| h & m)
+        .collect();
+    Bitmap::from_packed(bitmap.width(), bitmap.height(), data).expect("Hit-or-miss bitmap")
+}
+
+/// Erode each channel of an image.
+pub fn erode_gray(image: &Image, element: &Element) -> Image {
+    apply_gray(image, element, true)
+}
+
+/// Dilate each channel of an image.
+pub fn dilate_gray(image: &Image, element: &Element) -> Image {
+    apply_gray(image, element, false)
+}
+
+/// Open each channel of an image, which removes bright details
+/// smaller than the structuring element.
+pub fn open_gray(image: &Image, element: &Element) -> Image {
+    dilate_gray(&erode_gray(image, element), element)
+}
+
+/// Close each channel of an image, which removes dark details
+/// smaller than the structuring element.
+pub fn close_gray(image: &Image, element: &Element) -> Image {
+    // As with bitmaps, close the image with a black border.
+    let (px, py) = extent(element);
+    let padding = Padding {
+        left: px,
+        right: px,
+        top: py,
+        bottom: py,
+    };
+    let fill = vec![0; image.channels() as usize];
+    let padded = layout::pad(image, padding, &fill).expect("Padded image");
+    let closed = erode_gray(&dilate_gray(&padded, element), element);
+    let rect = Rect::new(px as i64, py as i64, image.width(), image.height());
+    layout::crop(&closed, rect, &CropMode::Strict).expect("Closed image")
+}
+
+/// Get the side of a square element with the given radius.
+fn diameter(radius: u32) -> Result<u32, NetpbmError> {
+    radius
+        .checked_mul(2)
+        .and_then(|d| d.checked_add(1))
+        .ok_or_else(|| NetpbmError::InvalidArgument {
+            info: format!("Structuring element radius {} is too large", radius),
+        })
+}
+
+/// Get the largest horizontal and vertical offsets of an element.
+fn extent(element: &Element) -> (u32, u32) {
+    element.offsets().fold((0, 0), |(px, py), (dx, dy)| {
+        (
+            px.max(dx.unsigned_abs() as u32),
+            py.max(dy.unsigned_abs() as u32),
+        )
+    })
+}
+
+/// Erode or dilate a bitmap on its packed rows.
+///
+/// Erosion ANDs the rows shifted by each offset, and dilation ORs
+/// the rows shifted by each reflected offset. `outside` is the
+/// color of the pixels past the edges.
+///
+fn apply(bitmap: &Bitmap, element: &Element, erode: bool, outside: bool) -> Bitmap {
+    let (width, height) = (bitmap.width(), bitmap.height());
+    let row_len = bitmap.row_len();
+    let fill = if outside { 0xFF } else { 0x00 };
+
+    // Padding bits are past the right edge, so give them the outside color.
+    let mut source = bitmap.as_bytes().to_vec();
+    if outside {
+        let padding = !(0xFFu8 << ((8 - width % 8) % 8));
+        for row in source.chunks_exact_mut(row_len) {
+            row[row_len - 1] |= padding;
+        }
+    }
+
+    let mut data = vec![if erode { 0xFF } else { 0x00 }; source.len()];
+    let outside_row = vec![fill; row_len];
+    for (dx, dy) in element.offsets() {
+        let (dx, dy) = if erode { (dx, dy) } else { (-dx, -dy) };
+        for (y, out) in data.chunks_exact_mut(row_len).enumerate() {
+            let sy = y as i64 + dy;
+            let row = if (0..height as i64).contains(&sy) {
+                &source[sy as usize * row_len..(sy as usize + 1) * row_len]
+            } else {
+                &outside_row
+            };
+
+            for (i, byte) in out.iter_mut().enumerate() {
+                let shifted = shifted_byte(row, i as i64 * 8 + dx, fill);
+                if erode {
+                    *byte &= shifted;
+                } else {
+                    *byte |= shifted;
+                }
+            }
+        }
+    }
+
+    Bitmap::from_packed(width, height, data).expect("Morphology bitmap")
+}
+
+/// Create a bitmap whose pixel `(x, y)` is the pixel
+/// `(x + dx, y + dy)` of another bitmap, or white past its edges.
+fn reframe(bitmap: &Bitmap, width: u32, height: u32, dx: i64, dy: i64) -> Bitmap {
+    let mut out = Bitmap::new(width, height).expect("Reframed bitmap");
+    for y in 0..height {
+        let sy = y as i64 + dy;
+        if !(0..bitmap.height() as i64).contains(&sy) {
+            continue;
+        }
+        let row = bitmap.row(sy as u32);
+        for (i, byte) in out.row_mut(y).iter_mut().enumerate() {
+            *byte = shifted_byte(row, i as i64 * 8 + dx, 0);
+        }
+    }
+    out.clear_padding();
+    out
+}
+
+/// Get the 8 bits of a packed row starting at bit `start`. Bits
+/// past either end of the row are taken from `fill`.
+fn shifted_byte(row: &[u8], start: i64, fill: u8) -> u8 {
+    let get = |i: i64| {
+        if (0..row.len() as i64).contains(&i) {
+            row[i as usize]
+        } else {
+            fill
+        }
+    };
+
+    let (index, bit) = (start.div_euclid(8), start.rem_euclid(8));
+    if bit == 0 {
+        get(index)
+    } else {
+        (get(index) << bit) | (get(index + 1) >> (8 - bit))
+    }
+}
+
+/// Take the minimum or maximum of each channel under a flat
+/// structuring element.
+fn apply_gray(image: &Image, element: &Element, erode: bool) -> Image {
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let offsets: Vec<(i64, i64)> = element
+        .offsets()
+        .map(|(dx, dy)| if erode { (dx, dy) } else { (-dx, -dy) })
+        .collect();
+
+    // Each tuple starts from the identity of the minimum or maximum,
+    // so only the set cells count, as with bitmaps. A pixel with no
+    // set cell inside the image keeps its value.
+    let identity = if erode { image.bit_depth() } else { 0 };
+    let mut out = image.clone();
+    for y in 0..height {
+        for x in 0..width {
+            let result = out.tuple_mut(x as u32, y as u32);
+            let mut visited = false;
+            for (dx, dy) in &offsets {
+                let (sx, sy) = (x + dx, y + dy);
+                if !(0..width).contains(&sx) || !(0..height).contains(&sy) {
+                    continue;
+                }
+                if !visited {
+                    result.fill(identity);
+                    visited = true;
+                }
+                let tuple = image.tuple(sx as u32, sy as u32);
+                for (r, &s) in result.iter_mut().zip(tuple) {
+                    *r = if erode { (*r).min(s) } else { (*r).max(s) };
+                }
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{bitmap, pgm};
+    use crate::EncodingType;
+
+    /// Erode or dilate one pixel at a time.
+    fn reference(bitmap: &Bitmap, element: &Element, erode: bool) -> Bitmap {
+        let (width, height) = (bitmap.width() as i64, bitmap.height() as i64);
+        let mut out = Bitmap::new(bitmap.width(), bitmap.height()).expect("bitmap");
+        for y in 0..height {
+            for x in 0..width {
+                let mut value = erode;
+                for (dx, dy) in element.offsets() {
+                    let (sx, sy) = if erode {
+                        (x + dx, y + dy)
+                    } else {
+                        (x - dx, y - dy)
+                    };
+                    let pixel = if (0..width).contains(&sx) && (0..height).contains(&sy) {
+                        bitmap.get(sx as u32, sy as u32)
+                    } else {
+                        erode
+                    };
+                    value = if erode {
+                        value && pixel
+                    } else {
+                        value || pixel
+                    };
+                }
+                out.set(x as u32, y as u32, value);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_packed_matches_reference() {
+        let mut image = Bitmap::new(21, 9).expect("bitmap");
+        for y in 0..9 {
+            for x in 0..21 {
+                image.set(x, y, (x * 5 + y * 11) % 7 < 4);
+            }
+        }
+
+        let asymmetric = Element::from_pattern(&["x..", "xx.", "..x"])
+            .expect("element")
+            .with_origin(0, 1)
+            .expect("origin");
+        for element in [
+            Element::rect(9, 1).expect("rect"),
+            Element::disk(2).expect("disk"),
+            asymmetric,
+        ] {
+            assert_eq!(erode(&image, &element), reference(&image, &element, true));
+            assert_eq!(dilate(&image, &element), reference(&image, &element, false));
+        }
+    }
+
+    #[test]
+    fn test_open_close() {
+        let image = bitmap(&[
+            "..........",
+            ".xxxx.....",
+            ".xxxx...x.",
+            ".x.xx.....",
+            ".xxxx.....",
+        ]);
+        let element = Element::rect(2, 2).expect("rect");
+
+        // Opening removes the specks and fills nothing.
+        assert_eq!(
+            open(&image, &element),
+            bitmap(&[
+                "..........",
+                ".xxxx.....",
+                ".xxxx.....",
+                "...xx.....",
+                "...xx.....",
+            ])
+        );
+
+        // Closing fills the hole and keeps the specks.
+        assert_eq!(
+            close(&image, &element),
+            bitmap(&[
+                "..........",
+                ".xxxx.....",
+                ".xxxx...x.",
+                ".xxxx.....",
+                ".xxxx.....",
+            ])
+        );
+    }
+
+    #[test]
+    fn test_hit_or_miss() {
+        let image = bitmap(&["x...x", "..x.x", "....."]);
+
+        // Find isolated black pixels.
+        let hits = Element::from_pattern(&["...", ".x.", "..."]).expect("hits");
+        let misses = Element::from_pattern(&["xxx", "x.x", "xxx"]).expect("misses");
+        assert_eq!(
+            hit_or_miss(&image, &hits, &misses),
+            bitmap(&["x....", "..x..", "....."])
+        );
+
+        assert!(Element::from_pattern(&["x.", "x"]).is_err());
+        assert!(Element::from_pattern(&["x?"]).is_err());
+        assert!(Element::rect(3, 3)
+            .expect("rect")
+            .with_origin(3, 0)
+            .is_err());
+    }
+
+    #[test]
+    fn test_gray() {
+        let image = pgm(5, 1, 1000, vec![5, 900, 7, 3, 1000]);
+        let element = Element::rect(3, 1).expect("rect");
+
+        assert_eq!(erode_gray(&image, &element).samples(), [5, 5, 3, 3, 3]);
+        assert_eq!(
+            dilate_gray(&image, &element).samples(),
+            [900, 900, 900, 1000, 1000]
+        );
+        assert_eq!(open_gray(&image, &element).samples(), [5, 5, 5, 3, 3]);
+        assert_eq!(
+            close_gray(&image, &element).samples(),
+            [5, 900, 900, 900, 1000]
+        );
+
+        // The origin of the element is unset, so it does not count.
+        let gaps = Element::from_pattern(&["x.x"]).expect("gaps");
+        let image = pgm(3, 1, 1000, vec![5, 900, 7]);
+        assert_eq!(dilate_gray(&image, &gaps).samples(), [900, 7, 900]);
+        assert_eq!(erode_gray(&image, &gaps).samples(), [900, 5, 900]);
+
+        assert!(Element::cross(u32::MAX / 2 + 1).is_err());
+        assert!(Element::disk(u32::MAX).is_err());
+    }
+
+    #[test]
+    fn test_gray_matches_packed() {
+        let mut image = Bitmap::new(21, 9).expect("bitmap");
+        for y in 0..9 {
+            for x in 0..21 {
+                image.set(x, y, (x * 3 + y * 7) % 5 < 2);
+            }
+        }
+        let gray = pgm(
+            21,
+            9,
+            1,
+            image.to_image(EncodingType::Raw).samples().to_vec(),
+        );
+
+        let rows = Element::from_pattern(&["x", ".", "x"]).expect("rows");
+        for element in [Element::from_pattern(&["x.x"]).expect("gaps"), rows] {
+            assert_eq!(
+                erode_gray(&gray, &element).samples(),
+                erode(&image, &element)
+                    .to_image(EncodingType::Raw)
+                    .samples()
+            );
+            assert_eq!(
+                dilate_gray(&gray, &element).samples(),
+                dilate(&image, &element)
+                    .to_image(EncodingType::Raw)
+                    .samples()
+            );
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! Interoperability with the `ndarray` crate.
+//!
+//! Enabled by the `ndarray` feature. Images decode into arrays
+//! of shape `(height, width)` for single-channel images, or
+//! `(height, width, channels)` for any image, with `u8` or `u16`
+//! samples. Samples are copied as is, so PBM samples are 1 for
+//! black.
+//!
+//! Arrays encode with the PGM, PPM, and PAM encoders. The width,
+//! height, and channel count come from the shape of the array,
+//! and the bit depth is the largest value of the sample type.
+//! Views of any layout can be encoded, including transposed and
+//! strided views.
+//!
+
+use crate::image::Image;
+use crate::{pam, pgm, ppm};
+use crate::{EncodingType, Info, NetpbmError, TypeInfo};
+use ndarray::{Array2, Array3, ArrayView2, ArrayView3};
+use std::io;
+
+mod sealed {
+    pub trait Sealed {}
+    impl Sealed for u8 {}
+    impl Sealed for u16 {}
+}
+
+/// A sample type of an array, either `u8` or `u16`.
+pub trait Sample: Copy + sealed::Sealed {
+    /// The largest sample value, used as the bit depth of encoded
+    /// arrays.
+    const MAX: u16;
+
+    /// Convert a sample no larger than `MAX`.
+    fn from_u16(sample: u16) -> Self;
+
+    /// Convert a sample to `u16`.
+    fn to_u16(self) -> u16;
+}
+
+impl Sample for u8 {
+    const MAX: u16 = u8::MAX as u16;
+
+    fn from_u16(sample: u16) -> Self {
+        sample as u8
+    }
+
+    fn to_u16(self) -> u16 {
+        self as u16
+    }
+}
+
+impl Sample for u16 {
+    const MAX: u16 = u16::MAX;
+
+    fn from_u16(sample: u16) -> Self {
+        sample
+    }
+
+    fn to_u16(self) -> u16 {
+        self
+    }
+}
+
+/// Decode the first image from the given reader into an array of
+/// shape `(height, width)`.
+///
+/// The image must have one channel. `u8` arrays can only hold
+/// images with a bit depth up to 255.
+///
+pub fn read_array2<T: Sample, R: io::Read>(reader: R) -> Result<(Info, Array2<T>), NetpbmError> {
+    let image = Image::read(reader)?;
+    Ok((image.info().clone(), to_array2(&image)?))
+}
+
+/// Decode the first image from the given reader into an array of
+/// shape `(height, width, channels)`.
+///
+/// `u8` arrays can only hold images with a bit depth up to 255.
+///
+pub fn read_array3<T: Sample, R: io::Read>(reader: R) -> Result<(Info, Array3<T>), NetpbmError> {
+    let image = Image::read(reader)?;
+    Ok((image.info().clone(), to_array3(&image)?))
+}
+
+/// Copy the samples of a single-channel image into an array of
+/// shape `(height, width)`.
+pub fn to_array2<T: Sample>(image: &Image) -> Result<Array2<T>, NetpbmError> {
+    if image.channels() != 1 {
+        return Err(NetpbmError::InvalidArgument {
+            info: format!(
+                "Images with {} channels cannot be two-dimensional arrays",
+                image.channels()
+            ),
+        });
+    }
+    let shape = (image.height() as usize, image.width() as usize);
+    Ok(Array2::from_shape_vec(shape, samples(image)?).expect("shape matches the image"))
+}
+
+/// Copy the samples of an image into an array of shape
+/// `(height, width, channels)`.
+pub fn to_array3<T: Sample>(image: &Image) -> Result<Array3<T>, NetpbmError> {
+    let shape = (
+        image.height() as usize,
+        image.width() as usize,
+        image.channels() as usize,
+    );
+    Ok(Array3::from_shape_vec(shape, samples(image)?).expect("shape matches the image"))
+}
+
+/// Write an array of shape `(height, width)` as a PGM image.
+pub fn write_array2<T: Sample, W: io::Write>(
+    writer: W,
+    encoding: EncodingType,
+    array: ArrayView2<T>,
+) -> Result<(), NetpbmError> {
+    let (height, width) = array.dim();
+    let samples: Vec<u16> = array.iter().map(|s| s.to_u16()).collect();
+    pgm::Encoder::new(writer).write_wide(encoding, dim(width)?, dim(height)?, T::MAX, &samples)
+}
+
+/// Write an array of shape `(height, width, channels)`.
+///
+/// Arrays with 1 channel are written as PGM, arrays with 3
+/// channels as PPM, and others as PAM, which is always `raw`.
+///
+pub fn write_array3<T: Sample, W: io::Write>(
+    writer: W,
+    encoding: EncodingType,
+    array: ArrayView3<T>,
+) -> Result<(), NetpbmError> {
+    let (height, width, channels) = array.dim();
+    let (width, height, channels) = (dim(width)?, dim(height)?, dim(channels)?);
+    // Iteration is in logical order, whatever the memory layout.
+    let samples: Vec<u16> = array.iter().map(|s| s.to_u16()).collect();
+    match channels {
+        1 => pgm::Encoder::new(writer).write_wide(encoding, width, height, T::MAX, &samples),
+        3 => ppm::Encoder::new(writer).write_wide(encoding, width, height, T::MAX, &samples),
+        _ => pam::Encoder::new(writer).write_wide(
+            width,
+            height,
+            T::MAX,
+            channels,
+            &TypeInfo::Empty,
+            &samples,
+        ),
+    }
+}
+
+/// Convert the samples of an image to an array sample type.
+fn samples<T: Sample>(image: &Image) -> Result<Vec<T>, NetpbmError> {
+    if image.bit_depth() > T::MAX {
+        return Err(NetpbmError::InvalidArgument {
+            info: format!("Bit depth {} requires two-byte samples", image.bit_depth()),
+        });
+    }
+    Ok(image.samples().iter().map(|&s| T::from_u16(s)).collect())
+}
+
+/// Convert an array length to an image dimension.
+fn dim(len: usize) -> Result<u32, NetpbmError> {
+    u32::try_from(len).map_err(|_| NetpbmError::InvalidArgument {
+        info: format!("Array length {} is too large for an image", len),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{array, s, Axis};
+
+    #[test]
+    fn test_read_arrays() {
+        let (info, gray) = read_array2::<u8, _>(&b"P2 3 2 15 0 1 2 3 4 15"[..]).expect("pgm");
+        assert_eq!(info.bit_depth.value(), 15);
+        assert_eq!(gray, array![[0, 1, 2], [3, 4, 15]]);
+
+        let data = b"P6 2 1 1000 \x00\x01\x00\x02\x00\x03\x03\xe8\x00\x00\x00\x00";
+        let (_, color) = read_array3::<u16, _>(&data[..]).expect("ppm");
+        assert_eq!(color.dim(), (1, 2, 3));
+        assert_eq!(color.slice(s![0, .., 0]), array![1, 1000]);
+        assert!(matches!(
+            read_array3::<u8, _>(&data[..]),
+            Err(NetpbmError::InvalidArgument { .. })
+        ));
+        assert!(read_array2::<u16, _>(&data[..]).is_err());
+    }
+
+    #[test]
+    fn test_write_views() {
+        let array: Array3<u8> =
+            Array3::from_shape_fn((2, 3, 2), |(y, x, c)| (y * 6 + x * 2 + c) as u8);
+        let mut out = Vec::new();
+        write_array3(&mut out, EncodingType::Raw, array.view()).expect("pam");
+        let (info, back) = read_array3::<u8, _>(&out[..]).expect("pam");
+        assert_eq!(info.channels.value(), 2);
+        assert_eq!(back, array);
+
+        // A transposed, strided view is written in logical order.
+        let view = array.index_axis(Axis(2), 1);
+        let view = view.t();
+        let view = view.slice(s![..;2, ..]);
+        let mut out = Vec::new();
+        write_array2(&mut out, EncodingType::Plain, view).expect("pgm");
+        let (_, back) = read_array2::<u8, _>(&out[..]).expect("pgm");
+        assert_eq!(back, array![[1, 7], [5, 11]]);
+    }
+}
@@ -24,9 +24,15 @@
 //! similar what is done with the `raw` format of
 //! PBM, PGM, and PPM. The PAM format uses the magic number `P7`.
 
+#[cfg(feature = "std")]
+use crate::formats::decode::{self, Scanner};
+use crate::io;
 use crate::Info;
+#[cfg(feature = "std")]
+use crate::NetpbmFormat;
 use crate::{NetpbmError, TypeInfo};
-use std::io;
+use alloc::format;
+use alloc::vec::Vec;
 
 /// PAM encoder.
 #[derive(Debug)]
@@ -115,11 +121,71 @@ impl<W: io::Write> Encoder<W> {
     }
 }
 
-// /// PAM decoder.
-// #[derive(Debug)]
-// pub struct Decoder<R: io::Read> {
-//     reader: R,
-// }
+/// PAM decoder.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct Decoder<R: io::Read> {
+    reader: R,
+}
+
+#[cfg(feature = "std")]
+impl<R: io::Read> Decoder<R> {
+    /// Create a new PAM decoder with the given reader.
+    pub fn new(reader: R) -> Self {
+        Decoder { reader }
+    }
+
+    /// Read one PAM image with one-byte samples into the provided
+    /// buffer and fill the `Info` metadata struct and
+    /// the `TypeInfo`.
+    ///
+    /// The buffer is cleared before samples are written to it.
+    /// Images with a bit depth greater than 255 must be read
+    /// with `read_wide`.
+    ///
+    pub fn read(&mut self, buf: &mut Vec<u8>) -> Result<(Info, TypeInfo), NetpbmError> {
+        let ((info, type_info), samples) = self.read_samples()?;
+        if info.bit_depth.is_multi_byte() {
+            return Err(NetpbmError::IOOperationFailed {
+                info: format!("Bit depth {} requires two-byte samples", info.bit_depth),
+            });
+        }
+
+        buf.clear();
+        buf.extend(samples.into_iter().map(|s| s as u8));
+
+        Ok((info, type_info))
+    }
+
+    /// Read one PAM image with two-byte samples into the provided
+    /// buffer and fill the `Info` metadata struct and
+    /// the `TypeInfo`.
+    ///
+    /// The buffer is cleared before samples are written to it.
+    ///
+    pub fn read_wide(&mut self, buf: &mut Vec<u16>) -> Result<(Info, TypeInfo), NetpbmError> {
+        let ((info, type_info), samples) = self.read_samples()?;
+        *buf = samples;
+        Ok((info, type_info))
+    }
+
+    /// Read the header and samples of one PAM image.
+    fn read_samples(&mut self) -> Result<((Info, TypeInfo), Vec<u16>), NetpbmError> {
+        let mut img_buf = Vec::new();
+        self.reader.read_to_end(&mut img_buf)?;
+
+        let mut scanner = Scanner::new(&img_buf);
+        let (info, type_info) = decode::read_header(&mut scanner)?;
+        if !matches!(info.format, NetpbmFormat::PAM) {
+            return Err(NetpbmError::IOOperationFailed {
+                info: format!("Invalid magic number: {}", info.format.magic()),
+            });
+        }
+        let samples = decode::read_samples(&mut scanner, &info)?;
+
+        Ok(((info, type_info), samples))
+    }
+}
 
 // impl<R: io::Read> Decoder<R> {
 //     /// Create a new PAM decoder with the given reader.
@@ -128,7 +194,7 @@ impl<W: io::Write> Encoder<W> {
 //     }
 // }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
 
@@ -198,4 +264,38 @@ mod tests {
         assert!(res.is_ok());
         assert_eq!(enc.writer.buffer[..], expected[..]);
     }
+
+    #[test]
+    fn test_read_pam() {
+        let data: Vec<u16> = vec![1056, 0, 0, 10, 0, 1056, 0, 10];
+        let type_info = TypeInfo::Info(vec!["RGB_ALPHA".to_string()]);
+        let mut enc = Encoder::new(ImageBuffer::new());
+        enc.write_wide(2, 1, 2048, 4, &type_info, &data)
+            .expect("write");
+
+        let mut dec = Decoder::new(&enc.writer.buffer[..]);
+        let mut buf = Vec::new();
+        let (info, read_type_info) = dec.read_wide(&mut buf).expect("read");
+        assert_eq!(info, Info::new_pam(2, 1, 2048, 4).expect("pam"));
+        assert_eq!(read_type_info, type_info);
+        assert_eq!(buf, data);
+    }
+
+    #[test]
+    fn test_read_pam_header() {
+        let header = b"P7\n# comment\nWIDTH 1\nHEIGHT 1\nDEPTH 2\nMAXVAL 3\nENDHDR\n\x01\x03";
+        let mut dec = Decoder::new(&header[..]);
+        let mut buf = Vec::new();
+        let (info, type_info) = dec.read(&mut buf).expect("read");
+        assert_eq!(info, Info::new_pam(1, 1, 3, 2).expect("pam"));
+        assert_eq!(type_info, TypeInfo::Empty);
+        assert_eq!(buf, [1, 3]);
+
+        let header = b"P7\nWIDTH 1\nHEIGHT 1\nMAXVAL 3\nENDHDR\n\x01";
+        let mut dec = Decoder::new(&header[..]);
+        assert!(matches!(
+            dec.read(&mut buf),
+            Err(NetpbmError::MalformedHeader { .. })
+        ));
+    }
 }
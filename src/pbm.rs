@@ -13,10 +13,17 @@
 //! The `plain` format uses the magic number `P1`.
 //!
 
-use std::io;
-
-use crate::formats::decode;
-use crate::{EncodingType, Info, MagicNumber, NetpbmError, NetpbmFormat};
+use crate::io;
+use alloc::borrow::ToOwned;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+use crate::formats::decode::{self, Scanner};
+use crate::{EncodingType, Info, NetpbmError};
+#[cfg(feature = "std")]
+use crate::{MagicNumber, NetpbmFormat};
 
 /// PBM encoder.
 #[derive(Debug)]
@@ -53,13 +60,56 @@ impl<W: io::Write> Encoder<W> {
         }
     }
 
+    /// Write one PBM image from byte-packed rows in either `raw`
+    /// or `plain` format.
+    ///
+    /// Each row is `ceil(width / 8)` bytes long, with the leftmost
+    /// pixel in the most significant bit. This is the `raw` raster
+    /// layout, so `raw` images are written without unpacking.
+    ///
+    pub fn write_packed(
+        &mut self,
+        encoding: EncodingType,
+        width: u32,
+        height: u32,
+        packed: &[u8],
+    ) -> Result<(), NetpbmError> {
+        let info = Info::new_pbm(encoding, width, height)?;
+        let row_len = (width as usize).div_ceil(8);
+        if packed.len() != row_len * height as usize {
+            return Err(NetpbmError::MalformedInitArray {
+                data_size: packed.len(),
+                width: info.width,
+                height: info.height,
+            });
+        }
+
+        match encoding {
+            EncodingType::Raw => {
+                let mut buf = Self::build_header(&info);
+                buf.extend(packed);
+                self.writer.write_all(&buf)?;
+                Ok(())
+            }
+            EncodingType::Plain => {
+                let samples: Vec<u8> = packed
+                    .chunks_exact(row_len)
+                    .flat_map(|row| (0..width as usize).map(|x| (row[x / 8] >> (7 - x % 8)) & 1))
+                    .collect();
+                self.write_plain(&info, &samples)
+            }
+        }
+    }
+
     /// Write a PBM image with `raw` encoding.
     fn write_raw(&mut self, info: &Info, samples: &[u8]) -> Result<(), NetpbmError> {
         let mut buf = Self::build_header(info);
 
-        // Pack bytes. Add right-side padding if there are remainder bits.
+        // Pack bytes. Each row is packed separately, with right-side
+        // padding if there are remainder bits.
         let packed_bytes = samples
-            .chunks(8)
+            .chunks(info.width.value() as usize)
+            .flat_map(|row| row.chunks(8))
             .map(|x| x.iter().enumerate().fold(0, |a, (i, b)| a | (b << (7 - i))));
         buf.extend(packed_bytes);
 
@@ -103,30 +153,59 @@ impl<W: io::Write> Encoder<W> {
 }
 
 /// PBM decoder.
+#[cfg(feature = "std")]
 #[derive(Debug)]
 pub struct Decoder<R: io::Read> {
     reader: R,
 }
 
+#[cfg(feature = "std")]
 impl<R: io::Read> Decoder<R> {
     /// Create a new PBM decoder with the given reader.
     pub fn new(reader: R) -> Self {
         Decoder { reader }
     }
 
-    /// Read from the provided buffer and fill the `Info` metadata struct.
-    pub fn read(&mut self, buf: &mut [u8]) -> Result<Info, NetpbmError> {
+    /// Read one PBM image into the provided buffer and fill the `Info` metadata struct.
+    ///
+    /// The buffer is cleared before samples are written to it.
+    /// Bits are unpacked to one sample per pixel.
+    ///
+    pub fn read(&mut self, buf: &mut Vec<u8>) -> Result<Info, NetpbmError> {
+        let (info, samples) = self.read_raster(decode::read_samples)?;
+
+        buf.clear();
+        buf.extend(samples.into_iter().map(|s| s as u8));
+
+        Ok(info)
+    }
+
+    /// Read one PBM image into the provided buffer as byte-packed
+    /// rows and fill the `Info` metadata struct.
+    ///
+    /// The buffer is replaced by the packed rows, using the layout
+    /// described in `Encoder::write_packed`. Padding bits are zero.
+    ///
+    pub fn read_packed(&mut self, buf: &mut Vec<u8>) -> Result<Info, NetpbmError> {
+        let (info, packed) = self.read_raster(decode::read_packed_samples)?;
+        *buf = packed;
+        Ok(info)
+    }
+
+    /// Read the header of one PBM image and its raster with the given function.
+    fn read_raster<T>(
+        &mut self,
+        read: fn(&mut Scanner, &Info) -> Result<T, NetpbmError>,
+    ) -> Result<(Info, T), NetpbmError> {
         let mut img_buf = Vec::new();
         self.reader.read_to_end(&mut img_buf)?;
 
-        let magic_number: [u8; 2] = img_buf[0..2].try_into()?;
-        let format = match MagicNumber::from_bytes(&magic_number) {
-            Some(magic_number) if magic_number == NetpbmFormat::PBMRaw.magic() => {
-                NetpbmFormat::PBMRaw
-            }
-            Some(magic_number) if magic_number == NetpbmFormat::PBMPlain.magic() => {
-                NetpbmFormat::PBMPlain
-            }
+        let mut scanner = Scanner::new(&img_buf);
+        let magic_number = scanner.clone().read_magic()?;
+        match MagicNumber::from_bytes(&magic_number) {
+            Some(magic_number)
+                if magic_number == NetpbmFormat::PBMRaw.magic()
+                    || magic_number == NetpbmFormat::PBMPlain.magic() => {}
             Some(magic_number) => {
                 return Err(NetpbmError::IOOperationFailed {
                     info: format!("Invalid magic number: {}", magic_number),
@@ -142,39 +221,14 @@ impl<R: io::Read> Decoder<R> {
             }
         };
 
-        match format {
-            NetpbmFormat::PBMRaw => self.read_raw(&img_buf, buf),
-            NetpbmFormat::PBMPlain => self.read_plain(&img_buf, buf),
-            _ => unreachable!(),
-        }
-    }
-
-    /// Read a PBM `raw` image to the provided buffer and fill the `Info` metadata struct.
-    ///
-    /// Assumes magic number bytes can be skipped.
-    ///
-    fn read_raw(&mut self, img_buf: &[u8], buf: &mut [u8]) -> Result<Info, NetpbmError> {
-        Info::new_pbm(EncodingType::Raw, 1, 1)
-    }
-
-    /// Read a PBM `plain` image to the provided buffer and fill the `Info` metadata struct.
-    ///
-    /// Assumes magic number bytes can be skipped.
-    ///
-    fn read_plain(&mut self, img_buf: &[u8], buf: &mut [u8]) -> Result<Info, NetpbmError> {
-        Info::new_pbm(EncodingType::Plain, 1, 1)
-    }
+        let (info, _) = decode::read_header(&mut scanner)?;
+        let raster = read(&mut scanner, &info)?;
 
-    /// Reads the next field.
-    ///
-    /// Assumes the last read byte
-    ///
-    fn read_field(&mut self, buf: &mut [u8]) -> Result<(), NetpbmError> {
-        Ok(())
+        Ok((info, raster))
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
 
@@ -205,7 +259,7 @@ mod tests {
         let mut enc = Encoder::new(ImageBuffer::new());
 
         let data: Vec<u8> = vec![1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0];
-        let expected = [80, 52, 10, 52, 32, 51, 10, 170, 160];
+        let expected = [80, 52, 10, 52, 32, 51, 10, 160, 160, 160];
 
         let res = enc.write(EncodingType::Raw, 4, 3, &data);
         assert!(res.is_ok());
@@ -220,12 +274,45 @@ mod tests {
             1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1,
             0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0,
         ];
-        let expected = format!(
-            "P1\n7 6\n1 0 1 0 1 0 1 0 1 0 1 0 1 0 1 0 1 0 1 0 1 0 1 0 1 0 1 0 1 0 1 0 1 0 1\n0 1 0 1 0 1 0\n"
-        );
+        let expected = "P1\n7 6\n1 0 1 0 1 0 1 0 1 0 1 0 1 0 1 0 1 0 1 0 1 0 1 0 1 0 1 0 1 0 1 0 1 0 1\n0 1 0 1 0 1 0\n".to_string();
 
         let res = enc.write(EncodingType::Plain, 7, 6, &data);
         assert!(res.is_ok());
         assert_eq!(enc.writer.buffer[..], *expected.as_bytes());
     }
+
+    #[test]
+    fn test_read_pbm_raw() {
+        let data: Vec<u8> = vec![
+            1, 0, 1, 0, 1, 0, 1, 0, 1, 1, //
+            0, 1, 0, 1, 0, 1, 0, 1, 0, 0,
+        ];
+        let mut enc = Encoder::new(ImageBuffer::new());
+        enc.write(EncodingType::Raw, 10, 2, &data).expect("write");
+        assert_eq!(enc.writer.buffer[8..], [170, 192, 85, 0]);
+
+        let mut dec = Decoder::new(&enc.writer.buffer[..]);
+        let mut buf = Vec::new();
+        let info = dec.read(&mut buf).expect("read");
+        assert_eq!(info, Info::new_pbm(EncodingType::Raw, 10, 2).expect("pbm"));
+        assert_eq!(buf, data);
+    }
+
+    #[test]
+    fn test_read_pbm_plain() {
+        let mut dec = Decoder::new(&b"P1\n# comment\n3 2\n101\n0 1 0\n"[..]);
+        let mut buf = Vec::new();
+        let info = dec.read(&mut buf).expect("read");
+        assert_eq!(info, Info::new_pbm(EncodingType::Plain, 3, 2).expect("pbm"));
+        assert_eq!(buf, [1, 0, 1, 0, 1, 0]);
+
+        let mut dec = Decoder::new(&b"P1\n3 2\n101\n0 2 0\n"[..]);
+        assert!(matches!(
+            dec.read(&mut buf),
+            Err(NetpbmError::MalformedRaster { offset: 13, .. })
+        ));
+
+        let mut dec = Decoder::new(&b"P2\n3 2 1\n101\n0 1 0\n"[..]);
+        assert!(dec.read(&mut buf).is_err());
+    }
 }
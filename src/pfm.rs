@@ -0,0 +1,404 @@
+//! PFM (Portable Float Map) image encoding and decoding.
+//!
+//! Each PFM image fundamentally consists of the image width,
+//! the image height, a scale factor, and a sequence of rows of
+//! 32-bit floating point samples. Color images have a triplet
+//! of samples per pixel (red, green, blue) and use the magic
+//! number `PF`. Grayscale images have one sample per pixel and
+//! use the magic number `Pf`.
+//!
+//! The sign of the scale factor gives the byte order of the
+//! samples: negative is little-endian and positive is big-endian.
+//! The magnitude of the scale factor is carried as metadata and
+//! is not applied to the samples.
+//!
+//! Unlike the other netpbm formats, PFM rows are stored
+//! bottom-to-top. The encoder and decoder flip the rows, so samples
+//! in memory are ordered top-to-bottom like every other format.
+//!
+//! PFM samples have no maxval. `to_integer` and `from_integer`
+//! convert samples to and from integer samples at a chosen bit
+//! depth, so that float maps can be written as PPM or PGM.
+//!
+
+#[cfg(feature = "std")]
+use crate::formats::decode::{self, Scanner};
+use crate::io;
+use crate::math;
+use crate::{BitDepth, Info, NetpbmError};
+#[cfg(feature = "std")]
+use crate::{MagicNumber, NetpbmFormat};
+use alloc::format;
+use alloc::vec::Vec;
+
+/// Byte order of PFM samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ByteOrder {
+    LittleEndian,
+    BigEndian,
+}
+
+/// PFM specific header fields.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Header {
+    /// The byte order of the samples.
+    pub byte_order: ByteOrder,
+
+    /// The magnitude of the scale factor. Must be positive.
+    pub scale: f32,
+}
+
+impl Default for Header {
+    fn default() -> Self {
+        Header {
+            byte_order: ByteOrder::LittleEndian,
+            scale: 1.0,
+        }
+    }
+}
+
+/// Tone mapping operator used to convert floating point
+/// samples to integer samples.
+///
+/// Each operator maps samples to the range `[0, 1]` before they
+/// are scaled to the bit depth. Negative and NaN samples map to 0.
+///
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum ToneMap {
+    /// Clamp samples to `[0, 1]`.
+    Clamp,
+
+    /// Divide samples by the largest sample in the image.
+    Normalize,
+
+    /// Apply the global Reinhard operator `x / (1 + x)`.
+    Reinhard,
+
+    /// Apply the exponential operator `1 - exp(-exposure * x)`.
+    Exposure(f32),
+}
+
+/// PFM encoder.
+#[derive(Debug)]
+pub struct Encoder<W: io::Write> {
+    writer: W,
+}
+
+impl<W: io::Write> Encoder<W> {
+    /// Create a new PFM encoder with the given writer.
+    pub fn new(writer: W) -> Self {
+        Encoder { writer }
+    }
+
+    /// Write one PFM image.
+    ///
+    /// Images with 3 channels are written as color images, and
+    /// images with 1 channel are written as grayscale images.
+    ///
+    pub fn write(
+        &mut self,
+        width: u32,
+        height: u32,
+        channels: u32,
+        header: &Header,
+        samples: &[f32],
+    ) -> Result<(), NetpbmError> {
+        let info = Info::new_pfm(width, height, channels)?;
+        info.validate_sample_size(samples.len())?;
+        if !(header.scale.is_finite() && header.scale > 0.0) {
+            return Err(NetpbmError::InvalidArgument {
+                info: format!("PFM scale {} should be positive", header.scale),
+            });
+        }
+
+        let mut buf = Self::build_header(&info, header);
+
+        // PFM rows are stored bottom-to-top.
+        let row_len = width as usize * channels as usize;
+        for row in samples.chunks_exact(row_len).rev() {
+            match header.byte_order {
+                ByteOrder::LittleEndian => buf.extend(row.iter().flat_map(|s| s.to_le_bytes())),
+                ByteOrder::BigEndian => buf.extend(row.iter().flat_map(|s| s.to_be_bytes())),
+            }
+        }
+
+        self.writer.write_all(&buf)?;
+
+        Ok(())
+    }
+
+    /// Build a PFM header.
+    fn build_header(info: &Info, header: &Header) -> Vec<u8> {
+        let scale = match header.byte_order {
+            ByteOrder::LittleEndian => -header.scale,
+            ByteOrder::BigEndian => header.scale,
+        };
+
+        format!(
+            "{}\n{} {}\n{:?}\n",
+            info.format.magic(),
+            info.width,
+            info.height,
+            scale
+        )
+        .into_bytes()
+    }
+}
+
+/// PFM decoder.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct Decoder<R: io::Read> {
+    reader: R,
+}
+
+#[cfg(feature = "std")]
+impl<R: io::Read> Decoder<R> {
+    /// Create a new PFM decoder with the given reader.
+    pub fn new(reader: R) -> Self {
+        Decoder { reader }
+    }
+
+    /// Read one PFM image into the provided buffer and fill the
+    /// `Info` metadata struct and the PFM `Header`.
+    ///
+    /// The buffer is cleared before samples are written to it.
+    ///
+    pub fn read(&mut self, buf: &mut Vec<f32>) -> Result<(Info, Header), NetpbmError> {
+        let mut img_buf = Vec::new();
+        self.reader.read_to_end(&mut img_buf)?;
+
+        let mut scanner = Scanner::new(&img_buf);
+        let magic_number = scanner.read_magic()?;
+        let channels = match MagicNumber::from_bytes(&magic_number) {
+            Some(magic_number) if magic_number == NetpbmFormat::PFMColor.magic() => 3,
+            Some(magic_number) if magic_number == NetpbmFormat::PFMGray.magic() => 1,
+            _ => {
+                return Err(NetpbmError::MalformedHeader {
+                    offset: 0,
+                    info: format!("Invalid PFM magic number: {:?}", magic_number),
+                })
+            }
+        };
+
+        let width = scanner.read_decimal()?;
+        let height = scanner.read_decimal()?;
+        let info = Info::new_pfm(width, height, channels)?;
+
+        let scale_offset = scanner.offset();
+        let scale: f32 = scanner.read_decimal()?;
+        if !scale.is_finite() || scale == 0.0 {
+            return Err(NetpbmError::MalformedHeader {
+                offset: scale_offset,
+                info: format!("PFM scale {} should be finite and non-zero", scale),
+            });
+        }
+        let header = Header {
+            byte_order: if scale < 0.0 {
+                ByteOrder::LittleEndian
+            } else {
+                ByteOrder::BigEndian
+            },
+            scale: scale.abs(),
+        };
+        scanner.read_separator()?;
+
+        let row_len = width as usize * channels as usize;
+        let (len, raster_len) = info
+            .sample_count()
+            .zip(info.raw_raster_len())
+            .ok_or_else(|| decode::oversized_raster(&scanner, &info))?;
+        let raster = scanner.take(raster_len)?;
+
+        buf.clear();
+        buf.reserve(len);
+
+        // PFM rows are stored bottom-to-top.
+        for row in raster.chunks_exact(row_len * 4).rev() {
+            buf.extend(row.chunks_exact(4).map(|bytes| {
+                let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+                match header.byte_order {
+                    ByteOrder::LittleEndian => f32::from_le_bytes(bytes),
+                    ByteOrder::BigEndian => f32::from_be_bytes(bytes),
+                }
+            }));
+        }
+
+        Ok((info, header))
+    }
+}
+
+/// Convert floating point samples to integer samples with the
+/// given bit depth.
+///
+/// The returned samples can be written with the PPM or PGM
+/// `write_wide` encoders using the same bit depth.
+///
+pub fn to_integer(
+    samples: &[f32],
+    bit_depth: u16,
+    tone_map: ToneMap,
+) -> Result<Vec<u16>, NetpbmError> {
+    let maxval = BitDepth::new(bit_depth)?.value() as f32;
+
+    let peak = match tone_map {
+        ToneMap::Normalize => samples
+            .iter()
+            .copied()
+            .filter(|s| s.is_finite())
+            .fold(0.0, f32::max),
+        _ => 1.0,
+    };
+
+    let mapped = samples.iter().map(|&s| {
+        let s = if s.is_nan() { 0.0 } else { s.max(0.0) };
+        let s = match tone_map {
+            ToneMap::Clamp => s,
+            ToneMap::Normalize if peak > 0.0 => s / peak,
+            ToneMap::Normalize => 0.0,
+            ToneMap::Reinhard if s.is_infinite() => 1.0,
+            ToneMap::Reinhard => s / (1.0 + s),
+            ToneMap::Exposure(exposure) => 1.0 - math::exp(f64::from(-exposure * s)) as f32,
+        };
+        math::round(f64::from(s.clamp(0.0, 1.0) * maxval)) as u16
+    });
+
+    Ok(mapped.collect())
+}
+
+/// Convert integer samples with the given bit depth to floating
+/// point samples in the range `[0, 1]`.
+///
+/// Samples are validated against the bit depth.
+///
+pub fn from_integer(samples: &[u16], bit_depth: u16) -> Result<Vec<f32>, NetpbmError> {
+    let bit_depth = BitDepth::new(bit_depth)?;
+    if let Some(offset) = samples.iter().position(|&s| s > bit_depth.value()) {
+        return Err(NetpbmError::OversizedSample { offset, bit_depth });
+    }
+
+    let maxval = bit_depth.value() as f32;
+    Ok(samples.iter().map(|&s| s as f32 / maxval).collect())
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct ImageBuffer {
+        buffer: Vec<u8>,
+    }
+
+    impl ImageBuffer {
+        fn new() -> Self {
+            ImageBuffer { buffer: Vec::new() }
+        }
+    }
+
+    impl io::Write for ImageBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.buffer.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_pfm_gray() {
+        let mut enc = Encoder::new(ImageBuffer::new());
+
+        let data: Vec<f32> = vec![1.0, 0.5, 0.0, 2.0];
+        let mut expected = b"Pf\n2 2\n-1.0\n".to_vec();
+        expected.extend([0, 0, 0, 0, 0, 0, 0, 64, 0, 0, 128, 63, 0, 0, 0, 63]);
+
+        let res = enc.write(2, 2, 1, &Header::default(), &data);
+        assert!(res.is_ok());
+        assert_eq!(enc.writer.buffer[..], expected[..]);
+    }
+
+    #[test]
+    fn test_write_pfm_color_big_endian() {
+        let mut enc = Encoder::new(ImageBuffer::new());
+
+        let data: Vec<f32> = vec![1.0, 0.0, 0.5];
+        let mut expected = b"PF\n1 1\n2.5\n".to_vec();
+        expected.extend([63, 128, 0, 0, 0, 0, 0, 0, 63, 0, 0, 0]);
+
+        let header = Header {
+            byte_order: ByteOrder::BigEndian,
+            scale: 2.5,
+        };
+        let res = enc.write(1, 1, 3, &header, &data);
+        assert!(res.is_ok());
+        assert_eq!(enc.writer.buffer[..], expected[..]);
+
+        assert!(enc.write(1, 1, 2, &header, &[0.0, 0.0]).is_err());
+        let negative = Header {
+            scale: -1.0,
+            ..header
+        };
+        assert!(matches!(
+            enc.write(1, 1, 1, &negative, &[0.0]),
+            Err(NetpbmError::InvalidArgument { .. })
+        ));
+        assert!(enc.write(1, 1, 3, &header, &data[..2]).is_err());
+    }
+
+    #[test]
+    fn test_read_pfm() {
+        let data: Vec<f32> = vec![0.25, 1.0, 8.0, 0.0, 0.5, 0.75];
+        let header = Header {
+            byte_order: ByteOrder::BigEndian,
+            scale: 1.0,
+        };
+        let mut enc = Encoder::new(ImageBuffer::new());
+        enc.write(1, 2, 3, &header, &data).expect("write");
+
+        let mut dec = Decoder::new(&enc.writer.buffer[..]);
+        let mut buf = Vec::new();
+        let (info, read_header) = dec.read(&mut buf).expect("read");
+        assert_eq!(info, Info::new_pfm(1, 2, 3).expect("pfm"));
+        assert_eq!(read_header, header);
+        assert_eq!(buf, data);
+
+        let mut dec = Decoder::new(&b"Pf\n2 2\n-1.0\n\x00\x00"[..]);
+        assert!(matches!(
+            dec.read(&mut buf),
+            Err(NetpbmError::UnexpectedEof { .. })
+        ));
+
+        let mut dec = Decoder::new(&b"P6\n2 2\n-1.0\n"[..]);
+        assert!(dec.read(&mut buf).is_err());
+
+        let mut dec = Decoder::new(&b"Pf\n4294967295 4294967295\n-1.0\n"[..]);
+        assert!(matches!(
+            dec.read(&mut buf),
+            Err(NetpbmError::MalformedHeader { .. })
+        ));
+    }
+
+    #[test]
+    fn test_integer_conversion() {
+        let samples = [0.0, 0.5, 1.0, 3.0, -1.0, f32::NAN];
+        assert_eq!(
+            to_integer(&samples, 255, ToneMap::Clamp),
+            Ok(vec![0, 128, 255, 255, 0, 0])
+        );
+        assert_eq!(
+            to_integer(&samples, 300, ToneMap::Normalize),
+            Ok(vec![0, 50, 100, 300, 0, 0])
+        );
+        assert_eq!(
+            to_integer(&samples, 65535, ToneMap::Reinhard),
+            Ok(vec![0, 21845, 32768, 49151, 0, 0])
+        );
+        assert!(to_integer(&samples, 0, ToneMap::Clamp).is_err());
+
+        assert_eq!(from_integer(&[0, 51, 255], 255), Ok(vec![0.0, 0.2, 1.0]));
+        assert!(from_integer(&[0, 256], 255).is_err());
+    }
+}
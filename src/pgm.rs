@@ -13,9 +13,16 @@
 //! Grey values are written as ASCII-encoded decimal numbers.
 //! The `plain` format uses the magic number `P2`.
 
+#[cfg(feature = "std")]
+use crate::formats::decode::{self, Scanner};
+use crate::io;
 use crate::NetpbmError;
+#[cfg(feature = "std")]
+use crate::NetpbmFormat;
 use crate::{EncodingType, Info};
-use std::io;
+use alloc::borrow::ToOwned;
+use alloc::format;
+use alloc::vec::Vec;
 
 /// PGM encoder.
 #[derive(Debug)]
@@ -159,11 +166,69 @@ impl<W: io::Write> Encoder<W> {
     }
 }
 
-// /// PGM decoder.
-// #[derive(Debug)]
-// pub struct Decoder<R: io::Read> {
-//     reader: R,
-// }
+/// PGM decoder.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct Decoder<R: io::Read> {
+    reader: R,
+}
+
+#[cfg(feature = "std")]
+impl<R: io::Read> Decoder<R> {
+    /// Create a new PGM decoder with the given reader.
+    pub fn new(reader: R) -> Self {
+        Decoder { reader }
+    }
+
+    /// Read one PGM image with one-byte samples into the provided
+    /// buffer and fill the `Info` metadata struct.
+    ///
+    /// The buffer is cleared before samples are written to it.
+    /// Images with a bit depth greater than 255 must be read
+    /// with `read_wide`.
+    ///
+    pub fn read(&mut self, buf: &mut Vec<u8>) -> Result<Info, NetpbmError> {
+        let (info, samples) = self.read_samples()?;
+        if info.bit_depth.is_multi_byte() {
+            return Err(NetpbmError::IOOperationFailed {
+                info: format!("Bit depth {} requires two-byte samples", info.bit_depth),
+            });
+        }
+
+        buf.clear();
+        buf.extend(samples.into_iter().map(|s| s as u8));
+
+        Ok(info)
+    }
+
+    /// Read one PGM image with two-byte samples into the provided
+    /// buffer and fill the `Info` metadata struct.
+    ///
+    /// The buffer is cleared before samples are written to it.
+    ///
+    pub fn read_wide(&mut self, buf: &mut Vec<u16>) -> Result<Info, NetpbmError> {
+        let (info, samples) = self.read_samples()?;
+        *buf = samples;
+        Ok(info)
+    }
+
+    /// Read the header and samples of one PGM image.
+    fn read_samples(&mut self) -> Result<(Info, Vec<u16>), NetpbmError> {
+        let mut img_buf = Vec::new();
+        self.reader.read_to_end(&mut img_buf)?;
+
+        let mut scanner = Scanner::new(&img_buf);
+        let (info, _) = decode::read_header(&mut scanner)?;
+        if !matches!(info.format, NetpbmFormat::PGMRaw | NetpbmFormat::PGMPlain) {
+            return Err(NetpbmError::IOOperationFailed {
+                info: format!("Invalid magic number: {}", info.format.magic()),
+            });
+        }
+        let samples = decode::read_samples(&mut scanner, &info)?;
+
+        Ok((info, samples))
+    }
+}
 
 // impl<R: io::Read> Decoder<R> {
 //     /// Create a new PGM decoder with the given reader.
@@ -172,7 +237,7 @@ impl<W: io::Write> Encoder<W> {
 //     }
 // }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
 
@@ -222,9 +287,9 @@ mod tests {
         let data: Vec<u8> = vec![
             255, 0, 255, 0, 255, 0, 255, 0, 255, 0, 255, 0, 255, 0, 255, 0,
         ];
-        let expected = format!(
+        let expected =
             "P2\n4 4 255\n255\n0\n255\n0\n255\n0\n255\n0\n255\n0\n255\n0\n255\n0\n255\n0\n"
-        );
+                .to_string();
 
         let res = enc.write(EncodingType::Plain, 4, 4, 255, &data);
         assert!(res.is_ok());
@@ -255,12 +320,55 @@ mod tests {
         let data: Vec<u16> = vec![
             1056, 0, 1056, 0, 1056, 0, 1056, 0, 1056, 0, 1056, 0, 1056, 0, 1056, 0,
         ];
-        let expected = format!(
-            "P2\n4 4 2048\n1056\n0\n1056\n0\n1056\n0\n1056\n0\n1056\n0\n1056\n0\n1056\n0\n1056\n0\n"
-        );
+        let expected = "P2\n4 4 2048\n1056\n0\n1056\n0\n1056\n0\n1056\n0\n1056\n0\n1056\n0\n1056\n0\n1056\n0\n".to_string();
 
         let res = enc.write_wide(EncodingType::Plain, 4, 4, 2048, &data);
         assert!(res.is_ok());
         assert_eq!(enc.writer.buffer[..], *expected.as_bytes());
     }
+
+    #[test]
+    fn test_read_pgm_plain() {
+        let mut dec = Decoder::new(&b"P2 # gray\n2 2\n# maxval\n15\n0 15\n7 3\n"[..]);
+        let mut buf = Vec::new();
+        let info = dec.read(&mut buf).expect("read");
+        assert_eq!(
+            info,
+            Info::new_pgm(EncodingType::Plain, 2, 2, 15).expect("pgm")
+        );
+        assert_eq!(buf, [0, 15, 7, 3]);
+
+        let mut dec = Decoder::new(&b"P2\n2 2\n15\n0 16\n7 3\n"[..]);
+        assert!(matches!(
+            dec.read(&mut buf),
+            Err(NetpbmError::OversizedSample { offset: 12, .. })
+        ));
+    }
+
+    #[test]
+    fn test_read_pgm_wide_raw() {
+        let data: Vec<u16> = vec![1056, 0, 2048, 7];
+        let mut enc = Encoder::new(ImageBuffer::new());
+        enc.write_wide(EncodingType::Raw, 2, 2, 2048, &data)
+            .expect("write");
+
+        let mut dec = Decoder::new(&enc.writer.buffer[..]);
+        let mut buf = Vec::new();
+        let info = dec.read_wide(&mut buf).expect("read");
+        assert_eq!(
+            info,
+            Info::new_pgm(EncodingType::Raw, 2, 2, 2048).expect("pgm")
+        );
+        assert_eq!(buf, data);
+
+        let mut dec = Decoder::new(&enc.writer.buffer[..]);
+        let mut buf = Vec::new();
+        assert!(dec.read(&mut buf).is_err());
+
+        let mut dec = Decoder::new(&enc.writer.buffer[..enc.writer.buffer.len() - 1]);
+        assert!(matches!(
+            dec.read_wide(&mut Vec::new()),
+            Err(NetpbmError::UnexpectedEof { .. })
+        ));
+    }
 }
@@ -15,9 +15,16 @@
 //! The `plain` format uses the magic number `P3`.
 //!
 
+#[cfg(feature = "std")]
+use crate::formats::decode::{self, Scanner};
+use crate::io;
 use crate::NetpbmError;
+#[cfg(feature = "std")]
+use crate::NetpbmFormat;
 use crate::{EncodingType, Info};
-use std::io;
+use alloc::borrow::ToOwned;
+use alloc::format;
+use alloc::vec::Vec;
 
 /// PPM encoder.
 #[derive(Debug)]
@@ -162,11 +169,69 @@ impl<W: io::Write> Encoder<W> {
     }
 }
 
-// /// PPM decoder.
-// #[derive(Debug)]
-// pub struct Decoder<R: io::Read> {
-//     reader: R,
-// }
+/// PPM decoder.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct Decoder<R: io::Read> {
+    reader: R,
+}
+
+#[cfg(feature = "std")]
+impl<R: io::Read> Decoder<R> {
+    /// Create a new PPM decoder with the given reader.
+    pub fn new(reader: R) -> Self {
+        Decoder { reader }
+    }
+
+    /// Read one PPM image with one-byte samples into the provided
+    /// buffer and fill the `Info` metadata struct.
+    ///
+    /// The buffer is cleared before samples are written to it.
+    /// Images with a bit depth greater than 255 must be read
+    /// with `read_wide`.
+    ///
+    pub fn read(&mut self, buf: &mut Vec<u8>) -> Result<Info, NetpbmError> {
+        let (info, samples) = self.read_samples()?;
+        if info.bit_depth.is_multi_byte() {
+            return Err(NetpbmError::IOOperationFailed {
+                info: format!("Bit depth {} requires two-byte samples", info.bit_depth),
+            });
+        }
+
+        buf.clear();
+        buf.extend(samples.into_iter().map(|s| s as u8));
+
+        Ok(info)
+    }
+
+    /// Read one PPM image with two-byte samples into the provided
+    /// buffer and fill the `Info` metadata struct.
+    ///
+    /// The buffer is cleared before samples are written to it.
+    ///
+    pub fn read_wide(&mut self, buf: &mut Vec<u16>) -> Result<Info, NetpbmError> {
+        let (info, samples) = self.read_samples()?;
+        *buf = samples;
+        Ok(info)
+    }
+
+    /// Read the header and samples of one PPM image.
+    fn read_samples(&mut self) -> Result<(Info, Vec<u16>), NetpbmError> {
+        let mut img_buf = Vec::new();
+        self.reader.read_to_end(&mut img_buf)?;
+
+        let mut scanner = Scanner::new(&img_buf);
+        let (info, _) = decode::read_header(&mut scanner)?;
+        if !matches!(info.format, NetpbmFormat::PPMRaw | NetpbmFormat::PPMPlain) {
+            return Err(NetpbmError::IOOperationFailed {
+                info: format!("Invalid magic number: {}", info.format.magic()),
+            });
+        }
+        let samples = decode::read_samples(&mut scanner, &info)?;
+
+        Ok((info, samples))
+    }
+}
 
 // impl<R: io::Read> Decoder<R> {
 //     /// Create a new PPM decoder with the given reader.
@@ -175,7 +240,7 @@ impl<W: io::Write> Encoder<W> {
 //     }
 // }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
 
@@ -226,7 +291,7 @@ mod tests {
             255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 0, 255, 255, 255, 0, 0, 0,
         ];
         let expected =
-            format!("P3\n3 2 255\n255 0 0\n0 255 0\n0 0 255\n255 255 0\n255 255 255\n0 0 0\n");
+            "P3\n3 2 255\n255 0 0\n0 255 0\n0 0 255\n255 255 0\n255 255 255\n0 0 0\n".to_string();
 
         let res = enc.write(EncodingType::Plain, 3, 2, 255, &data);
         assert!(res.is_ok());
@@ -257,12 +322,44 @@ mod tests {
         let data: Vec<u16> = vec![
             1056, 0, 0, 0, 1056, 0, 0, 0, 1056, 1056, 1056, 0, 1056, 1056, 1056, 0, 0, 0,
         ];
-        let expected = format!(
+        let expected =
             "P3\n3 2 2048\n1056 0 0\n0 1056 0\n0 0 1056\n1056 1056 0\n1056 1056 1056\n0 0 0\n"
-        );
+                .to_string();
 
         let res = enc.write_wide(EncodingType::Plain, 3, 2, 2048, &data);
         assert!(res.is_ok());
         assert_eq!(enc.writer.buffer[..], *expected.as_bytes());
     }
+
+    #[test]
+    fn test_read_ppm_raw() {
+        let data: Vec<u8> = vec![255, 0, 0, 0, 255, 0, 0, 0, 255, 10, 20, 30];
+        let mut enc = Encoder::new(ImageBuffer::new());
+        enc.write(EncodingType::Raw, 2, 2, 255, &data)
+            .expect("write");
+
+        let mut dec = Decoder::new(&enc.writer.buffer[..]);
+        let mut buf = Vec::new();
+        let info = dec.read(&mut buf).expect("read");
+        assert_eq!(
+            info,
+            Info::new_ppm(EncodingType::Raw, 2, 2, 255).expect("ppm")
+        );
+        assert_eq!(buf, data);
+    }
+
+    #[test]
+    fn test_read_ppm_plain() {
+        let mut dec = Decoder::new(&b"P3\n1 2\n1000\n1000 0 0\n1 2 3\n"[..]);
+        let mut buf = Vec::new();
+        let info = dec.read_wide(&mut buf).expect("read");
+        assert_eq!(
+            info,
+            Info::new_ppm(EncodingType::Plain, 1, 2, 1000).expect("ppm")
+        );
+        assert_eq!(buf, [1000, 0, 0, 1, 2, 3]);
+
+        let mut dec = Decoder::new(&b"P3\n1 2\n1000\n1000 0 0\n1 2\n"[..]);
+        assert!(dec.read_wide(&mut buf).is_err());
+    }
 }
@@ -0,0 +1,465 @@
+//! Color quantization.
+//!
+//! `median_cut` and `k_means` choose a palette of at most a given
+//! number of colors for an image, similar to netpbm's
+//! `pnmcolormap`. `remap` replaces every pixel of an image with a
+//! palette color, similar to netpbm's `pnmremap`.
+//!
+//! Palettes are exchanged as PPM images with one pixel per color.
+//! `Palette::to_image` writes a single row, as `pnmcolormap` does,
+//! and `Palette::from_image` accepts a palette image of any shape.
+//!
+//! Quantization works on RGB images: PPM images, or PAM images with
+//! 3 channels.
+//!
+
+use crate::image::Image;
+#[cfg(feature = "std")]
+use crate::io;
+use crate::math;
+use crate::{BitDepth, ChannelDepth, EncodingType, Info, NetpbmError, TypeInfo};
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// A list of colors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    bit_depth: BitDepth,
+    colors: Vec<[u16; 3]>,
+}
+
+/// Error diffusion when remapping an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Dither {
+    /// Use the nearest palette color.
+    None,
+
+    /// Diffuse the error of each pixel to its unvisited neighbours
+    /// with Floyd-Steinberg weights, alternating the direction of
+    /// each row.
+    FloydSteinberg,
+}
+
+impl Palette {
+    /// Create a palette from a list of colors.
+    pub fn new(bit_depth: u16, colors: Vec<[u16; 3]>) -> Result<Self, NetpbmError> {
+        let bit_depth = BitDepth::new(bit_depth)?;
+        if colors.is_empty() {
+            return Err(NetpbmError::InvalidArgument {
+                info: "A palette needs at least one color".to_string(),
+            });
+        }
+        if let Some(offset) = colors.iter().flatten().position(|&s| s > bit_depth.value()) {
+            return Err(NetpbmError::OversizedSample { offset, bit_depth });
+        }
+
+        Ok(Palette { bit_depth, colors })
+    }
+
+    /// Create a palette from the colors of a palette image.
+    ///
+    /// Repeated colors are kept once, in the order they first appear.
+    ///
+    pub fn from_image(image: &Image) -> Result<Self, NetpbmError> {
+        check_rgb(image)?;
+
+        let mut seen = BTreeSet::new();
+        let colors = image
+            .samples()
+            .chunks_exact(3)
+            .map(|rgb| [rgb[0], rgb[1], rgb[2]])
+            .filter(|&color| seen.insert(color))
+            .collect();
+        Self::new(image.bit_depth(), colors)
+    }
+
+    /// Decode a palette from the first image in the given reader.
+    #[cfg(feature = "std")]
+    pub fn read<R: io::Read>(reader: R) -> Result<Self, NetpbmError> {
+        Self::from_image(&Image::read(reader)?)
+    }
+
+    /// Convert the palette to a PPM image with one row.
+    pub fn to_image(&self, encoding: EncodingType) -> Image {
+        let info = Info::new_ppm(
+            encoding,
+            self.colors.len() as u32,
+            1,
+            self.bit_depth.value(),
+        )
+        .expect("Palette image info");
+        let samples = self.colors.iter().flatten().copied().collect();
+        Image::from_parts(info, TypeInfo::Empty, samples)
+    }
+
+    /// Get the palette bit depth.
+    pub fn bit_depth(&self) -> u16 {
+        self.bit_depth.value()
+    }
+
+    /// Get the palette colors.
+    pub fn colors(&self) -> &[[u16; 3]] {
+        &self.colors
+    }
+
+    /// Get the number of colors.
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    /// Determine whether the palette has no colors.
+    ///
+    /// Palettes always have at least one color.
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    /// Get the index of the color nearest to `color`.
+    pub fn nearest(&self, color: [u16; 3]) -> usize {
+        nearest(
+            &self.colors.iter().map(|&c| to_f64(c)).collect::<Vec<_>>(),
+            to_f64(color),
+        )
+    }
+
+    /// Rescale the palette colors to another bit depth.
+    fn rescale(&self, bit_depth: u16) -> Vec<[f64; 3]> {
+        let scale = bit_depth as f64 / self.bit_depth() as f64;
+        self.colors
+            .iter()
+            .map(|&c| to_f64(c).map(|s| math::round(s * scale)))
+            .collect()
+    }
+}
+
+/// Choose a palette with median cut.
+///
+/// The colors are split into boxes. The box with the most pixels is
+/// split in two at the median of its widest channel, until there
+/// are `colors` boxes. Each box contributes its mean color.
+///
+pub fn median_cut(image: &Image, colors: usize) -> Result<Palette, NetpbmError> {
+    check_rgb(image)?;
+    check_colors(colors)?;
+
+    let mut histogram = histogram(image);
+    let mut boxes = Vec::with_capacity(colors);
+    boxes.push(0..histogram.len());
+    while boxes.len() < colors {
+        // Split the most populous box with more than one color.
+        let Some(index) = (0..boxes.len())
+            .filter(|&i| boxes[i].len() > 1)
+            .max_by_key(|&i| (pixels(&histogram[boxes[i].clone()]), core::cmp::Reverse(i)))
+        else {
+            break;
+        };
+
+        let range = boxes[index].clone();
+        let entries = &mut histogram[range.clone()];
+        let channel = widest_channel(entries);
+        entries.sort_unstable_by_key(|&(color, _)| (color[channel], color));
+
+        // Split after the median pixel, keeping both halves nonempty.
+        let half = pixels(entries) / 2;
+        let mut sum = 0;
+        let mut split = entries.len() - 1;
+        for (i, &(_, count)) in entries.iter().enumerate() {
+            sum += count;
+            if sum > half {
+                split = (i + 1).clamp(1, entries.len() - 1);
+                break;
+            }
+        }
+
+        boxes[index] = range.start..range.start + split;
+        boxes.push(range.start + split..range.end);
+    }
+
+    let colors = boxes
+        .into_iter()
+        .map(|range| from_f64(mean(&histogram[range])))
+        .collect();
+    Palette::new(image.bit_depth(), colors)
+}
+
+/// Choose a palette with k-means clustering.
+///
+/// Clusters start from the median cut palette and are refined for
+/// at most `iterations` rounds, or until no color changes cluster.
+///
+pub fn k_means(image: &Image, colors: usize, iterations: u32) -> Result<Palette, NetpbmError> {
+    let mut centers: Vec<[f64; 3]> = median_cut(image, colors)?
+        .colors
+        .iter()
+        .map(|&c| to_f64(c))
+        .collect();
+
+    let histogram = histogram(image);
+    let mut assignment = vec![usize::MAX; histogram.len()];
+    for _ in 0..iterations {
+        let mut changed = false;
+        for (cluster, &(color, _)) in assignment.iter_mut().zip(&histogram) {
+            let nearest = nearest(&centers, to_f64(color));
+            changed |= *cluster != nearest;
+            *cluster = nearest;
+        }
+        if !changed {
+            break;
+        }
+
+        // Empty clusters keep their center.
+        let mut sums = vec![([0.0; 3], 0u64); centers.len()];
+        for (&cluster, &(color, count)) in assignment.iter().zip(&histogram) {
+            let (sum, n) = &mut sums[cluster];
+            for (s, c) in sum.iter_mut().zip(color) {
+                *s += c as f64 * count as f64;
+            }
+            *n += count;
+        }
+        for (center, (sum, n)) in centers.iter_mut().zip(sums) {
+            if n > 0 {
+                *center = sum.map(|s| s / n as f64);
+            }
+        }
+    }
+
+    let colors = centers.into_iter().map(from_f64).collect();
+    Palette::new(image.bit_depth(), colors)
+}
+
+/// Replace every pixel of an image with a palette color.
+///
+/// Palettes with a different bit depth are rescaled to the bit
+/// depth of the image.
+///
+pub fn remap(image: &Image, palette: &Palette, dither: Dither) -> Result<Image, NetpbmError> {
+    check_rgb(image)?;
+    let colors = palette.rescale(image.bit_depth());
+    let mut out = image.clone();
+
+    match dither {
+        Dither::None => {
+            let mut cache = BTreeMap::new();
+            for rgb in out.samples_mut().chunks_exact_mut(3) {
+                let key = [rgb[0], rgb[1], rgb[2]];
+                let index = *cache
+                    .entry(key)
+                    .or_insert_with(|| nearest(&colors, to_f64(key)));
+                rgb.copy_from_slice(&from_f64(colors[index]));
+            }
+        }
+        Dither::FloydSteinberg => {
+            let width = image.width() as usize;
+            let maxval = image.bit_depth() as f64;
+            let mut errors = vec![[0.0; 3]; width + 2];
+            let mut next = vec![[0.0; 3]; width + 2];
+
+            for y in 0..image.height() {
+                let forward = y % 2 == 0;
+                let y = y as usize;
+                let row = &mut out.samples_mut()[y * width * 3..(y + 1) * width * 3];
+                for i in 0..width {
+                    let x = if forward { i } else { width - 1 - i };
+                    let rgb = &mut row[x * 3..x * 3 + 3];
+
+                    // Errors are indexed from 1, leaving room at both ends.
+                    let wanted: [f64; 3] = core::array::from_fn(|c| {
+                        (rgb[c] as f64 + errors[x + 1][c]).clamp(0.0, maxval)
+                    });
+                    let color = colors[nearest(&colors, wanted)];
+                    rgb.copy_from_slice(&from_f64(color));
+
+                    let (ahead, behind) = if forward { (x + 2, x) } else { (x, x + 2) };
+                    for c in 0..3 {
+                        let error = wanted[c] - color[c];
+                        errors[ahead][c] += error * 7.0 / 16.0;
+                        next[behind][c] += error * 3.0 / 16.0;
+                        next[x + 1][c] += error * 5.0 / 16.0;
+                        next[ahead][c] += error * 1.0 / 16.0;
+                    }
+                }
+
+                errors = core::mem::replace(&mut next, vec![[0.0; 3]; width + 2]);
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// Check that an image has RGB tuples.
+pub(crate) fn check_rgb(image: &Image) -> Result<(), NetpbmError> {
+    if image.channels() != 3 {
+        return Err(NetpbmError::ChannelMismatch {
+            expected: ChannelDepth::new(3).expect("RGB24 channel depth"),
+            found: image.info().channels,
+        });
+    }
+    Ok(())
+}
+
+/// Check that a palette size is positive.
+fn check_colors(colors: usize) -> Result<(), NetpbmError> {
+    if colors == 0 {
+        return Err(NetpbmError::InvalidArgument {
+            info: "A palette needs at least one color".to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Count the pixels of each unique color.
+fn histogram(image: &Image) -> Vec<([u16; 3], u64)> {
+    let mut counts: BTreeMap<[u16; 3], u64> = BTreeMap::new();
+    for rgb in image.samples().chunks_exact(3) {
+        *counts.entry([rgb[0], rgb[1], rgb[2]]).or_default() += 1;
+    }
+
+    // The map iterates in color order, so results are deterministic.
+    counts.into_iter().collect()
+}
+
+/// Count the pixels of histogram entries.
+fn pixels(entries: &[([u16; 3], u64)]) -> u64 {
+    entries.iter().map(|&(_, count)| count).sum()
+}
+
+/// Find the channel with the largest range of histogram entries.
+fn widest_channel(entries: &[([u16; 3], u64)]) -> usize {
+    (0..3)
+        .max_by_key(|&c| {
+            let values = entries.iter().map(|(color, _)| color[c]);
+            let range = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+            (range, core::cmp::Reverse(c))
+        })
+        .expect("RGB channels")
+}
+
+/// Get the mean color of histogram entries.
+fn mean(entries: &[([u16; 3], u64)]) -> [f64; 3] {
+    let total = pixels(entries) as f64;
+    core::array::from_fn(|c| {
+        entries
+            .iter()
+            .map(|&(color, count)| color[c] as f64 * count as f64)
+            .sum::<f64>()
+            / total
+    })
+}
+
+/// Get the index of the color nearest to `color` by squared
+/// Euclidean distance.
+fn nearest(colors: &[[f64; 3]], color: [f64; 3]) -> usize {
+    let distance = |c: &[f64; 3]| (0..3).map(|i| math::powi(c[i] - color[i], 2)).sum::<f64>();
+    (0..colors.len())
+        .min_by(|&a, &b| distance(&colors[a]).total_cmp(&distance(&colors[b])))
+        .expect("Nonempty palette")
+}
+
+fn to_f64(color: [u16; 3]) -> [f64; 3] {
+    color.map(|s| s as f64)
+}
+
+fn from_f64(color: [f64; 3]) -> [u16; 3] {
+    color.map(|s| math::round(s) as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{pgm, ppm};
+
+    #[test]
+    fn test_median_cut() {
+        let image = ppm(
+            3,
+            2,
+            255,
+            [
+                [250, 0, 0],
+                [255, 0, 0],
+                [0, 0, 250],
+                [0, 0, 255],
+                [0, 0, 250],
+                [0, 0, 255],
+            ]
+            .concat(),
+        );
+
+        let palette = median_cut(&image, 2).expect("palette");
+        let mut colors = palette.colors().to_vec();
+        colors.sort();
+        assert_eq!(colors, [[0, 0, 253], [253, 0, 0]]);
+
+        // Asking for more colors than the image has gives every color.
+        let palette = median_cut(&image, 10).expect("palette");
+        assert_eq!(palette.len(), 4);
+
+        assert!(median_cut(&image, 0).is_err());
+    }
+
+    #[test]
+    fn test_k_means() {
+        let colors: Vec<[u16; 3]> = (0..16)
+            .map(|i| {
+                if i < 12 {
+                    [i, i, i]
+                } else {
+                    [990 + i, 990 + i, 990 + i]
+                }
+            })
+            .collect();
+        let image = ppm(4, 4, 1023, colors.concat());
+
+        let palette = k_means(&image, 2, 10).expect("palette");
+        let mut colors = palette.colors().to_vec();
+        colors.sort();
+        assert_eq!(colors, [[6, 6, 6], [1004, 1004, 1004]]);
+    }
+
+    #[test]
+    fn test_remap() {
+        let palette = Palette::new(1, vec![[0, 0, 0], [1, 1, 1]]).expect("palette");
+
+        // The palette is rescaled to the image bit depth.
+        let image = ppm(2, 1, 255, [[10, 20, 30], [200, 220, 250]].concat());
+        let out = remap(&image, &palette, Dither::None).expect("remap");
+        assert_eq!(out.samples(), [0, 0, 0, 255, 255, 255]);
+
+        // Dithering a mid gray gives an even mix.
+        let image = ppm(8, 8, 255, [[128, 128, 128]; 64].concat());
+        let out = remap(&image, &palette, Dither::FloydSteinberg).expect("remap");
+        let white = out.samples().iter().filter(|&&s| s == 255).count() / 3;
+        assert!((30..=34).contains(&white), "{}", white);
+        assert!(out.samples().iter().all(|&s| s == 0 || s == 255));
+
+        let gray = pgm(1, 1, 255, vec![0]);
+        assert!(matches!(
+            remap(&gray, &palette, Dither::None),
+            Err(NetpbmError::ChannelMismatch { .. })
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_palette_image() {
+        let palette = Palette::new(255, vec![[1, 2, 3], [4, 5, 6]]).expect("palette");
+        let image = palette.to_image(EncodingType::Plain);
+        assert_eq!((image.width(), image.height()), (2, 1));
+
+        let mut buf = Vec::new();
+        image.write(&mut buf).expect("write");
+        assert_eq!(buf, b"P3\n2 1 255\n1 2 3\n4 5 6\n");
+        assert_eq!(Palette::read(&buf[..]), Ok(palette));
+
+        let image = ppm(3, 1, 255, [[1, 2, 3], [1, 2, 3], [7, 8, 9]].concat());
+        let palette = Palette::from_image(&image).expect("palette");
+        assert_eq!(palette.colors(), [[1, 2, 3], [7, 8, 9]]);
+
+        assert!(Palette::new(255, vec![[256, 0, 0]]).is_err());
+        assert!(Palette::new(255, vec![]).is_err());
+    }
+}
@@ -0,0 +1,348 @@
+//! Image scaling.
+//!
+//! Images are resampled with a separable filter: rows are
+//! resampled first, followed by columns. Intermediate values are
+//! kept in floating point, so 16-bit samples keep their precision.
+//!
+//! When shrinking an image, the filter is stretched to cover every
+//! source sample that maps to an output sample. This makes the
+//! `Box` filter an area average, similar to netpbm's `pamscale`.
+//!
+//! Scaling keeps the format, encoding, bit depth, channel count,
+//! and type info of the input image.
+//!
+
+use crate::image::Image;
+use crate::math;
+use crate::{ImageDim, NetpbmError};
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::f64::consts::PI;
+
+/// Resampling filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Filter {
+    /// Use the nearest source sample.
+    Nearest,
+
+    /// Linear interpolation between the two nearest samples.
+    Bilinear,
+
+    /// Catmull-Rom cubic interpolation over four samples.
+    Bicubic,
+
+    /// Lanczos windowed sinc over six samples.
+    Lanczos3,
+
+    /// Average of the samples covered by each output sample.
+    Box,
+}
+
+impl Filter {
+    /// Get the radius of the filter kernel.
+    fn support(&self) -> f64 {
+        match self {
+            Filter::Nearest | Filter::Box => 0.5,
+            Filter::Bilinear => 1.0,
+            Filter::Bicubic => 2.0,
+            Filter::Lanczos3 => 3.0,
+        }
+    }
+
+    /// Evaluate the filter kernel at `x`.
+    fn kernel(&self, x: f64) -> f64 {
+        match self {
+            Filter::Nearest | Filter::Box => {
+                if (-0.5..0.5).contains(&x) {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Filter::Bilinear => (1.0 - x.abs()).max(0.0),
+            Filter::Bicubic => {
+                // Catmull-Rom spline, i.e. a = -0.5.
+                let a = -0.5;
+                let x = x.abs();
+                if x < 1.0 {
+                    (a + 2.0) * math::powi(x, 3) - (a + 3.0) * math::powi(x, 2) + 1.0
+                } else if x < 2.0 {
+                    a * math::powi(x, 3) - 5.0 * a * math::powi(x, 2) + 8.0 * a * x - 4.0 * a
+                } else {
+                    0.0
+                }
+            }
+            Filter::Lanczos3 => {
+                if x == 0.0 {
+                    1.0
+                } else if x.abs() < 3.0 {
+                    let px = PI * x;
+                    3.0 * math::sin(px) * math::sin(px / 3.0) / (px * px)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// Source samples that contribute to one output sample.
+struct Contribution {
+    start: usize,
+    weights: Vec<f64>,
+}
+
+/// Resize an image to exactly `width` by `height`.
+pub fn resize(
+    image: &Image,
+    width: u32,
+    height: u32,
+    filter: Filter,
+) -> Result<Image, NetpbmError> {
+    let mut info = image.info().clone();
+    info.width = ImageDim::new(width)?;
+    info.height = ImageDim::new(height)?;
+
+    let channels = image.channels() as usize;
+    let (src_width, src_height) = (image.width() as usize, image.height() as usize);
+    let (width, height) = (width as usize, height as usize);
+
+    // Both buffers are sized by the target dimensions, which may be
+    // far larger than the source image.
+    let too_large = || NetpbmError::InvalidArgument {
+        info: format!(
+            "Resized image of {}x{} with {} channels is too large",
+            width, height, channels
+        ),
+    };
+    let row_len = width.checked_mul(channels).ok_or_else(too_large)?;
+    let len = info.sample_count().ok_or_else(too_large)?;
+    let mut rows = Vec::new();
+    rows.try_reserve_exact(row_len.checked_mul(src_height).ok_or_else(too_large)?)
+        .map_err(|_| too_large())?;
+    let mut samples = Vec::new();
+    samples.try_reserve_exact(len).map_err(|_| too_large())?;
+
+    // Resample rows.
+    let columns = contributions(src_width, width, filter);
+    rows.resize(row_len * src_height, 0.0);
+    for y in 0..src_height {
+        let src = image.row(y as u32);
+        let dst = &mut rows[y * row_len..(y + 1) * row_len];
+        for (x, contribution) in columns.iter().enumerate() {
+            for (i, weight) in contribution.weights.iter().enumerate() {
+                let src = &src[(contribution.start + i) * channels..][..channels];
+                for c in 0..channels {
+                    dst[x * channels + c] += weight * src[c] as f64;
+                }
+            }
+        }
+    }
+
+    // Resample columns.
+    let maxval = image.bit_depth() as f64;
+    for contribution in contributions(src_height, height, filter) {
+        let mut dst = vec![0.0; row_len];
+        for (i, weight) in contribution.weights.iter().enumerate() {
+            let src = &rows[(contribution.start + i) * row_len..][..row_len];
+            for (d, s) in dst.iter_mut().zip(src) {
+                *d += weight * s;
+            }
+        }
+        samples.extend(
+            dst.into_iter()
+                .map(|s| math::round(s).clamp(0.0, maxval) as u16),
+        );
+    }
+
+    Image::new(info, image.type_info().clone(), samples)
+}
+
+/// Resize an image to the largest size that fits within `width`
+/// by `height` while keeping its aspect ratio.
+pub fn resize_to_fit(
+    image: &Image,
+    width: u32,
+    height: u32,
+    filter: Filter,
+) -> Result<Image, NetpbmError> {
+    ImageDim::new(width)?;
+    ImageDim::new(height)?;
+
+    let factor = f64::min(
+        width as f64 / image.width() as f64,
+        height as f64 / image.height() as f64,
+    );
+    let fit_width = (math::round(image.width() as f64 * factor) as u32).clamp(1, width);
+    let fit_height = (math::round(image.height() as f64 * factor) as u32).clamp(1, height);
+
+    resize(image, fit_width, fit_height, filter)
+}
+
+/// Resize an image by a scale factor applied to both dimensions.
+///
+/// Scaled dimensions are rounded to the nearest integer, and
+/// are at least 1.
+///
+pub fn scale(image: &Image, factor: f64, filter: Filter) -> Result<Image, NetpbmError> {
+    if !(factor.is_finite() && factor > 0.0) {
+        return Err(NetpbmError::InvalidArgument {
+            info: format!("Scale factor {} should be positive", factor),
+        });
+    }
+
+    let scaled = |dim: u32| {
+        let dim = math::round(dim as f64 * factor).max(1.0);
+        if dim > u32::MAX as f64 {
+            return Err(NetpbmError::InvalidArgument {
+                info: format!("Scaled dimension {} is too large", dim),
+            });
+        }
+        Ok(dim as u32)
+    };
+
+    resize(
+        image,
+        scaled(image.width())?,
+        scaled(image.height())?,
+        filter,
+    )
+}
+
+/// Compute the contributions of source samples to each output
+/// sample along one axis.
+fn contributions(src_len: usize, dst_len: usize, filter: Filter) -> Vec<Contribution> {
+    let ratio = src_len as f64 / dst_len as f64;
+
+    if filter == Filter::Nearest {
+        return (0..dst_len)
+            .map(|i| Contribution {
+                start: (((i as f64 + 0.5) * ratio) as usize).min(src_len - 1),
+                weights: vec![1.0],
+            })
+            .collect();
+    }
+
+    // Stretch the filter when shrinking so every source sample contributes.
+    let filter_scale = ratio.max(1.0);
+    let support = filter.support() * filter_scale;
+
+    (0..dst_len)
+        .map(|i| {
+            let center = (i as f64 + 0.5) * ratio;
+            let start = math::floor(center - support).max(0.0) as usize;
+            let end = (math::ceil(center + support) as usize).min(src_len);
+
+            let mut weights: Vec<f64> = (start..end)
+                .map(|j| filter.kernel((j as f64 + 0.5 - center) / filter_scale))
+                .collect();
+
+            let total: f64 = weights.iter().sum();
+            if total.abs() < f64::EPSILON {
+                // Fall back to the nearest sample.
+                return Contribution {
+                    start: (center as usize).min(src_len - 1),
+                    weights: vec![1.0],
+                };
+            }
+            weights.iter_mut().for_each(|w| *w /= total);
+
+            Contribution { start, weights }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{pgm, ppm};
+    use crate::{Info, TypeInfo};
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_resize_nearest() {
+        let image = pgm(2, 2, 255, vec![1, 2, 3, 4]);
+        let scaled = resize(&image, 4, 4, Filter::Nearest).expect("resize");
+        assert_eq!(
+            scaled.samples(),
+            [1, 1, 2, 2, 1, 1, 2, 2, 3, 3, 4, 4, 3, 3, 4, 4]
+        );
+        assert_eq!(scaled.info().format, image.info().format);
+        assert_eq!(scaled.bit_depth(), 255);
+    }
+
+    #[test]
+    fn test_resize_box() {
+        let image = pgm(
+            4,
+            2,
+            65535,
+            vec![1000, 1002, 0, 65535, 1000, 1002, 2, 65535],
+        );
+        let scaled = resize(&image, 2, 1, Filter::Box).expect("resize");
+        assert_eq!(scaled.samples(), [1001, 32768]);
+    }
+
+    #[test]
+    fn test_resize_preserves_flat_images() {
+        let info = Info::new_pam(5, 3, 4000, 2).expect("pam");
+        let type_info = TypeInfo::Info(vec!["GRAYSCALE_ALPHA".to_string()]);
+        let samples = [3999, 17].repeat(15);
+        let image = Image::new(info, type_info, samples).expect("image");
+
+        for filter in [
+            Filter::Nearest,
+            Filter::Bilinear,
+            Filter::Bicubic,
+            Filter::Lanczos3,
+            Filter::Box,
+        ] {
+            for (width, height) in [(5, 3), (2, 7), (13, 1)] {
+                let scaled = resize(&image, width, height, filter).expect("resize");
+                assert_eq!(
+                    scaled.samples(),
+                    [3999, 17].repeat((width * height) as usize)
+                );
+                assert_eq!(scaled.type_info(), image.type_info());
+                assert_eq!(scaled.channels(), 2);
+            }
+        }
+    }
+
+    #[test]
+    fn test_resize_bilinear() {
+        let image = pgm(2, 1, 65535, vec![0, 40000]);
+        let scaled = resize(&image, 4, 1, Filter::Bilinear).expect("resize");
+        assert_eq!(scaled.samples(), [0, 10000, 30000, 40000]);
+    }
+
+    #[test]
+    fn test_resize_targets() {
+        let image = pgm(40, 20, 255, vec![0; 800]);
+        let fit = resize_to_fit(&image, 10, 10, Filter::Box).expect("fit");
+        assert_eq!((fit.width(), fit.height()), (10, 5));
+        let scaled = scale(&image, 0.01, Filter::Bicubic).expect("scale");
+        assert_eq!((scaled.width(), scaled.height()), (1, 1));
+        let scaled = scale(&image, 1.5, Filter::Lanczos3).expect("scale");
+        assert_eq!((scaled.width(), scaled.height()), (60, 30));
+
+        assert!(resize(&image, 0, 10, Filter::Box).is_err());
+        assert!(scale(&image, -1.0, Filter::Box).is_err());
+        assert!(scale(&image, f64::NAN, Filter::Box).is_err());
+    }
+
+    #[test]
+    fn test_resize_too_large() {
+        // The sample count of the target overflows `usize`.
+        let image = ppm(1, 1, 255, vec![1, 2, 3]);
+        assert!(matches!(
+            resize(&image, u32::MAX, u32::MAX, Filter::Box),
+            Err(NetpbmError::InvalidArgument { .. })
+        ));
+        assert!(matches!(
+            scale(&image, 4e9, Filter::Nearest),
+            Err(NetpbmError::InvalidArgument { .. })
+        ));
+    }
+}
@@ -0,0 +1,284 @@
+//! Image statistics.
+//!
+//! `statistics` computes the minimum, maximum, mean, standard
+//! deviation, and histogram of each channel of an image, similar
+//! to netpbm's `pamsumm`. Histograms have one bin per sample value,
+//! so they have `bit_depth + 1` bins.
+//!
+//! `color_histogram` counts the unique colors of a PPM image,
+//! similar to netpbm's `ppmhist`.
+//!
+//! Both results implement `Display`, which formats them as a
+//! plain-text report.
+//!
+
+use crate::image::Image;
+use crate::math;
+use crate::{BitDepth, ChannelDepth, NetpbmError};
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Statistics of one channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelStats {
+    /// The smallest sample value.
+    pub min: u16,
+
+    /// The largest sample value.
+    pub max: u16,
+
+    /// The mean sample value.
+    pub mean: f64,
+
+    /// The population standard deviation of the sample values.
+    pub std_dev: f64,
+
+    /// The number of samples with each value.
+    pub histogram: Vec<u64>,
+}
+
+impl ChannelStats {
+    /// Compute the statistics of a histogram.
+    fn from_histogram(histogram: Vec<u64>) -> Self {
+        let count: u64 = histogram.iter().sum();
+        let values = || {
+            histogram
+                .iter()
+                .enumerate()
+                .filter(|(_, &n)| n > 0)
+                .map(|(value, &n)| (value as f64, n as f64))
+        };
+
+        let min = histogram.iter().position(|&n| n > 0).unwrap_or(0) as u16;
+        let max = histogram.iter().rposition(|&n| n > 0).unwrap_or(0) as u16;
+        let mean = values().map(|(v, n)| v * n).sum::<f64>() / count as f64;
+        let variance = values()
+            .map(|(v, n)| math::powi(v - mean, 2) * n)
+            .sum::<f64>()
+            / count as f64;
+
+        ChannelStats {
+            min,
+            max,
+            mean,
+            std_dev: math::sqrt(variance),
+            histogram,
+        }
+    }
+}
+
+/// Statistics of every channel of an image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    /// The image bit depth.
+    pub bit_depth: BitDepth,
+
+    /// The number of tuples in the image.
+    pub tuples: u64,
+
+    /// The statistics of each channel.
+    pub channels: Vec<ChannelStats>,
+}
+
+/// Number of occurrences of a color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ColorCount {
+    /// The red, green, and blue samples of the color.
+    pub color: [u16; 3],
+
+    /// The number of pixels with the color.
+    pub count: u64,
+}
+
+/// Unique colors of an image, from most to least frequent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColorHistogram {
+    /// The image bit depth.
+    pub bit_depth: BitDepth,
+
+    /// The unique colors. Colors with the same count are
+    /// ordered by their samples.
+    pub colors: Vec<ColorCount>,
+}
+
+/// Compute the statistics of each channel of an image.
+pub fn statistics(image: &Image) -> Stats {
+    let channels = image.channels() as usize;
+    let bins = image.bit_depth() as usize + 1;
+
+    let mut histograms = vec![vec![0u64; bins]; channels];
+    for tuple in image.samples().chunks_exact(channels) {
+        for (histogram, &sample) in histograms.iter_mut().zip(tuple) {
+            histogram[sample as usize] += 1;
+        }
+    }
+
+    Stats {
+        bit_depth: image.info().bit_depth,
+        tuples: image.width() as u64 * image.height() as u64,
+        channels: histograms
+            .into_iter()
+            .map(ChannelStats::from_histogram)
+            .collect(),
+    }
+}
+
+/// Count the unique colors of an image with 3 channels.
+pub fn color_histogram(image: &Image) -> Result<ColorHistogram, NetpbmError> {
+    if image.channels() != 3 {
+        return Err(NetpbmError::ChannelMismatch {
+            expected: ChannelDepth::new(3).expect("RGB24 channel depth"),
+            found: image.info().channels,
+        });
+    }
+
+    let mut counts: BTreeMap<[u16; 3], u64> = BTreeMap::new();
+    for rgb in image.samples().chunks_exact(3) {
+        *counts.entry([rgb[0], rgb[1], rgb[2]]).or_default() += 1;
+    }
+
+    let mut colors: Vec<ColorCount> = counts
+        .into_iter()
+        .map(|(color, count)| ColorCount { color, count })
+        .collect();
+    colors.sort_by(|a, b| b.count.cmp(&a.count).then(a.color.cmp(&b.color)));
+
+    Ok(ColorHistogram {
+        bit_depth: image.info().bit_depth,
+        colors,
+    })
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "tuples: {}", self.tuples)?;
+        writeln!(f, "maxval: {}", self.bit_depth)?;
+        for (channel, stats) in self.channels.iter().enumerate() {
+            writeln!(
+                f,
+                "channel {}: min {} max {} mean {:.3} stddev {:.3}",
+                channel, stats.min, stats.max, stats.mean, stats.std_dev
+            )?;
+        }
+
+        for (channel, stats) in self.channels.iter().enumerate() {
+            writeln!(f)?;
+            writeln!(f, "channel {} histogram:", channel)?;
+            for (value, &count) in stats.histogram.iter().enumerate() {
+                if count > 0 {
+                    writeln!(f, "{:>7} {:>10}", value, count)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for ColorHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>7} {:>7} {:>7} {:>7} {:>10}",
+            "r", "g", "b", "lum", "count"
+        )?;
+        writeln!(
+            f,
+            "{:>7} {:>7} {:>7} {:>7} {:>10}",
+            "-----", "-----", "-----", "-----", "-----"
+        )?;
+        for ColorCount { color, count } in &self.colors {
+            // Rec. 601 luma, as reported by ppmhist.
+            let [r, g, b] = color.map(|s| s as f64);
+            let lum = math::round(0.299 * r + 0.587 * g + 0.114 * b);
+            writeln!(
+                f,
+                "{:>7} {:>7} {:>7} {:>7} {:>10}",
+                color[0], color[1], color[2], lum, count
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{pgm, ppm};
+    use crate::{Info, TypeInfo};
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_statistics() {
+        let info = Info::new_pam(2, 2, 1000, 2).expect("pam");
+        let samples = vec![0, 1000, 2, 1000, 4, 1000, 6, 0];
+        let image = Image::new(info, TypeInfo::Empty, samples).expect("image");
+
+        let stats = statistics(&image);
+        assert_eq!(stats.tuples, 4);
+        assert_eq!(stats.channels.len(), 2);
+
+        let first = &stats.channels[0];
+        assert_eq!((first.min, first.max), (0, 6));
+        assert_eq!(first.mean, 3.0);
+        assert_eq!(first.std_dev, 5.0f64.sqrt());
+        assert_eq!(first.histogram.len(), 1001);
+        assert_eq!(first.histogram[..7], [1, 0, 1, 0, 1, 0, 1]);
+
+        let second = &stats.channels[1];
+        assert_eq!((second.min, second.max), (0, 1000));
+        assert_eq!(second.mean, 750.0);
+        assert_eq!(second.histogram[1000], 3);
+    }
+
+    #[test]
+    fn test_statistics_report() {
+        let image = pgm(2, 1, 255, vec![10, 20]);
+
+        let report = statistics(&image).to_string();
+        assert_eq!(
+            report,
+            "tuples: 2\nmaxval: 255\nchannel 0: min 10 max 20 mean 15.000 stddev 5.000\n\n\
+             channel 0 histogram:\n     10          1\n     20          1\n"
+        );
+    }
+
+    #[test]
+    fn test_color_histogram() {
+        let samples = vec![255, 0, 0, 0, 0, 255, 255, 0, 0, 0, 0, 0];
+        let image = ppm(4, 1, 255, samples);
+
+        let histogram = color_histogram(&image).expect("histogram");
+        assert_eq!(
+            histogram.colors,
+            [
+                ColorCount {
+                    color: [255, 0, 0],
+                    count: 2
+                },
+                ColorCount {
+                    color: [0, 0, 0],
+                    count: 1
+                },
+                ColorCount {
+                    color: [0, 0, 255],
+                    count: 1
+                },
+            ]
+        );
+
+        let report = histogram.to_string();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[2], "    255       0       0      76          2");
+
+        let image = pgm(1, 1, 255, vec![0]);
+        assert!(matches!(
+            color_histogram(&image),
+            Err(NetpbmError::ChannelMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,38 @@
+//! Fixtures shared by the unit tests.
+
+use crate::bitmap::Bitmap;
+use crate::image::Image;
+use crate::{EncodingType, Info, TypeInfo};
+use alloc::vec::Vec;
+
+/// Create the info of a `raw` PGM image.
+pub fn pgm_info(width: u32, height: u32, bit_depth: u16) -> Info {
+    Info::new_pgm(EncodingType::Raw, width, height, bit_depth).expect("pgm")
+}
+
+/// Create a `raw` PGM image.
+pub fn pgm(width: u32, height: u32, bit_depth: u16, samples: Vec<u16>) -> Image {
+    Image::new(pgm_info(width, height, bit_depth), TypeInfo::Empty, samples).expect("pgm")
+}
+
+/// Create a `raw` PGM image with a single row of samples.
+pub fn pgm_row(bit_depth: u16, samples: Vec<u16>) -> Image {
+    pgm(samples.len() as u32, 1, bit_depth, samples)
+}
+
+/// Create a `raw` PPM image.
+pub fn ppm(width: u32, height: u32, bit_depth: u16, samples: Vec<u16>) -> Image {
+    let info = Info::new_ppm(EncodingType::Raw, width, height, bit_depth).expect("ppm");
+    Image::new(info, TypeInfo::Empty, samples).expect("ppm")
+}
+
+/// Create a bitmap from rows of `x` for black and `.` for white.
+pub fn bitmap(rows: &[&str]) -> Bitmap {
+    let mut bitmap = Bitmap::new(rows[0].len() as u32, rows.len() as u32).expect("bitmap");
+    for (y, row) in rows.iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            bitmap.set(x as u32, y as u32, c == 'x');
+        }
+    }
+    bitmap
+}
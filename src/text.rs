@@ -0,0 +1,669 @@
+//! Bitmap font text rendering.
+//!
+//! `render` draws text into a new bitmap, similar to netpbm's
+//! `pbmtext`. `draw` stamps text onto an existing image at a
+//! position, with a foreground and optional background tuple.
+//!
+//! `Font::builtin` is a fixed-width 5x7 font covering printable
+//! ASCII, in cells of 6x8 pixels. Other fonts can be loaded from
+//! BDF (Glyph Bitmap Distribution Format) files, which may be
+//! proportional.
+//!
+//! Text may span several lines separated by `\n`. Characters
+//! missing from a font are drawn with the font's default character,
+//! or `?`, or skipped if neither exists.
+//!
+
+use crate::bitmap::Bitmap;
+use crate::image::Image;
+#[cfg(feature = "std")]
+use crate::io;
+use crate::NetpbmError;
+use alloc::collections::BTreeMap;
+use alloc::format;
+#[cfg(feature = "std")]
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+/// A glyph of a bitmap font.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Glyph {
+    /// The distance from the start of this glyph to the next.
+    pub advance: u32,
+
+    /// The offset of the left edge of the bitmap from the pen.
+    pub x_offset: i32,
+
+    /// The offset of the top edge of the bitmap from the top of
+    /// the line.
+    pub y_offset: i32,
+
+    /// The glyph pixels, or `None` for blank glyphs.
+    pub bitmap: Option<Bitmap>,
+}
+
+/// A bitmap font.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Font {
+    line_height: u32,
+    glyphs: BTreeMap<char, Glyph>,
+    default_char: Option<char>,
+}
+
+impl Font {
+    /// Create a font from its glyphs.
+    pub fn new(
+        line_height: u32,
+        glyphs: BTreeMap<char, Glyph>,
+        default_char: Option<char>,
+    ) -> Result<Self, NetpbmError> {
+        if line_height == 0 {
+            return Err(NetpbmError::InvalidArgument {
+                info: "Font line height should be greater than 0".to_string(),
+            });
+        }
+
+        Ok(Font {
+            line_height,
+            glyphs,
+            default_char,
+        })
+    }
+
+    /// Get the built-in 5x7 font.
+    pub fn builtin() -> Self {
+        let glyphs = (' '..='~')
+            .zip(FONT_5X7.iter())
+            .map(|(c, columns)| {
+                let mut bitmap = Bitmap::new(5, 7).expect("Builtin glyph");
+                for (x, column) in columns.iter().enumerate() {
+                    for y in 0..7 {
+                        bitmap.set(x as u32, y, column & (1 << y) != 0);
+                    }
+                }
+                let glyph = Glyph {
+                    advance: 6,
+                    x_offset: 0,
+                    y_offset: 0,
+                    bitmap: Some(bitmap),
+                };
+                (c, glyph)
+            })
+            .collect();
+
+        Self::new(8, glyphs, Some('?')).expect("Builtin font")
+    }
+
+    /// Decode a BDF font from the given reader.
+    #[cfg(feature = "std")]
+    pub fn read_bdf<R: io::Read>(mut reader: R) -> Result<Self, NetpbmError> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        Self::parse_bdf(&text)
+    }
+
+    /// Parse a BDF font.
+    ///
+    /// Glyphs are placed using the `FONT_ASCENT` and `FONT_DESCENT`
+    /// properties if present, or the font bounding box otherwise.
+    /// Glyphs with an encoding of -1 are skipped.
+    ///
+    pub fn parse_bdf(text: &str) -> Result<Self, NetpbmError> {
+        let mut bounding_box = None;
+        let mut ascent = None;
+        let mut descent = None;
+        let mut default_char = None;
+        let mut glyphs = BTreeMap::new();
+
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()));
+        let mut last = 0;
+        while let Some((line, content)) = lines.next() {
+            last = line;
+            let (keyword, args) = content.split_once(' ').unwrap_or((content, ""));
+            match keyword {
+                "FONTBOUNDINGBOX" => {
+                    let [_, height, _, y_offset] = numbers::<4>(line, args)?;
+                    bounding_box = Some((
+                        convert::<u32>(line, "Font height", height)?,
+                        convert::<i32>(line, "Font offset", y_offset)?,
+                    ));
+                }
+                "FONT_ASCENT" => {
+                    let [value] = numbers::<1>(line, args)?;
+                    ascent = Some(convert::<i32>(line, "Font ascent", value)?);
+                }
+                "FONT_DESCENT" => {
+                    let [value] = numbers::<1>(line, args)?;
+                    descent = Some(convert::<i32>(line, "Font descent", value)?);
+                }
+                "DEFAULT_CHAR" => {
+                    let [code] = numbers::<1>(line, args)?;
+                    default_char = u32::try_from(code).ok().and_then(char::from_u32);
+                }
+                "STARTCHAR" => {
+                    let (encoding, glyph, end) = parse_glyph(&mut lines, line)?;
+                    last = end;
+                    if let Some(c) = encoding {
+                        glyphs.insert(c, glyph);
+                    }
+                }
+                "ENDFONT" => break,
+                _ => {}
+            }
+        }
+
+        let (height, y_offset) = bounding_box.ok_or_else(|| NetpbmError::MalformedFont {
+            line: last,
+            info: "Missing FONTBOUNDINGBOX".to_string(),
+        })?;
+        let ascent = match ascent {
+            Some(ascent) => ascent,
+            None => convert(last, "Font ascent", height as i64 + y_offset as i64)?,
+        };
+        let descent = match descent {
+            Some(descent) => descent,
+            None => convert(last, "Font descent", -(y_offset as i64))?,
+        };
+
+        // Glyph offsets were read relative to the baseline.
+        for glyph in glyphs.values_mut() {
+            glyph.y_offset = convert(last, "Glyph offset", glyph.y_offset as i64 + ascent as i64)?;
+        }
+
+        let height = ascent as i64 + descent as i64;
+        let line_height = u32::try_from(height).unwrap_or(0);
+        Self::new(line_height, glyphs, default_char).map_err(|_| NetpbmError::MalformedFont {
+            line: last,
+            info: format!("Font height {} should be greater than 0", height),
+        })
+    }
+
+    /// Get the height of a line of text.
+    pub fn line_height(&self) -> u32 {
+        self.line_height
+    }
+
+    /// Get the glyphs of the font.
+    pub fn glyphs(&self) -> &BTreeMap<char, Glyph> {
+        &self.glyphs
+    }
+
+    /// Get the glyph drawn for a character.
+    pub fn glyph(&self, c: char) -> Option<&Glyph> {
+        self.glyphs
+            .get(&c)
+            .or_else(|| self.default_char.and_then(|d| self.glyphs.get(&d)))
+            .or_else(|| self.glyphs.get(&'?'))
+    }
+
+    /// Get the width and height of rendered text.
+    pub fn measure(&self, text: &str) -> Result<(u32, u32), NetpbmError> {
+        let too_large = || NetpbmError::InvalidArgument {
+            info: format!("Text {:?} is too large to measure", text),
+        };
+        let mut width = 0;
+        let mut lines = 0u32;
+        for line in text.split('\n') {
+            let line_width = line
+                .chars()
+                .filter_map(|c| self.glyph(c))
+                .try_fold(0u32, |sum, g| sum.checked_add(g.advance))
+                .ok_or_else(too_large)?;
+            width = width.max(line_width);
+            lines = lines.checked_add(1).ok_or_else(too_large)?;
+        }
+        let height = lines.checked_mul(self.line_height).ok_or_else(too_large)?;
+        Ok((width, height))
+    }
+
+    /// Get the coordinates of the black pixels of rendered text.
+    fn pixels(&self, text: &str) -> Vec<(i64, i64)> {
+        let mut pixels = Vec::new();
+        for (row, line) in text.split('\n').enumerate() {
+            let top = row as i64 * self.line_height as i64;
+            let mut pen = 0i64;
+            for glyph in line.chars().filter_map(|c| self.glyph(c)) {
+                if let Some(bitmap) = &glyph.bitmap {
+                    let left = pen + glyph.x_offset as i64;
+                    let top = top + glyph.y_offset as i64;
+                    for y in 0..bitmap.height() {
+                        for x in (0..bitmap.width()).filter(|&x| bitmap.get(x, y)) {
+                            pixels.push((left + x as i64, top + y as i64));
+                        }
+                    }
+                }
+                pen += glyph.advance as i64;
+            }
+        }
+        pixels
+    }
+}
+
+/// Render text into a new bitmap, black on white.
+pub fn render(text: &str, font: &Font) -> Result<Bitmap, NetpbmError> {
+    let (width, height) = font.measure(text)?;
+    if width == 0 {
+        return Err(NetpbmError::InvalidArgument {
+            info: format!("Text {:?} has no width", text),
+        });
+    }
+
+    let mut bitmap = Bitmap::new(width, height)?;
+    for (x, y) in font.pixels(text) {
+        if (0..width as i64).contains(&x) && (0..height as i64).contains(&y) {
+            bitmap.set(x as u32, y as u32, true);
+        }
+    }
+    Ok(bitmap)
+}
+
+/// Draw text onto an image with its top-left corner at `(x, y)`.
+///
+/// Text pixels take the `foreground` tuple. If a `background` tuple
+/// is given, the rectangle measured for the text is filled with it
+/// first. Text past the edges of the image is clipped.
+///
+pub fn draw(
+    image: &mut Image,
+    text: &str,
+    font: &Font,
+    x: i64,
+    y: i64,
+    foreground: &[u16],
+    background: Option<&[u16]>,
+) -> Result<(), NetpbmError> {
+    image.info().validate_tuple(foreground)?;
+    if let Some(background) = background {
+        image.info().validate_tuple(background)?;
+    }
+
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let inside = |px: i64, py: i64| (0..width).contains(&px) && (0..height).contains(&py);
+
+    if let Some(background) = background {
+        let (w, h) = font.measure(text)?;
+        for py in y.max(0)..y.saturating_add(h as i64).min(height) {
+            for px in x.max(0)..x.saturating_add(w as i64).min(width) {
+                image
+                    .tuple_mut(px as u32, py as u32)
+                    .copy_from_slice(background);
+            }
+        }
+    }
+
+    for (px, py) in font.pixels(text) {
+        let (px, py) = (x.saturating_add(px), y.saturating_add(py));
+        if inside(px, py) {
+            image
+                .tuple_mut(px as u32, py as u32)
+                .copy_from_slice(foreground);
+        }
+    }
+
+    Ok(())
+}
+
+/// Parse a glyph from the line after `STARTCHAR` to `ENDCHAR`.
+///
+/// Returns the character, the glyph with its vertical offset
+/// relative to the baseline, and the line number of `ENDCHAR`.
+///
+fn parse_glyph<'a>(
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+    start: usize,
+) -> Result<(Option<char>, Glyph, usize), NetpbmError> {
+    let mut encoding = None;
+    let mut advance = None;
+    let mut bbx: Option<(u32, u32, i32, i32)> = None;
+    let mut rows: Vec<Vec<u8>> = Vec::new();
+    let mut in_bitmap = false;
+
+    for (line, content) in lines.by_ref() {
+        let (keyword, args) = content.split_once(' ').unwrap_or((content, ""));
+        match keyword {
+            "ENDCHAR" => {
+                let (width, height, x_offset, y_offset) =
+                    bbx.ok_or_else(|| malformed(line, "Missing BBX"))?;
+                let advance = advance.unwrap_or(width);
+                let bitmap = if width == 0 || height == 0 {
+                    None
+                } else {
+                    if rows.len() != height as usize {
+                        return Err(malformed(
+                            line,
+                            &format!("Expected {} bitmap rows, found {}", height, rows.len()),
+                        ));
+                    }
+                    // Rows hold every byte of the glyph width, so the
+                    // bitmap is no larger than the input.
+                    let row_len = (width as usize).div_ceil(8);
+                    if rows.iter().any(|row| row.len() < row_len) {
+                        return Err(malformed(
+                            line,
+                            &format!("Bitmap rows should be {} bytes long", row_len),
+                        ));
+                    }
+                    let mut data = Vec::with_capacity(row_len * rows.len());
+                    for row in &rows {
+                        // Rows may be padded past the glyph width.
+                        data.extend_from_slice(&row[..row_len]);
+                    }
+                    Some(Bitmap::from_packed(width, height, data)?)
+                };
+
+                let glyph = Glyph {
+                    advance,
+                    x_offset,
+                    y_offset: convert(line, "Glyph offset", -(height as i64 + y_offset as i64))?,
+                    bitmap,
+                };
+                return Ok((encoding, glyph, line));
+            }
+            _ if in_bitmap => rows.push(hex_row(line, content)?),
+            "ENCODING" => {
+                let [code] = numbers::<1>(line, args)?;
+                encoding = u32::try_from(code).ok().and_then(char::from_u32);
+            }
+            "DWIDTH" => {
+                let [advance_x, _] = numbers::<2>(line, args)?;
+                advance = Some(convert(line, "Glyph advance", advance_x.max(0))?);
+            }
+            "BBX" => {
+                let [width, height, x_offset, y_offset] = numbers::<4>(line, args)?;
+                bbx = Some((
+                    convert(line, "Glyph width", width)?,
+                    convert(line, "Glyph height", height)?,
+                    convert(line, "Glyph offset", x_offset)?,
+                    convert(line, "Glyph offset", y_offset)?,
+                ));
+            }
+            "BITMAP" => in_bitmap = true,
+            _ => {}
+        }
+    }
+
+    Err(malformed(start, "STARTCHAR without ENDCHAR"))
+}
+
+/// Parse whitespace-separated integers.
+fn numbers<const N: usize>(line: usize, args: &str) -> Result<[i64; N], NetpbmError> {
+    let mut values = [0; N];
+    let mut tokens = args.split_whitespace();
+    for value in values.iter_mut() {
+        *value = tokens
+            .next()
+            .and_then(|token| token.parse().ok())
+            .ok_or_else(|| {
+                malformed(line, &format!("Expected {} integers, found {:?}", N, args))
+            })?;
+    }
+    Ok(values)
+}
+
+/// Convert a parsed integer to the type of a font value.
+fn convert<T: TryFrom<i64>>(line: usize, what: &str, value: i64) -> Result<T, NetpbmError> {
+    T::try_from(value).map_err(|_| malformed(line, &format!("{} {} is out of range", what, value)))
+}
+
+/// Parse a row of hexadecimal bitmap bytes.
+fn hex_row(line: usize, row: &str) -> Result<Vec<u8>, NetpbmError> {
+    if !row.len().is_multiple_of(2) || !row.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(malformed(line, &format!("Invalid bitmap row {:?}", row)));
+    }
+    Ok((0..row.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&row[i..i + 2], 16).expect("Hex digits"))
+        .collect())
+}
+
+fn malformed(line: usize, info: &str) -> NetpbmError {
+    NetpbmError::MalformedFont {
+        line,
+        info: info.to_string(),
+    }
+}
+
+/// Glyphs of the built-in font from `' '` to `'~'`. Each glyph is
+/// 5 columns, with the top row in the least significant bit.
+#[rustfmt::skip]
+const FONT_5X7: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5F, 0x00, 0x00], // '!'
+    [0x00, 0x07, 0x00, 0x07, 0x00], // '"'
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], // '#'
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], // '$'
+    [0x23, 0x13, 0x08, 0x64, 0x62], // '%'
+    [0x36, 0x49, 0x55, 0x22, 0x50], // '&'
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '''
+    [0x00, 0x1C, 0x22, 0x41, 0x00], // '('
+    [0x00, 0x41, 0x22, 0x1C, 0x00], // ')'
+    [0x08, 0x2A, 0x1C, 0x2A, 0x08], // '*'
+    [0x08, 0x08, 0x3E, 0x08, 0x08], // '+'
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ','
+    [0x08, 0x08, 0x08, 0x08, 0x08], // '-'
+    [0x00, 0x60, 0x60, 0x00, 0x00], // '.'
+    [0x20, 0x10, 0x08, 0x04, 0x02], // '/'
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], // '0'
+    [0x00, 0x42, 0x7F, 0x40, 0x00], // '1'
+    [0x42, 0x61, 0x51, 0x49, 0x46], // '2'
+    [0x21, 0x41, 0x45, 0x4B, 0x31], // '3'
+    [0x18, 0x14, 0x12, 0x7F, 0x10], // '4'
+    [0x27, 0x45, 0x45, 0x45, 0x39], // '5'
+    [0x3C, 0x4A, 0x49, 0x49, 0x30], // '6'
+    [0x01, 0x71, 0x09, 0x05, 0x03], // '7'
+    [0x36, 0x49, 0x49, 0x49, 0x36], // '8'
+    [0x06, 0x49, 0x49, 0x29, 0x1E], // '9'
+    [0x00, 0x36, 0x36, 0x00, 0x00], // ':'
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ';'
+    [0x08, 0x14, 0x22, 0x41, 0x00], // '<'
+    [0x14, 0x14, 0x14, 0x14, 0x14], // '='
+    [0x00, 0x41, 0x22, 0x14, 0x08], // '>'
+    [0x02, 0x01, 0x51, 0x09, 0x06], // '?'
+    [0x32, 0x49, 0x79, 0x41, 0x3E], // '@'
+    [0x7E, 0x11, 0x11, 0x11, 0x7E], // 'A'
+    [0x7F, 0x49, 0x49, 0x49, 0x36], // 'B'
+    [0x3E, 0x41, 0x41, 0x41, 0x22], // 'C'
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], // 'D'
+    [0x7F, 0x49, 0x49, 0x49, 0x41], // 'E'
+    [0x7F, 0x09, 0x09, 0x09, 0x01], // 'F'
+    [0x3E, 0x41, 0x49, 0x49, 0x7A], // 'G'
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], // 'H'
+    [0x00, 0x41, 0x7F, 0x41, 0x00], // 'I'
+    [0x20, 0x40, 0x41, 0x3F, 0x01], // 'J'
+    [0x7F, 0x08, 0x14, 0x22, 0x41], // 'K'
+    [0x7F, 0x40, 0x40, 0x40, 0x40], // 'L'
+    [0x7F, 0x02, 0x0C, 0x02, 0x7F], // 'M'
+    [0x7F, 0x04, 0x08, 0x10, 0x7F], // 'N'
+    [0x3E, 0x41, 0x41, 0x41, 0x3E], // 'O'
+    [0x7F, 0x09, 0x09, 0x09, 0x06], // 'P'
+    [0x3E, 0x41, 0x51, 0x21, 0x5E], // 'Q'
+    [0x7F, 0x09, 0x19, 0x29, 0x46], // 'R'
+    [0x46, 0x49, 0x49, 0x49, 0x31], // 'S'
+    [0x01, 0x01, 0x7F, 0x01, 0x01], // 'T'
+    [0x3F, 0x40, 0x40, 0x40, 0x3F], // 'U'
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], // 'V'
+    [0x3F, 0x40, 0x38, 0x40, 0x3F], // 'W'
+    [0x63, 0x14, 0x08, 0x14, 0x63], // 'X'
+    [0x07, 0x08, 0x70, 0x08, 0x07], // 'Y'
+    [0x61, 0x51, 0x49, 0x45, 0x43], // 'Z'
+    [0x00, 0x7F, 0x41, 0x41, 0x00], // '['
+    [0x02, 0x04, 0x08, 0x10, 0x20], // '\'
+    [0x00, 0x41, 0x41, 0x7F, 0x00], // ']'
+    [0x04, 0x02, 0x01, 0x02, 0x04], // '^'
+    [0x40, 0x40, 0x40, 0x40, 0x40], // '_'
+    [0x00, 0x01, 0x02, 0x04, 0x00], // '`'
+    [0x20, 0x54, 0x54, 0x54, 0x78], // 'a'
+    [0x7F, 0x48, 0x44, 0x44, 0x38], // 'b'
+    [0x38, 0x44, 0x44, 0x44, 0x20], // 'c'
+    [0x38, 0x44, 0x44, 0x48, 0x7F], // 'd'
+    [0x38, 0x54, 0x54, 0x54, 0x18], // 'e'
+    [0x08, 0x7E, 0x09, 0x01, 0x02], // 'f'
+    [0x0C, 0x52, 0x52, 0x52, 0x3E], // 'g'
+    [0x7F, 0x08, 0x04, 0x04, 0x78], // 'h'
+    [0x00, 0x44, 0x7D, 0x40, 0x00], // 'i'
+    [0x20, 0x40, 0x44, 0x3D, 0x00], // 'j'
+    [0x7F, 0x10, 0x28, 0x44, 0x00], // 'k'
+    [0x00, 0x41, 0x7F, 0x40, 0x00], // 'l'
+    [0x7C, 0x04, 0x18, 0x04, 0x78], // 'm'
+    [0x7C, 0x08, 0x04, 0x04, 0x78], // 'n'
+    [0x38, 0x44, 0x44, 0x44, 0x38], // 'o'
+    [0x7C, 0x14, 0x14, 0x14, 0x08], // 'p'
+    [0x08, 0x14, 0x14, 0x18, 0x7C], // 'q'
+    [0x7C, 0x08, 0x04, 0x04, 0x08], // 'r'
+    [0x48, 0x54, 0x54, 0x54, 0x20], // 's'
+    [0x04, 0x3F, 0x44, 0x40, 0x20], // 't'
+    [0x3C, 0x40, 0x40, 0x20, 0x7C], // 'u'
+    [0x1C, 0x20, 0x40, 0x20, 0x1C], // 'v'
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], // 'w'
+    [0x44, 0x28, 0x10, 0x28, 0x44], // 'x'
+    [0x0C, 0x50, 0x50, 0x50, 0x3C], // 'y'
+    [0x44, 0x64, 0x54, 0x4C, 0x44], // 'z'
+    [0x00, 0x08, 0x36, 0x41, 0x00], // '{'
+    [0x00, 0x00, 0x7F, 0x00, 0x00], // '|'
+    [0x00, 0x41, 0x36, 0x08, 0x00], // '}'
+    [0x08, 0x04, 0x08, 0x10, 0x08], // '~'
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::ppm;
+    use crate::EncodingType;
+    use alloc::vec;
+
+    const BDF: &str = "STARTFONT 2.1
+FONT -test-fixed-medium-r-normal--4-40-75-75-c-30-iso10646-1
+SIZE 4 75 75
+FONTBOUNDINGBOX 3 4 0 -1
+STARTPROPERTIES 2
+FONT_ASCENT 3
+FONT_DESCENT 1
+ENDPROPERTIES
+CHARS 2
+STARTCHAR L
+ENCODING 76
+SWIDTH 750 0
+DWIDTH 4 0
+BBX 2 3 0 0
+BITMAP
+80
+80
+C0
+ENDCHAR
+STARTCHAR space
+ENCODING 32
+DWIDTH 2 0
+BBX 0 0 0 0
+BITMAP
+ENDCHAR
+ENDFONT
+";
+
+    #[test]
+    fn test_builtin_font() {
+        let font = Font::builtin();
+        assert_eq!(font.glyphs().len(), 95);
+        assert_eq!(font.measure("Hi\nthere"), Ok((30, 16)));
+
+        let bitmap = render("H", &font).expect("render");
+        assert_eq!((bitmap.width(), bitmap.height()), (6, 8));
+        let rows: Vec<u8> = (0..8).map(|y| bitmap.row(y)[0]).collect();
+        assert_eq!(rows, [0x88, 0x88, 0x88, 0xF8, 0x88, 0x88, 0x88, 0x00]);
+
+        // Missing characters use '?'.
+        assert_eq!(render("\u{e9}", &font), render("?", &font));
+        assert!(render("", &font).is_err());
+    }
+
+    #[test]
+    fn test_bdf_font() {
+        let font = Font::parse_bdf(BDF).expect("font");
+        #[cfg(feature = "std")]
+        assert_eq!(Font::read_bdf(BDF.as_bytes()).as_ref(), Ok(&font));
+        assert_eq!(font.line_height(), 4);
+        assert_eq!(font.glyph(' ').map(|g| g.advance), Some(2));
+
+        let bitmap = render("L L", &font).expect("render");
+        assert_eq!((bitmap.width(), bitmap.height()), (10, 4));
+        let image = bitmap.to_image(EncodingType::Plain);
+        assert_eq!(image.row(0), [1, 0, 0, 0, 0, 0, 1, 0, 0, 0]);
+        assert_eq!(image.row(2), [1, 1, 0, 0, 0, 0, 1, 1, 0, 0]);
+        assert_eq!(image.row(3), [0; 10]);
+
+        let truncated = &BDF[..BDF.find("ENDCHAR").expect("ENDCHAR")];
+        assert!(matches!(
+            Font::parse_bdf(truncated),
+            Err(NetpbmError::MalformedFont { line: 10, .. })
+        ));
+        assert!(matches!(
+            Font::parse_bdf("STARTFONT 2.1\nENDFONT\n"),
+            Err(NetpbmError::MalformedFont { .. })
+        ));
+    }
+
+    #[test]
+    fn test_hostile_font() {
+        let bbx = BDF.replace("BBX 2 3 0 0", "BBX 1000000000000000000 1 0 0");
+        assert!(matches!(
+            Font::parse_bdf(&bbx),
+            Err(NetpbmError::MalformedFont { line: 14, .. })
+        ));
+        let bbx = BDF.replace(
+            "BBX 2 3 0 0\nBITMAP\n80\n80\n",
+            "BBX 4294967295 1 0 0\nBITMAP\n",
+        );
+        assert!(matches!(
+            Font::parse_bdf(&bbx),
+            Err(NetpbmError::MalformedFont { line: 17, .. })
+        ));
+        let offset = BDF.replace("BBX 2 3 0 0", "BBX 2 3 0 2147483647");
+        assert!(Font::parse_bdf(&offset).is_err());
+        let ascent = BDF.replace("FONT_ASCENT 3", "FONT_ASCENT 4294967296");
+        assert!(Font::parse_bdf(&ascent).is_err());
+
+        // Advances that sum past `u32::MAX` cannot be measured.
+        let wide = BDF.replace("DWIDTH 4 0", "DWIDTH 4294967295 0");
+        let font = Font::parse_bdf(&wide).expect("font");
+        assert_eq!(font.glyph('L').map(|g| g.advance), Some(u32::MAX));
+        assert!(matches!(
+            font.measure("LL"),
+            Err(NetpbmError::InvalidArgument { .. })
+        ));
+        assert!(render("LL", &font).is_err());
+    }
+
+    #[test]
+    fn test_draw() {
+        let font = Font::parse_bdf(BDF).expect("font");
+        let mut image = ppm(4, 3, 255, vec![9; 36]);
+
+        // Draw partly past the top-left corner.
+        draw(
+            &mut image,
+            "L",
+            &font,
+            -1,
+            -1,
+            &[255, 0, 0],
+            Some(&[0, 0, 0]),
+        )
+        .expect("draw");
+        let red: Vec<bool> = image
+            .samples()
+            .chunks_exact(3)
+            .map(|t| t == [255, 0, 0])
+            .collect();
+        assert_eq!(red.iter().filter(|&&r| r).count(), 1);
+        assert!(red[4]);
+        assert_eq!(image.tuple(2, 0), [0, 0, 0]);
+        assert_eq!(image.tuple(3, 0), [9, 9, 9]);
+        assert_eq!(image.tuple(0, 2), [0, 0, 0]);
+
+        assert!(draw(&mut image, "L", &font, 0, 0, &[256, 0, 0], None).is_err());
+        assert!(draw(&mut image, "L", &font, 0, 0, &[1], None).is_err());
+    }
+}
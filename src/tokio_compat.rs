@@ -0,0 +1,360 @@
+//! Asynchronous encoding and decoding with `tokio`.
+//!
+//! Enabled by the `tokio` feature. `Decoder` and `Encoder` read
+//! and write images over `AsyncRead` and `AsyncWrite`, and
+//! `ImageStream` decodes the images of a netpbm file as they
+//! arrive, as a `Stream`.
+//!
+//! Images are parsed and serialized with the same code as
+//! `Image::decode`, `Image::write`, and `Images`; only the
+//! transfer of bytes is asynchronous.
+//!
+
+use crate::formats::decode::{self, Scanner};
+use crate::image::{Image, Images};
+use crate::{EncodingType, NetpbmError};
+use futures_core::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+/// Smallest number of bytes read at a time by `ImageStream`.
+const CHUNK_LEN: usize = 64 * 1024;
+
+/// Asynchronous image decoder.
+#[derive(Debug)]
+pub struct Decoder<R: AsyncRead + Unpin> {
+    reader: R,
+}
+
+impl<R: AsyncRead + Unpin> Decoder<R> {
+    /// Create a new decoder with the given reader.
+    pub fn new(reader: R) -> Self {
+        Decoder { reader }
+    }
+
+    /// Decode the first image, reading until the end of the input.
+    pub async fn read(&mut self) -> Result<Image, NetpbmError> {
+        let mut img_buf = Vec::new();
+        self.reader.read_to_end(&mut img_buf).await?;
+        let (image, _) = Image::decode(&img_buf)?;
+        Ok(image)
+    }
+
+    /// Decode every image of the input as a stream.
+    pub fn images(self) -> ImageStream<R> {
+        ImageStream::new(self.reader)
+    }
+}
+
+/// Asynchronous image encoder.
+#[derive(Debug)]
+pub struct Encoder<W: AsyncWrite + Unpin> {
+    writer: W,
+}
+
+impl<W: AsyncWrite + Unpin> Encoder<W> {
+    /// Create a new encoder with the given writer.
+    pub fn new(writer: W) -> Self {
+        Encoder { writer }
+    }
+
+    /// Encode the image with the encoder for its format.
+    ///
+    /// The writer is not flushed.
+    ///
+    pub async fn write(&mut self, image: &Image) -> Result<(), NetpbmError> {
+        let mut buf = Vec::new();
+        image.write(&mut buf)?;
+        self.writer.write_all(&buf).await?;
+        Ok(())
+    }
+
+    /// Get the writer back.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Stream of the images of a netpbm file, like `Images`.
+///
+/// A `raw` image is yielded once its raster has arrived. A `plain`
+/// image is yielded once the bytes after it have arrived, or the
+/// input has ended, since its last sample could otherwise be cut
+/// short. Incomplete or malformed images are only reported as
+/// errors at the end of the input.
+///
+/// While the reader waits, buffered `plain` data is only decoded
+/// again once it has doubled, so an image delivered in many small
+/// reads is decoded a logarithmic number of times.
+///
+/// Error offsets are relative to the start of the input.
+///
+#[derive(Debug)]
+pub struct ImageStream<R: AsyncRead + Unpin> {
+    reader: R,
+    buf: Vec<u8>,
+    /// Input offset of the start of `buf`.
+    consumed: usize,
+    /// Length `buf` is read up to before parsing again, unless
+    /// the reader has to wait.
+    target: usize,
+    /// Length of `buf` when it was last parsed, if it has been
+    /// parsed since the last image was yielded.
+    parsed: Option<usize>,
+    count: usize,
+    eof: bool,
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> ImageStream<R> {
+    /// Create a stream over the images of the given reader.
+    pub fn new(reader: R) -> Self {
+        ImageStream {
+            reader,
+            buf: Vec::new(),
+            consumed: 0,
+            target: CHUNK_LEN,
+            parsed: None,
+            count: 0,
+            eof: false,
+            done: false,
+        }
+    }
+
+    /// Get the input offset of the next image.
+    pub fn offset(&self) -> usize {
+        self.consumed
+    }
+
+    /// Decode the next image if all of its bytes have arrived.
+    fn parse(&mut self) -> Option<Option<Result<Image, NetpbmError>>> {
+        self.parsed = Some(self.buf.len());
+
+        let mut scanner = Scanner::new(&self.buf);
+        let image = Images::read_next(&mut scanner, self.count == 0);
+        let complete = match &image {
+            Some(Ok(image)) => !scanner.is_empty() || image.info().encoding == EncodingType::Raw,
+            Some(Err(NetpbmError::UnexpectedEof { .. })) | None => false,
+            // Samples only get worse as digits arrive.
+            Some(Err(
+                NetpbmError::MalformedRaster { .. } | NetpbmError::OversizedSample { .. },
+            )) => true,
+            // A header error stands unless the last token read runs
+            // to the end of the buffer and may still grow.
+            Some(Err(_)) => {
+                !scanner.is_empty() || self.buf.last().is_some_and(|&b| decode::is_whitespace(b))
+            }
+        };
+        if !(complete || self.eof) {
+            // Grow reads with the buffer, so that a large image is
+            // parsed a logarithmic number of times.
+            self.target = self.buf.len() + self.buf.len().max(CHUNK_LEN);
+            return None;
+        }
+
+        let (offset, base) = (scanner.offset(), self.consumed);
+        self.buf.drain(..offset);
+        self.parsed = None;
+        self.consumed += offset;
+        self.count += 1;
+        self.done = !matches!(image, Some(Ok(_)));
+        Some(image.map(|image| image.map_err(|err| shift_offset(err, base))))
+    }
+
+    /// Check whether parsing while the reader waits could yield an
+    /// image, without decoding samples.
+    ///
+    /// `raw` rasters have a length known from the header, so they
+    /// are ready once it has arrived, and a malformed header is
+    /// ready to be reported. Otherwise, the buffer must have doubled
+    /// since it was last parsed.
+    ///
+    fn ready(&self) -> bool {
+        let mut scanner = Scanner::new(&self.buf);
+        scanner.skip_whitespace();
+        match decode::read_header(&mut scanner) {
+            Ok((info, _)) if info.encoding == EncodingType::Raw => info
+                .raw_raster_len()
+                .is_some_and(|len| scanner.remaining().len() >= len),
+            Ok(_) | Err(NetpbmError::UnexpectedEof { .. }) => {
+                self.buf.len() >= 2 * self.parsed.unwrap_or(0)
+            }
+            Err(_) => true,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for ImageStream<R> {
+    type Item = Result<Image, NetpbmError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if this.done {
+                return Poll::Ready(None);
+            }
+
+            // Parse once the read target is reached, the input has
+            // ended, or the reader has to wait for new bytes.
+            let fresh = this.parsed != Some(this.buf.len());
+            if this.eof || (fresh && this.buf.len() >= this.target) {
+                if let Some(image) = this.parse() {
+                    return Poll::Ready(image);
+                }
+            }
+
+            let len = this.buf.len();
+            this.buf.resize(this.target.max(len + 1), 0);
+            let mut read_buf = ReadBuf::new(&mut this.buf[len..]);
+            let poll = Pin::new(&mut this.reader).poll_read(cx, &mut read_buf);
+            let filled = read_buf.filled().len();
+            this.buf.truncate(len + filled);
+
+            match poll {
+                Poll::Ready(Ok(())) => this.eof = filled == 0,
+                Poll::Ready(Err(err)) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(err.into())));
+                }
+                Poll::Pending if fresh && this.ready() => {
+                    if let Some(image) = this.parse() {
+                        return Poll::Ready(image);
+                    }
+                    return Poll::Pending;
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Add `base` to the byte offset of a decoding error.
+fn shift_offset(err: NetpbmError, base: usize) -> NetpbmError {
+    use NetpbmError::*;
+    match err {
+        OversizedSample { offset, bit_depth } => OversizedSample {
+            offset: offset + base,
+            bit_depth,
+        },
+        MalformedHeader { offset, info } => MalformedHeader {
+            offset: offset + base,
+            info,
+        },
+        UnexpectedEof { offset } => UnexpectedEof {
+            offset: offset + base,
+        },
+        MalformedRaster { offset, info } => MalformedRaster {
+            offset: offset + base,
+            info,
+        },
+        err => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Info, TypeInfo};
+    use std::future::poll_fn;
+
+    async fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+        poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+    }
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        let info = Info::new_pgm(EncodingType::Plain, 2, 1, 300).expect("pgm");
+        let image = Image::new(info, TypeInfo::Empty, vec![12, 300]).expect("pgm");
+
+        let mut out = Vec::new();
+        let mut encoder = Encoder::new(&mut out);
+        encoder.write(&image).await.expect("write");
+        let mut sync = Vec::new();
+        image.write(&mut sync).expect("write");
+        assert_eq!(out, sync);
+
+        let decoded = Decoder::new(&out[..]).read().await.expect("read");
+        assert_eq!(decoded, image);
+    }
+
+    #[tokio::test]
+    async fn test_stream() {
+        // The reader delivers a few bytes at a time, splitting
+        // plain samples and raw rasters.
+        let data = b"P2 2 1 255 12 34 # two\nP5 1 1 255 \x07\n\nP1 1 1 x";
+        let (mut client, server) = tokio::io::duplex(3);
+        let writer = tokio::spawn(async move {
+            client.write_all(data).await.expect("write");
+        });
+
+        let mut stream = Decoder::new(server).images();
+        let first = next(&mut stream).await.expect("first").expect("pgm");
+        assert_eq!(first.samples(), [12, 34]);
+        let second = next(&mut stream).await.expect("second").expect("pgm");
+        assert_eq!(second.samples(), [7]);
+        // Errors match those of the synchronous iterator.
+        let error = Images::new(data).nth(2);
+        assert!(matches!(error, Some(Err(_))));
+        assert_eq!(next(&mut stream).await, error);
+        assert!(next(&mut stream).await.is_none());
+        writer.await.expect("writer");
+    }
+
+    #[tokio::test]
+    async fn test_stream_waiting() {
+        // A raw image is yielded as soon as its raster arrives, even
+        // though the writer stays open.
+        let (mut client, server) = tokio::io::duplex(64);
+        let mut stream = Decoder::new(server).images();
+        client
+            .write_all(b"P5 2 1 255 \x07\x09")
+            .await
+            .expect("write");
+        let image = next(&mut stream).await.expect("image").expect("pgm");
+        assert_eq!(image.samples(), [7, 9]);
+
+        client.write_all(b"P2 1 1 255 12 ").await.expect("write");
+        drop(client);
+        let image = next(&mut stream).await.expect("image").expect("pgm");
+        assert_eq!(image.samples(), [12]);
+        assert!(next(&mut stream).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stream_malformed_header() {
+        // Errors in a header that has arrived are reported without
+        // waiting for the writer to close.
+        let (mut client, server) = tokio::io::duplex(64);
+        let mut stream = Decoder::new(server).images();
+        client.write_all(b"P9 1 1 255\n").await.expect("write");
+        assert!(matches!(
+            next(&mut stream).await,
+            Some(Err(NetpbmError::MalformedHeader { offset: 0, .. }))
+        ));
+        assert!(next(&mut stream).await.is_none());
+
+        let (mut client, server) = tokio::io::duplex(64);
+        let mut stream = Decoder::new(server).images();
+        client
+            .write_all(b"P7\nWIDTH 1\nBOGUS 1\n")
+            .await
+            .expect("write");
+        assert!(matches!(
+            next(&mut stream).await,
+            Some(Err(NetpbmError::MalformedHeader { offset: 11, .. }))
+        ));
+
+        // A maxval of 0 at the end of the buffer may still grow into
+        // a valid one, so the stream waits.
+        let (mut client, server) = tokio::io::duplex(64);
+        let mut stream = Decoder::new(server).images();
+        client.write_all(b"P2 1 1 0").await.expect("write");
+        let poll = poll_fn(|cx| Poll::Ready(Pin::new(&mut stream).poll_next(cx))).await;
+        assert!(poll.is_pending());
+        client.write_all(b"1 1 ").await.expect("write");
+        drop(client);
+        let image = next(&mut stream).await.expect("image").expect("pgm");
+        assert_eq!((image.bit_depth(), image.samples()), (1, &[1][..]));
+    }
+}
@@ -0,0 +1,73 @@
+//! Per-sample tone adjustments.
+//!
+//! These functions map every sample of an image independently,
+//! similar to netpbm's `pnminvert` and `pnmgamma`. They apply to
+//! every channel, including alpha channels, and keep the format,
+//! bit depth, and type info of the image.
+//!
+
+use crate::image::Image;
+use crate::math;
+use crate::NetpbmError;
+use alloc::format;
+use alloc::vec::Vec;
+
+/// Invert every sample, so that 0 becomes the bit depth.
+///
+/// Black and white swap in every format, including PBM.
+///
+pub fn invert(image: &Image) -> Image {
+    let bit_depth = image.bit_depth();
+    map(image, |s| bit_depth - s)
+}
+
+/// Apply a gamma correction of `gamma` to every sample.
+///
+/// Samples are raised to the power `1 / gamma` as fractions of the
+/// bit depth, so a gamma above 1 brightens the image.
+///
+pub fn gamma(image: &Image, gamma: f64) -> Result<Image, NetpbmError> {
+    if !(gamma.is_finite() && gamma > 0.0) {
+        return Err(NetpbmError::InvalidArgument {
+            info: format!("Gamma {} should be positive", gamma),
+        });
+    }
+
+    let bit_depth = image.bit_depth() as f64;
+    let lut: Vec<u16> = (0..=image.bit_depth())
+        .map(|s| math::round(bit_depth * math::powf(s as f64 / bit_depth, 1.0 / gamma)) as u16)
+        .collect();
+    Ok(map(image, |s| lut[s as usize]))
+}
+
+/// Map every sample of an image.
+fn map<F: Fn(u16) -> u16>(image: &Image, f: F) -> Image {
+    let mut image = image.clone();
+    for sample in image.samples_mut() {
+        *sample = f(*sample);
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::pgm;
+    use alloc::vec;
+
+    #[test]
+    fn test_invert() {
+        let image = pgm(3, 1, 100, vec![0, 30, 100]);
+        assert_eq!(invert(&image).samples(), [100, 70, 0]);
+        assert_eq!(invert(&invert(&image)), image);
+    }
+
+    #[test]
+    fn test_gamma() {
+        let image = pgm(3, 1, 100, vec![0, 25, 100]);
+        assert_eq!(gamma(&image, 2.0).expect("gamma").samples(), [0, 50, 100]);
+        assert_eq!(gamma(&image, 0.5).expect("gamma").samples(), [0, 6, 100]);
+        assert_eq!(gamma(&image, 1.0).expect("gamma"), image);
+        assert!(gamma(&image, 0.0).is_err());
+    }
+}
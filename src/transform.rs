@@ -0,0 +1,231 @@
+//! Lossless geometric transforms.
+//!
+//! Images can be flipped, rotated by multiples of 90 degrees, and
+//! transposed, similar to netpbm's `pamflip`. Transforms move whole
+//! tuples, so they work for any channel count and bit depth.
+//!
+//! Bitmaps are transformed directly on their byte-packed rows,
+//! without unpacking pixels to samples.
+//!
+
+use crate::bitmap::Bitmap;
+use crate::image::Image;
+use alloc::vec::Vec;
+
+/// Geometric transform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Transform {
+    /// Mirror left to right.
+    FlipHorizontal,
+
+    /// Mirror top to bottom.
+    FlipVertical,
+
+    /// Rotate 90 degrees clockwise.
+    Rotate90,
+
+    /// Rotate 180 degrees.
+    Rotate180,
+
+    /// Rotate 270 degrees clockwise.
+    Rotate270,
+
+    /// Swap rows and columns.
+    Transpose,
+}
+
+impl Transform {
+    /// Determine whether the transform swaps the width and height.
+    pub fn swaps_dims(&self) -> bool {
+        matches!(
+            self,
+            Transform::Rotate90 | Transform::Rotate270 | Transform::Transpose
+        )
+    }
+
+    /// Get the source coordinates of the output pixel at `(x, y)`
+    /// for a source image of `width` by `height`.
+    fn source(&self, x: u32, y: u32, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            Transform::FlipHorizontal => (width - 1 - x, y),
+            Transform::FlipVertical => (x, height - 1 - y),
+            Transform::Rotate90 => (y, height - 1 - x),
+            Transform::Rotate180 => (width - 1 - x, height - 1 - y),
+            Transform::Rotate270 => (width - 1 - y, x),
+            Transform::Transpose => (y, x),
+        }
+    }
+}
+
+/// Apply a geometric transform to an image.
+pub fn transform(image: &Image, transform: Transform) -> Image {
+    let (width, height) = (image.width(), image.height());
+    let mut info = image.info().clone();
+    if transform.swaps_dims() {
+        (info.width, info.height) = (info.height, info.width);
+    }
+
+    let mut samples = Vec::with_capacity(image.samples().len());
+    for y in 0..info.height.value() {
+        for x in 0..info.width.value() {
+            let (x, y) = transform.source(x, y, width, height);
+            samples.extend_from_slice(image.tuple(x, y));
+        }
+    }
+
+    Image::from_parts(info, image.type_info().clone(), samples)
+}
+
+/// Apply a geometric transform to a bitmap.
+pub fn transform_bitmap(bitmap: &Bitmap, transform: Transform) -> Bitmap {
+    match transform {
+        Transform::FlipHorizontal => flip_horizontal(bitmap),
+        Transform::FlipVertical => flip_vertical(bitmap),
+        Transform::Rotate90 => flip_horizontal(&transpose(bitmap)),
+        Transform::Rotate180 => flip_vertical(&flip_horizontal(bitmap)),
+        Transform::Rotate270 => flip_vertical(&transpose(bitmap)),
+        Transform::Transpose => transpose(bitmap),
+    }
+}
+
+/// Mirror a bitmap left to right.
+fn flip_horizontal(bitmap: &Bitmap) -> Bitmap {
+    let row_len = bitmap.row_len();
+    let pad = (8 - bitmap.width() % 8) % 8;
+
+    let mut data = Vec::with_capacity(bitmap.as_bytes().len());
+    for row in bitmap.as_bytes().chunks_exact(row_len) {
+        // Reversing the bytes and their bits moves the padding to the
+        // start of the row, so shift the row left to drop it.
+        let mut reversed = row.iter().rev().map(|b| b.reverse_bits()).peekable();
+        while let Some(byte) = reversed.next() {
+            let next = reversed.peek().copied().unwrap_or(0);
+            data.push(if pad == 0 {
+                byte
+            } else {
+                (byte << pad) | (next >> (8 - pad))
+            });
+        }
+    }
+
+    Bitmap::from_packed(bitmap.width(), bitmap.height(), data).expect("Flipped bitmap")
+}
+
+/// Mirror a bitmap top to bottom.
+fn flip_vertical(bitmap: &Bitmap) -> Bitmap {
+    let data = bitmap
+        .as_bytes()
+        .chunks_exact(bitmap.row_len())
+        .rev()
+        .flatten()
+        .copied()
+        .collect();
+
+    Bitmap::from_packed(bitmap.width(), bitmap.height(), data).expect("Flipped bitmap")
+}
+
+/// Swap the rows and columns of a bitmap.
+///
+/// The bitmap is transposed in blocks of 8 by 8 pixels.
+///
+fn transpose(bitmap: &Bitmap) -> Bitmap {
+    let (width, height) = (bitmap.width(), bitmap.height());
+    let mut out = Bitmap::new(height, width).expect("Transposed bitmap");
+
+    for by in (0..height).step_by(8) {
+        for bx in 0..bitmap.row_len() {
+            // Gather a block of 8 rows. Rows past the end are white.
+            let mut block = [0u8; 8];
+            for (i, byte) in block.iter_mut().enumerate() {
+                let y = by + i as u32;
+                if y < height {
+                    *byte = bitmap.row(y)[bx];
+                }
+            }
+
+            for (j, byte) in transpose_block(block).into_iter().enumerate() {
+                let x = bx as u32 * 8 + j as u32;
+                if x < width {
+                    out.row_mut(x)[by as usize / 8] = byte;
+                }
+            }
+        }
+    }
+
+    out
+}
+
+/// Transpose an 8 by 8 block of bits, stored as one byte per row
+/// with the leftmost bit in the most significant bit.
+fn transpose_block(block: [u8; 8]) -> [u8; 8] {
+    let mut out = [0u8; 8];
+    for (i, &row) in block.iter().enumerate() {
+        for (j, column) in out.iter_mut().enumerate() {
+            *column |= ((row >> (7 - j)) & 1) << (7 - i);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EncodingType, Info, TypeInfo};
+    use alloc::vec;
+
+    const TRANSFORMS: [Transform; 6] = [
+        Transform::FlipHorizontal,
+        Transform::FlipVertical,
+        Transform::Rotate90,
+        Transform::Rotate180,
+        Transform::Rotate270,
+        Transform::Transpose,
+    ];
+
+    #[test]
+    fn test_transform_image() {
+        // 3x2 image with 2 channels.
+        let info = Info::new_pam(3, 2, 1000, 2).expect("pam");
+        let samples = vec![1, 10, 2, 20, 3, 30, 4, 40, 5, 50, 6, 60];
+        let image = Image::new(info, TypeInfo::Empty, samples).expect("image");
+
+        let expected: [(Transform, Vec<u16>); 6] = [
+            (Transform::FlipHorizontal, vec![3, 2, 1, 6, 5, 4]),
+            (Transform::FlipVertical, vec![4, 5, 6, 1, 2, 3]),
+            (Transform::Rotate90, vec![4, 1, 5, 2, 6, 3]),
+            (Transform::Rotate180, vec![6, 5, 4, 3, 2, 1]),
+            (Transform::Rotate270, vec![3, 6, 2, 5, 1, 4]),
+            (Transform::Transpose, vec![1, 4, 2, 5, 3, 6]),
+        ];
+
+        for (t, firsts) in expected {
+            let out = transform(&image, t);
+            let samples: Vec<u16> = firsts.iter().flat_map(|&s| [s, s * 10]).collect();
+            assert_eq!(out.samples(), samples, "{:?}", t);
+            if t.swaps_dims() {
+                assert_eq!((out.width(), out.height()), (2, 3));
+            } else {
+                assert_eq!((out.width(), out.height()), (3, 2));
+            }
+            assert_eq!(out.bit_depth(), 1000);
+        }
+    }
+
+    #[test]
+    fn test_transform_bitmap() {
+        for (width, height) in [(13, 10), (8, 3), (1, 17), (16, 16)] {
+            let mut bitmap = Bitmap::new(width, height).expect("bitmap");
+            for y in 0..height {
+                for x in 0..width {
+                    bitmap.set(x, y, (x * 7 + y * 3) % 5 < 2);
+                }
+            }
+            let image = bitmap.to_image(EncodingType::Raw);
+
+            for t in TRANSFORMS {
+                let expected = Bitmap::from_image(&transform(&image, t)).expect("bitmap");
+                assert_eq!(transform_bitmap(&bitmap, t), expected, "{:?}", t);
+            }
+        }
+    }
+}
@@ -0,0 +1,391 @@
+//! Zero-copy views of `raw` images.
+//!
+//! An `ImageView` parses the header of a PGM, PPM, or PAM image
+//! with `raw` encoding and borrows its raster from the input, for
+//! example a memory-mapped file, instead of copying it into an
+//! `Image`.
+//!
+//! With a bit depth up to 255, the raster is exactly the sample
+//! layout of the image, and is returned as a byte slice. With a
+//! larger bit depth, samples are decoded from big-endian byte pairs
+//! as they are accessed.
+//!
+//! `ImageView::decode` checks every sample against the bit depth,
+//! which reads the whole raster. For large files where only part of
+//! the image is needed, `ImageView::decode_unchecked` reads just the
+//! header, and `ImageView::validate` runs the check later.
+//!
+//! PBM rasters are bit-packed and `plain` rasters are ASCII, so
+//! neither can be viewed.
+//!
+
+use crate::formats::decode::{self, Scanner};
+use crate::image::Image;
+use crate::{EncodingType, Info, NetpbmError, NetpbmFormat, TypeInfo};
+use alloc::format;
+use core::iter::FusedIterator;
+use core::slice::ChunksExact;
+
+/// A `raw` image whose samples borrow the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageView<'a> {
+    info: Info,
+    type_info: TypeInfo,
+    samples: Samples<'a>,
+    /// Offset of the raster in the decoded buffer.
+    start: usize,
+}
+
+/// Samples borrowed from a raster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Samples<'a> {
+    /// One byte per sample, for bit depths up to 255.
+    Narrow(&'a [u8]),
+    /// Two big-endian bytes per sample, for larger bit depths.
+    Wide(WideSamples<'a>),
+}
+
+/// Big-endian two-byte samples, decoded on access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WideSamples<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ImageView<'a> {
+    /// Decode the header of one image from the start of the buffer
+    /// and borrow its raster.
+    ///
+    /// Returns the view and the number of bytes consumed. Samples
+    /// are checked against the bit depth as by `validate`, unless it
+    /// is the largest value of the sample size. Checking reads the
+    /// whole raster, which pages in all of a memory-mapped file;
+    /// `decode_unchecked` only reads the header.
+    ///
+    pub fn decode(buf: &'a [u8]) -> Result<(Self, usize), NetpbmError> {
+        let (view, len) = Self::decode_unchecked(buf)?;
+        view.validate()?;
+        Ok((view, len))
+    }
+
+    /// Decode the header of one image from the start of the buffer
+    /// and borrow its raster, without checking the samples.
+    ///
+    /// Only the header is read, so this takes the same time for any
+    /// raster size. Samples may exceed the bit depth until the view
+    /// is checked with `validate`; accessors return them as stored.
+    ///
+    pub fn decode_unchecked(buf: &'a [u8]) -> Result<(Self, usize), NetpbmError> {
+        let mut scanner = Scanner::new(buf);
+        let (info, type_info) = decode::read_header(&mut scanner)?;
+        if info.encoding != EncodingType::Raw
+            || matches!(info.format, NetpbmFormat::PBMRaw | NetpbmFormat::PBMPlain)
+        {
+            return Err(NetpbmError::InvalidArgument {
+                info: format!("{:?} images cannot be viewed without copying", info.format),
+            });
+        }
+
+        // The raster length comes from untrusted input, so it is
+        // checked before any of the buffer is borrowed.
+        let len = info
+            .raw_raster_len()
+            .ok_or_else(|| decode::oversized_raster(&scanner, &info))?;
+        let start = scanner.offset();
+        let raster = scanner.take(len)?;
+        let samples = if info.bit_depth.is_multi_byte() {
+            Samples::Wide(WideSamples::new(raster))
+        } else {
+            Samples::Narrow(raster)
+        };
+
+        let view = ImageView {
+            info,
+            type_info,
+            samples,
+            start,
+        };
+        Ok((view, scanner.offset()))
+    }
+
+    /// Check that every sample is at most the bit depth.
+    ///
+    /// This reads the whole raster, unless the bit depth is the
+    /// largest value of the sample size. Errors carry the offset of
+    /// the sample in the buffer the view was decoded from.
+    ///
+    pub fn validate(&self) -> Result<(), NetpbmError> {
+        match self.find_oversized() {
+            Some(offset) => Err(NetpbmError::OversizedSample {
+                offset,
+                bit_depth: self.info.bit_depth,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Find the buffer offset of the first sample above the bit
+    /// depth.
+    fn find_oversized(&self) -> Option<usize> {
+        let maxval = self.bit_depth();
+        let index = match self.samples {
+            Samples::Narrow(_) if maxval >= u8::MAX as u16 => None,
+            Samples::Narrow(bytes) => bytes.iter().position(|&b| b as u16 > maxval),
+            Samples::Wide(_) if maxval == u16::MAX => None,
+            Samples::Wide(samples) => samples.iter().position(|s| s > maxval).map(|i| 2 * i),
+        };
+        index.map(|i| self.start + i)
+    }
+
+    /// Get the image metadata.
+    pub fn info(&self) -> &Info {
+        &self.info
+    }
+
+    /// Get the image type info.
+    pub fn type_info(&self) -> &TypeInfo {
+        &self.type_info
+    }
+
+    /// Get the image width.
+    pub fn width(&self) -> u32 {
+        self.info.width.value()
+    }
+
+    /// Get the image height.
+    pub fn height(&self) -> u32 {
+        self.info.height.value()
+    }
+
+    /// Get the number of samples per tuple.
+    pub fn channels(&self) -> u32 {
+        self.info.channels.value()
+    }
+
+    /// Get the image bit depth. This is the maximum value
+    /// a sample can hold.
+    pub fn bit_depth(&self) -> u16 {
+        self.info.bit_depth.value()
+    }
+
+    /// Get the samples of the image.
+    pub fn samples(&self) -> Samples<'a> {
+        self.samples
+    }
+
+    /// Get the samples of row `y`.
+    pub fn row(&self, y: u32) -> Samples<'a> {
+        let row_len = self.width() as usize * self.channels() as usize;
+        let range = y as usize * row_len..(y as usize + 1) * row_len;
+        match self.samples {
+            Samples::Narrow(bytes) => Samples::Narrow(&bytes[range]),
+            Samples::Wide(samples) => Samples::Wide(WideSamples::new(
+                &samples.bytes[range.start * 2..range.end * 2],
+            )),
+        }
+    }
+
+    /// Copy the view into an owned image.
+    ///
+    /// Samples are checked against the bit depth as by `validate`,
+    /// so views from `decode_unchecked` are safe to convert.
+    ///
+    pub fn to_image(&self) -> Result<Image, NetpbmError> {
+        self.validate()?;
+        let samples = match self.samples {
+            Samples::Narrow(bytes) => bytes.iter().map(|&b| b as u16).collect(),
+            Samples::Wide(samples) => samples.iter().collect(),
+        };
+        Ok(Image::from_parts(
+            self.info.clone(),
+            self.type_info.clone(),
+            samples,
+        ))
+    }
+}
+
+impl<'a> Samples<'a> {
+    /// Get the number of samples.
+    pub fn len(&self) -> usize {
+        match self {
+            Samples::Narrow(bytes) => bytes.len(),
+            Samples::Wide(samples) => samples.len(),
+        }
+    }
+
+    /// Check if there are no samples.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the sample at `index`.
+    pub fn get(&self, index: usize) -> Option<u16> {
+        match self {
+            Samples::Narrow(bytes) => bytes.get(index).map(|&b| b as u16),
+            Samples::Wide(samples) => samples.get(index),
+        }
+    }
+
+    /// Get the raster bytes the samples are read from.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        match self {
+            Samples::Narrow(bytes) => bytes,
+            Samples::Wide(samples) => samples.bytes,
+        }
+    }
+}
+
+impl<'a> WideSamples<'a> {
+    /// Wrap big-endian sample bytes. A trailing odd byte is ignored.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        WideSamples {
+            bytes: &bytes[..bytes.len() & !1],
+        }
+    }
+
+    /// Get the number of samples.
+    pub fn len(&self) -> usize {
+        self.bytes.len() / 2
+    }
+
+    /// Check if there are no samples.
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Get the sample at `index`.
+    pub fn get(&self, index: usize) -> Option<u16> {
+        let bytes = self.bytes.get(index * 2..index * 2 + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Iterate over the decoded samples.
+    pub fn iter(&self) -> WideIter<'a> {
+        WideIter {
+            chunks: self.bytes.chunks_exact(2),
+        }
+    }
+}
+
+impl<'a> IntoIterator for WideSamples<'a> {
+    type Item = u16;
+    type IntoIter = WideIter<'a>;
+
+    fn into_iter(self) -> WideIter<'a> {
+        self.iter()
+    }
+}
+
+/// Iterator over `WideSamples`.
+#[derive(Debug, Clone)]
+pub struct WideIter<'a> {
+    chunks: ChunksExact<'a, u8>,
+}
+
+impl Iterator for WideIter<'_> {
+    type Item = u16;
+
+    fn next(&mut self) -> Option<u16> {
+        self.chunks.next().map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.chunks.size_hint()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<u16> {
+        self.chunks.nth(n).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+}
+
+impl DoubleEndedIterator for WideIter<'_> {
+    fn next_back(&mut self) -> Option<u16> {
+        self.chunks
+            .next_back()
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+}
+
+impl ExactSizeIterator for WideIter<'_> {}
+
+impl FusedIterator for WideIter<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_narrow() {
+        let data = b"P6 2 1 255\n\x01\x02\x03\x04\x05\x06P5 1 1 255 \x07";
+        let (view, len) = ImageView::decode(data).expect("ppm");
+        assert_eq!(view.channels(), 3);
+        assert_eq!(view.samples(), Samples::Narrow(&data[11..17]));
+        // The raster is borrowed, not copied.
+        assert!(core::ptr::eq(view.samples().as_bytes(), &data[11..17]));
+        assert_eq!(view.to_image(), Ok(Image::decode(data).expect("ppm").0));
+
+        let (next, _) = ImageView::decode(&data[len..]).expect("pgm");
+        assert_eq!(next.samples().get(0), Some(7));
+    }
+
+    #[test]
+    fn test_wide() {
+        let data = b"P7\nWIDTH 2\nHEIGHT 2\nDEPTH 1\nMAXVAL 1000\nENDHDR\n\x00\x01\x03\xe8\x01\x00\x00\x02";
+        let (view, _) = ImageView::decode(data).expect("pam");
+        let Samples::Wide(samples) = view.row(1) else {
+            panic!("expected wide samples");
+        };
+        assert_eq!(samples.iter().collect::<Vec<_>>(), [256, 2]);
+        assert_eq!(view.samples().get(1), Some(1000));
+        assert_eq!(view.samples().len(), 4);
+        assert_eq!(view.to_image(), Ok(Image::decode(data).expect("pam").0));
+    }
+
+    #[test]
+    fn test_errors() {
+        let oversized = ImageView::decode(b"P5 2 1 1000 \x00\x01\x03\xe9");
+        assert!(matches!(
+            oversized,
+            Err(NetpbmError::OversizedSample { offset: 14, .. })
+        ));
+        assert!(matches!(
+            ImageView::decode(b"P5 2 1 255 \x00"),
+            Err(NetpbmError::UnexpectedEof { .. })
+        ));
+        assert!(ImageView::decode(b"P2 1 1 255 7").is_err());
+        let huge = b"P7\nWIDTH 2147483648\nHEIGHT 2147483648\nDEPTH 4\nMAXVAL 255\nENDHDR\n";
+        assert!(matches!(
+            ImageView::decode(huge),
+            Err(NetpbmError::MalformedHeader { .. })
+        ));
+        assert!(matches!(
+            ImageView::decode(b"P5 4294967295 4294967295 65535 "),
+            Err(NetpbmError::MalformedHeader { .. })
+        ));
+        assert!(ImageView::decode(b"P4 8 1 \xff").is_err());
+    }
+
+    #[test]
+    fn test_unchecked() {
+        let data = b"P5 2 1 1000 \x00\x01\x03\xe9";
+        let (view, len) = ImageView::decode_unchecked(data).expect("pgm");
+        assert_eq!(len, data.len());
+        // Samples are returned as stored until the view is checked.
+        assert_eq!(view.samples().get(1), Some(1001));
+        let oversized = Err(NetpbmError::OversizedSample {
+            offset: 14,
+            bit_depth: view.info().bit_depth,
+        });
+        assert_eq!(view.validate(), oversized);
+        assert_eq!(view.to_image().map(|_| ()), oversized);
+
+        let data = b"P5 2 1 100 \x64\x65";
+        let (view, _) = ImageView::decode_unchecked(data).expect("pgm");
+        assert!(matches!(
+            view.validate(),
+            Err(NetpbmError::OversizedSample { offset: 12, .. })
+        ));
+        let (view, _) = ImageView::decode_unchecked(b"P5 2 1 65535 \xff\xff\x00\x01").expect("pgm");
+        assert_eq!(view.validate(), Ok(()));
+    }
+}
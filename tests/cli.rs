@@ -0,0 +1,204 @@
+//! End-to-end tests of the `netpbmr` command-line tool.
+
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// Run the tool with `input` on standard input.
+fn run(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_netpbmr"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("spawn netpbmr");
+    // The tool may fail before reading its input.
+    let _ = child.stdin.take().expect("stdin").write_all(input);
+    child.wait_with_output().expect("wait for netpbmr")
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn test_info() {
+    let output = run(&["info"], b"P2 2 1 255 1 2\nP7\nWIDTH 1\nHEIGHT 1\nDEPTH 2\nMAXVAL 15\nTUPLTYPE GRAYSCALE_ALPHA\nENDHDR\n\x07\x0f");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "stdin: image 0: P2 PGMPlain, 2 by 1, maxval 255, 1 channel\n\
+         stdin: image 1: P7 PAM, 1 by 1, maxval 15, 2 channels\n    \
+         tuple type: GRAYSCALE_ALPHA\n"
+    );
+
+    let output = run(&["info", "--bogus"], b"");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("unknown option \"--bogus\""));
+}
+
+#[test]
+fn test_validate() {
+    let output = run(&["validate"], b"P2 2 1 255 1 2\nP5 1 1 15 \x07P1 1 1 0");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "stdin: 3 valid images\n");
+
+    // The second image of the stream is truncated.
+    let output = run(&["validate"], b"P2 2 1 255 1 2\nP5 1 2 15 \x07");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        stderr(&output),
+        "netpbmr: stdin: image 1: Unexpected end of data at byte [26]\n"
+    );
+
+    let output = run(&["validate", "/nonexistent/in.pgm"], b"");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).starts_with("netpbmr: /nonexistent/in.pgm: "));
+}
+
+#[test]
+fn test_convert() {
+    let output = run(&["convert", "p5"], b"P2 2 1 255 1 2\nP3 1 1 15 1 2 3");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(output.stdout, b"P5\n2 1 255\n\x01\x02P5\n1 1 15\n\x02");
+
+    let output = run(
+        &["convert", "--plain", "--maxval", "3"],
+        b"P5 2 1 15 \x00\x0f",
+    );
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "P2\n2 1 3\n0\n3\n");
+
+    // Images converted before a truncated one are still written.
+    let output = run(&["convert", "pgm"], b"P2 1 1 255 9\nP2 2 1 255 1");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout(&output).starts_with("P2\n"));
+    assert!(stderr(&output).starts_with("netpbmr: stdin: image 1: "));
+
+    let output = run(&["convert", "gif", "in.pgm"], b"");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("unknown format \"gif\""));
+    let output = run(&["convert", "--plain", "--raw"], b"");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_scale() {
+    let output = run(&["scale", "2", "--filter", "nearest"], b"P2 2 1 255 0 100");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "P2\n4 2 255\n0\n0\n100\n100\n0\n0\n100\n100\n"
+    );
+
+    // A missing dimension keeps the aspect ratio.
+    let output = run(
+        &["scale", "--width", "1"],
+        b"P5 4 2 255 \x00\x00\x00\x00\x00\x00\x00\x00",
+    );
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert!(output.stdout.starts_with(b"P5\n1 1 255\n"));
+
+    let output = run(&["scale", "2", "--width", "4"], b"");
+    assert_eq!(output.status.code(), Some(2));
+    let output = run(&["scale", "2", "--filter", "sinc"], b"");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("unknown filter \"sinc\""));
+}
+
+#[test]
+fn test_flip() {
+    let output = run(&["flip", "--lr"], b"P2 2 1 255 0 255");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "P2\n2 1 255\n255\n0\n");
+
+    let output = run(&["flip", "--cw"], b"P2 2 1 255 1 2");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "P2\n1 2 255\n1\n2\n");
+
+    let output = run(&["flip", "--lr", "--tb"], b"");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("flip needs exactly one transform"));
+}
+
+#[test]
+fn test_cut() {
+    let output = run(&["cut", "1", "0", "1", "2"], b"P2 2 2 255 1 2 3 4");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "P2\n1 2 255\n2\n4\n");
+
+    // Negative offsets pad above and to the left of the image.
+    let output = run(
+        &["cut", "-1", "-1", "2", "2", "--pad"],
+        b"P2 2 2 255 1 2 3 4",
+    );
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "P2\n2 2 255\n0\n0\n0\n1\n");
+    let output = run(&["cut", "-1", "-1", "2", "2", "--pad"], b"P1 1 1 0");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "P1\n2 2\n1 1 1 0\n");
+
+    let output = run(&["cut", "-1", "0", "2", "2"], b"P2 2 2 255 1 2 3 4");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).starts_with("netpbmr: stdin: image 0: "));
+    let output = run(&["cut", "0", "0", "-2", "2"], b"");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("invalid width \"-2\""));
+}
+
+#[test]
+fn test_invert_and_gamma() {
+    let output = run(&["invert"], b"P2 2 1 255 0 55\nP1 1 1 1");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "P2\n2 1 255\n255\n200\nP1\n1 1\n0\n");
+
+    let output = run(&["gamma", "2"], b"P2 3 1 100 0 25 100");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "P2\n3 1 100\n0\n50\n100\n");
+
+    let output = run(&["gamma", "-0.5"], b"");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).starts_with("netpbmr: gamma -0.5 should be positive\n"));
+    let output = run(&["gamma"], b"");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("missing gamma"));
+}
+
+#[test]
+fn test_stack() {
+    let dir = env!("CARGO_TARGET_TMPDIR");
+    let alpha = format!("{}/stack-alpha.pgm", dir);
+    fs::write(&alpha, b"P5 2 1 255 \x0a\x0bP5 1 1 255 \x0c").expect("write alpha");
+
+    let output = run(
+        &["stack", "--tupletype", "GRAYSCALE_ALPHA", "-", &alpha],
+        b"P5 2 1 255 \x01\x02P5 1 1 255 \x03",
+    );
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let header = "P7\nWIDTH 2\nHEIGHT 1\nDEPTH 2\nMAXVAL 255\nTUPLTYPE GRAYSCALE_ALPHA\nENDHDR\n";
+    assert!(output.stdout.starts_with(header.as_bytes()));
+    assert_eq!(
+        &output.stdout[header.len()..header.len() + 4],
+        b"\x01\x0a\x02\x0b"
+    );
+
+    // Standard input holds one image fewer than the other file.
+    let output = run(&["stack", "-", &alpha], b"P5 2 1 255 \x01\x02");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stderr(&output),
+        "netpbmr: image 1: some files have no more images\n"
+    );
+
+    let output = run(&["stack", "-", "-"], b"");
+    assert_eq!(output.status.code(), Some(2));
+    let output = run(&["stack"], b"");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("missing files to stack"));
+}